@@ -25,6 +25,7 @@ pub const DATA_DIR: &str = "data";
 pub const FONTS_DIR: &str = "fonts";
 pub const FONTS_PATH: &str = "assets/fonts/";
 pub const IMAGES_DIR: &str = "images";
+pub const TILEMAPS_PATH: &str = "assets/tilemaps/";
 
 pub trait PathHelper {
     fn to_string_owned(&self) -> String;
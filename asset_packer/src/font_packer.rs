@@ -11,12 +11,13 @@ use image::{DynamicImage, Rgba};
 use ron;
 use rusttype;
 use rusttype::{point, PositionedGlyph, Scale};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 const COLOR_GLYPH: [u8; 4] = [255, 255, 255, 255];
 const COLOR_BORDER: [u8; 4] = [0, 0, 0, 255];
-const FIRST_VISIBLE_ASCII_CODE_POINT: u8 = 32;
-const LAST_ASCII_CODE_POINT: u8 = 126;
+const FIRST_VISIBLE_ASCII_CODE_POINT: u32 = 32;
+const LAST_ASCII_CODE_POINT: u32 = 126;
+const DEFAULT_FALLBACK_CHARACTER: char = '?';
 
 const FONTS_INFO_FILE_PATH: &str = "assets/fonts/fonts.ron";
 type FontInfoMap = HashMap<ResourcePath, FontInfo>;
@@ -27,13 +28,33 @@ struct FontInfo {
     pixel_height: i32,
     border_thickness: u8,
     atlas_padding: u8,
+    /// Inclusive ranges of code points that are baked into the font
+    #[serde(default = "default_code_point_ranges")]
+    code_point_ranges: Vec<(u32, u32)>,
+    /// Text file in the fonts directory whose characters are baked into the font in addition to
+    /// the code point ranges
+    #[serde(default)]
+    charset_file: Option<String>,
+    /// Drawn in place of characters that were not baked into the font
+    #[serde(default = "default_fallback_character")]
+    fallback_character: char,
+}
+
+fn default_code_point_ranges() -> Vec<(u32, u32)> {
+    vec![(FIRST_VISIBLE_ASCII_CODE_POINT, LAST_ASCII_CODE_POINT)]
+}
+
+fn default_fallback_character() -> char {
+    DEFAULT_FALLBACK_CHARACTER
 }
 
 #[derive(Debug)]
 pub struct RawFont {
     font_height: i32,
     vertical_advance: i32,
+    baseline: i32,
     glyphs: Vec<RawGlyph>,
+    fallback_code_point: char,
 }
 
 #[derive(Debug)]
@@ -53,8 +74,14 @@ pub fn pack_fonts(
     trace!("FontInfo map: {:?}", font_info_map);
 
     for (font_name, font_info) in font_info_map {
+        let code_points = collect_code_points(&font_info).context(format!(
+            "Could not collect code points of font '{}'",
+            font_name
+        ))?;
         let font = create_font(
             &font_info.filename,
+            &code_points,
+            font_info.fallback_character,
             font_info.pixel_height,
             font_info.border_thickness,
             font_info.atlas_padding,
@@ -70,16 +97,21 @@ pub fn pack_fonts(
 }
 
 pub fn pack_font(packer: &mut AtlasPacker, raw_font: RawFont) -> Font {
-    let packed_glyphs = raw_font
+    let packed_glyphs: HashMap<char, Glyph> = raw_font
         .glyphs
         .into_iter()
-        .map(|raw_glyph| pack_glyph(packer, raw_glyph))
+        .map(|raw_glyph| (raw_glyph.code_point, pack_glyph(packer, raw_glyph)))
         .collect();
 
+    // NOTE: `create_font` makes sure that the fallback glyph exists
+    let fallback_glyph = packed_glyphs[&raw_font.fallback_code_point];
+
     Font {
         font_height: raw_font.font_height as f32,
         vertical_advance: raw_font.vertical_advance as f32,
+        baseline: raw_font.baseline as f32,
         glyphs: packed_glyphs,
+        fallback_glyph,
     }
 }
 
@@ -100,8 +132,33 @@ fn pack_glyph(packer: &mut AtlasPacker, raw_glyph: RawGlyph) -> Glyph {
     }
 }
 
+/// Returns the sorted and deduplicated code points of the configured ranges and charset file
+fn collect_code_points(font_info: &FontInfo) -> Result<Vec<char>, Error> {
+    let mut code_points = BTreeSet::new();
+    for &(first, last) in &font_info.code_point_ranges {
+        code_points.extend((first..=last).filter_map(std::char::from_u32));
+    }
+
+    if let Some(charset_filename) = &font_info.charset_file {
+        let charset_filepath = Path::new(ASSETS_DIR).join(FONTS_DIR).join(charset_filename);
+        let charset = std::fs::read_to_string(&charset_filepath).context(format!(
+            "Could not read charset file '{}'",
+            charset_filepath.display()
+        ))?;
+        code_points.extend(charset.chars());
+    }
+
+    // NOTE: Control characters like '\n' are handled by the text layout and are never drawn
+    Ok(code_points
+        .into_iter()
+        .filter(|code_point| !code_point.is_control())
+        .collect())
+}
+
 pub fn create_font(
     font_filename: &str,
+    code_points: &[char],
+    fallback_code_point: char,
     font_height: i32,
     border_thickness: u8,
     atlas_padding: u8,
@@ -114,19 +171,31 @@ pub fn create_font(
     let vertical_advance = (metrics.ascent - metrics.descent + metrics.line_gap).ceil() as i32;
     let descent = metrics.descent.ceil() as i32;
 
-    // NOTE: We want to turn ASCII characters 0..127 into glyphs but want to treat the
-    //       non-displayable characters 0..31 as just whitespace. So we repeat the whitespace
-    //       character 32 times and chain it to the remaining ASCII characters.
-    //       The reason we want to treat the non-displayable characters as whitespace is that
-    //       if we just use their corresponding codepoints, the glyph will draw unwanted
-    //       '▯' symbols instead.
-    let code_points: Vec<char> = std::iter::repeat(' ')
-        .take(FIRST_VISIBLE_ASCII_CODE_POINT as usize)
-        .chain((FIRST_VISIBLE_ASCII_CODE_POINT..=LAST_ASCII_CODE_POINT).map(|byte| byte as char))
-        .collect();
+    // NOTE: Code points that the font does not contain would be drawn as '▯' symbols. We skip
+    //       them so that they get replaced by the fallback glyph instead.
+    let (mut contained_code_points, missing_code_points): (Vec<char>, Vec<char>) = code_points
+        .iter()
+        .partition(|&&code_point| font.glyph(code_point).id().0 != 0);
+    if !missing_code_points.is_empty() {
+        warn!(
+            "Font '{}' does not contain {} of the requested code points: {}",
+            font_filename,
+            missing_code_points.len(),
+            missing_code_points.iter().collect::<String>()
+        );
+    }
+    if font.glyph(fallback_code_point).id().0 == 0 {
+        return Err(failure::err_msg(format!(
+            "Font does not contain the fallback character '{}'",
+            fallback_code_point
+        )));
+    }
+    if !contained_code_points.contains(&fallback_code_point) {
+        contained_code_points.push(fallback_code_point);
+    }
 
     // Create glyphs
-    let glyphs: Vec<RawGlyph> = code_points
+    let glyphs: Vec<RawGlyph> = contained_code_points
         .iter()
         .map(|&code_point| {
             create_glyph(
@@ -143,7 +212,10 @@ pub fn create_font(
     Ok(RawFont {
         font_height: font_height + 2 * i32::from(border_thickness),
         vertical_advance: vertical_advance + 2 * i32::from(border_thickness),
+        // NOTE: See `create_glyph` for how the glyphs are positioned relative to the baseline
+        baseline: font_height + descent,
         glyphs,
+        fallback_code_point,
     })
 }
 
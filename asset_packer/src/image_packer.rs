@@ -1,20 +1,46 @@
-use game_lib::{ResourcePath, Sprite, Vec2};
+use game_lib::{
+    NineSlice, NineSliceInsets, ResourcePath, Sprite, Vec2, MISSING_SPRITE_RESOURCE_PATH,
+};
 
 use crate::common;
 use crate::common::AtlasPacker;
 use crate::common::*;
 
 use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
 
 use failure::{Error, ResultExt};
 use image;
+use ron;
+
+const MISSING_SPRITE_SIZE: u32 = 8;
+const MISSING_SPRITE_CHECKER_SIZE: u32 = 2;
+
+const NINE_SLICES_INFO_FILE_PATH: &str = "assets/images/nine_slices.ron";
+type NineSliceInfoMap = HashMap<ResourcePath, NineSliceInfo>;
+
+/// The border insets of a nine-slice sprite in pixels measured from the edges of the image. For
+/// aseprite files the image is the trimmed image as it is stored in the atlas.
+#[derive(Debug, Serialize, Deserialize)]
+struct NineSliceInfo {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
 
 pub fn pack_sprites(
     packer: &mut AtlasPacker,
     sprites: &mut HashMap<ResourcePath, Sprite>,
 ) -> Result<(), Error> {
     debug!("Creating list of images");
-    let image_filelist = common::collect_all_files_with_extension(common::ASSETS_DIR, "png");
+    // NOTE: Tileset images are packed tile by tile when packing the tilemaps
+    let image_filelist: Vec<_> =
+        common::collect_all_files_with_extension(common::ASSETS_DIR, "png")
+            .into_iter()
+            .filter(|filepath| !filepath.starts_with(TILEMAPS_PATH))
+            .collect();
     trace!("Image list: {:?}", image_filelist);
 
     for image_filepath in image_filelist {
@@ -44,3 +70,68 @@ pub fn pack_sprites(
     }
     Ok(())
 }
+
+/// Packs a magenta/black checkerboard sprite that the game shows in place of sprites that do not
+/// exist in the atlas
+pub fn pack_missing_sprite(packer: &mut AtlasPacker, sprites: &mut HashMap<ResourcePath, Sprite>) {
+    let image = image::RgbaImage::from_fn(MISSING_SPRITE_SIZE, MISSING_SPRITE_SIZE, |x, y| {
+        let checker_x = x / MISSING_SPRITE_CHECKER_SIZE;
+        let checker_y = y / MISSING_SPRITE_CHECKER_SIZE;
+        if (checker_x + checker_y) % 2 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    });
+
+    let region = packer.pack_image(image);
+    let sprite = region.to_sprite(packer.atlas_size, Vec2::zero());
+    sprites.insert(ResourcePath::from(MISSING_SPRITE_RESOURCE_PATH), sprite);
+}
+
+/// Creates nine-slices for all already packed sprites that have insets declared in the
+/// nine-slices info file
+pub fn pack_nine_slices(
+    sprites: &HashMap<ResourcePath, Sprite>,
+    nine_slices: &mut HashMap<ResourcePath, NineSlice>,
+) -> Result<(), Error> {
+    if !Path::new(NINE_SLICES_INFO_FILE_PATH).exists() {
+        debug!(
+            "No nine-slices info file found at '{}'",
+            NINE_SLICES_INFO_FILE_PATH
+        );
+        return Ok(());
+    }
+
+    let file = File::open(NINE_SLICES_INFO_FILE_PATH).context("Could not open nine-slices file")?;
+    let info_map: NineSliceInfoMap =
+        ron::de::from_reader(file).context("Could not read nine-slices info map")?;
+
+    for (resource_path, info) in info_map {
+        debug!("Creating nine-slice: '{}'", resource_path);
+
+        let sprite = *sprites.get(&resource_path).ok_or_else(|| {
+            failure::err_msg(format!(
+                "Nine-slice '{}' has no sprite with the same resource path",
+                resource_path
+            ))
+        })?;
+        let width = sprite.vertex_bounds.width() as u32;
+        let height = sprite.vertex_bounds.height() as u32;
+        if info.left + info.right > width || info.top + info.bottom > height {
+            return Err(failure::err_msg(format!(
+                "Insets {:?} of nine-slice '{}' do not fit into its sprite of size {}x{}",
+                info, resource_path, width, height
+            )));
+        }
+
+        let insets = NineSliceInsets {
+            left: info.left as f32,
+            right: info.right as f32,
+            top: info.top as f32,
+            bottom: info.bottom as f32,
+        };
+        nine_slices.insert(resource_path, NineSlice { sprite, insets });
+    }
+    Ok(())
+}
@@ -30,6 +30,7 @@ pub mod aseprite_packer;
 pub mod common;
 pub mod font_packer;
 pub mod image_packer;
+pub mod tilemap_packer;
 
 const DEBUG_WRITE_HUMAN_READABLE_ATLAS_META: bool = true;
 const ATLAS_TEXTURE_SIZE: u32 = 64;
@@ -62,6 +63,8 @@ fn main() -> Result<(), Error> {
     let mut animations = HashMap::new();
     let mut sprites = HashMap::new();
     let mut fonts = HashMap::new();
+    let mut nine_slices = HashMap::new();
+    let mut tilemaps = HashMap::new();
 
     debug!("Packing fonts");
     font_packer::pack_fonts(&mut packer, &mut fonts)?;
@@ -75,6 +78,18 @@ fn main() -> Result<(), Error> {
     image_packer::pack_sprites(&mut packer, &mut sprites)?;
     info!("Successfully packed png images");
 
+    debug!("Packing missing sprite");
+    image_packer::pack_missing_sprite(&mut packer, &mut sprites);
+    info!("Successfully packed missing sprite");
+
+    debug!("Creating nine-slices");
+    image_packer::pack_nine_slices(&sprites, &mut nine_slices)?;
+    info!("Successfully created nine-slices");
+
+    debug!("Packing tilemaps");
+    tilemap_packer::pack_tilemaps(&mut packer, &mut tilemaps)?;
+    info!("Successfully packed tilemaps");
+
     debug!("Saving atlas textures");
     let atlases = packer.into_atlas_textures();
     for (atlas_index, atlas) in atlases.iter().enumerate() {
@@ -90,6 +105,8 @@ fn main() -> Result<(), Error> {
         fonts,
         animations,
         sprites,
+        nine_slices,
+        tilemaps,
     };
     let meta_filepath = "data/atlas.tex";
     let mut meta_file =
@@ -111,6 +128,12 @@ fn main() -> Result<(), Error> {
     std::fs::copy("assets/key_mapping_debug.txt", "data/key_mapping_debug.txt")
         .context("Could not copy debug keymapping file")?;
 
+    std::fs::copy("assets/particle_effects.txt", "data/particle_effects.txt")
+        .context("Could not copy particle effects file")?;
+
+    std::fs::copy("assets/palette.gpl", "data/palette.gpl")
+        .context("Could not copy palette file")?;
+
     std::fs::copy("assets/sounds/pongi_blip.wav", "data/pongi_blip.wav")
         .context("Could not copy test sound file")?;
 
@@ -0,0 +1,321 @@
+use game_lib::{Animation, ResourcePath, Tile, TileLayer, Tilemap, Vec2};
+
+use crate::common;
+use crate::common::AtlasPacker;
+use crate::common::*;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use failure::{Error, ResultExt};
+use image;
+use serde_json;
+
+/// Layers without a `depth` property are placed slightly in front of the previous layer starting
+/// at this depth
+const DEFAULT_FIRST_LAYER_DEPTH: f32 = -1.0;
+const DEFAULT_LAYER_DEPTH_INCREMENT: f32 = 0.01;
+
+/// Tiled stores flipping and rotation of a tile in the upper bits of its global tile id
+const TILED_TILE_FLAGS_MASK: u32 = 0xe000_0000;
+
+// NOTE: The following structs mirror the parts of Tiled's JSON map format that we support.
+//       For more information see: https://doc.mapeditor.org/en/stable/reference/json-map-format/
+//
+#[derive(Debug, Deserialize)]
+struct TiledMap {
+    tilewidth: u32,
+    tileheight: u32,
+    layers: Vec<TiledLayer>,
+    tilesets: Vec<TiledTileset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledLayer {
+    name: String,
+    #[serde(rename = "type")]
+    layer_type: String,
+    #[serde(default)]
+    width: usize,
+    #[serde(default)]
+    height: usize,
+    #[serde(default)]
+    data: Vec<u32>,
+    encoding: Option<String>,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    opacity: f32,
+    visible: bool,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledTileset {
+    firstgid: u32,
+    /// Is set for external tilesets which we do not support
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledTile {
+    id: u32,
+    #[serde(default)]
+    animation: Vec<TiledAnimationFrame>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledAnimationFrame {
+    tileid: u32,
+    /// Given in milliseconds like our animation frame durations
+    duration: u32,
+}
+
+/// Imports all Tiled maps in JSON format from the tilemaps directory. The tiles of their
+/// tilesets are packed into the atlas individually.
+pub fn pack_tilemaps(
+    packer: &mut AtlasPacker,
+    tilemaps: &mut HashMap<ResourcePath, Tilemap>,
+) -> Result<(), Error> {
+    if !Path::new(TILEMAPS_PATH).exists() {
+        debug!("No tilemaps directory found at '{}'", TILEMAPS_PATH);
+        return Ok(());
+    }
+
+    debug!("Creating list of tilemaps");
+    let tilemap_filelist = common::collect_all_files_with_extension(TILEMAPS_PATH, "json");
+    trace!("Tilemap list: {:?}", tilemap_filelist);
+
+    for tilemap_filepath in tilemap_filelist {
+        debug!("Packing tilemap: '{}'", tilemap_filepath.display());
+
+        let file = File::open(&tilemap_filepath).context(format!(
+            "Could not open tilemap '{}'",
+            tilemap_filepath.display()
+        ))?;
+        let tiled_map: TiledMap = serde_json::from_reader(file).context(format!(
+            "Could not read tilemap '{}'",
+            tilemap_filepath.display()
+        ))?;
+        let tilemap_dir = tilemap_filepath.parent().unwrap_or_else(|| Path::new(""));
+        let tilemap = create_tilemap(packer, &tiled_map, tilemap_dir).context(format!(
+            "Could not create tilemap '{}'",
+            tilemap_filepath.display()
+        ))?;
+
+        let tilemap_relative_filepath = tilemap_filepath
+            .strip_prefix(ASSETS_DIR)
+            .context(format!(
+                "Could not strip '{}' from tilemap path {:?}",
+                ASSETS_DIR,
+                tilemap_filepath.display()
+            ))?
+            .to_path_buf();
+        let resource_path = filepath_to_string_without_extension(&tilemap_relative_filepath)?;
+        tilemaps.insert(resource_path, tilemap);
+    }
+    Ok(())
+}
+
+fn create_tilemap(
+    packer: &mut AtlasPacker,
+    tiled_map: &TiledMap,
+    tilemap_dir: &Path,
+) -> Result<Tilemap, Error> {
+    let mut tilemap = Tilemap::new(Vec2::new(
+        tiled_map.tilewidth as f32,
+        tiled_map.tileheight as f32,
+    ));
+
+    // NOTE: Our tiles are the tiles of all tilesets in order. We remember where the tiles of
+    //       each tileset start to translate Tiled's global tile ids into our tile indices.
+    let mut tileset_first_indices = Vec::new();
+    for tileset in &tiled_map.tilesets {
+        tileset_first_indices.push((
+            tileset.firstgid,
+            tilemap.tiles.len() as u32,
+            tileset.tilecount,
+        ));
+        let tiles = pack_tileset(packer, tileset, tilemap_dir)
+            .context(format!("Could not pack tileset '{}'", tileset.name))?;
+        tilemap.tiles.extend(tiles);
+    }
+
+    let tile_layers = tiled_map
+        .layers
+        .iter()
+        .filter(|layer| layer.layer_type == "tilelayer");
+    for (layer_index, tiled_layer) in tile_layers.enumerate() {
+        if let Some(encoding) = &tiled_layer.encoding {
+            if encoding != "csv" {
+                return Err(failure::err_msg(format!(
+                    "Layer '{}' uses the unsupported encoding '{}'",
+                    tiled_layer.name, encoding
+                )));
+            }
+        }
+        if tiled_layer.data.len() != tiled_layer.width * tiled_layer.height {
+            return Err(failure::err_msg(format!(
+                "Layer '{}' has {} tiles but expected {}x{}",
+                tiled_layer.name,
+                tiled_layer.data.len(),
+                tiled_layer.width,
+                tiled_layer.height
+            )));
+        }
+
+        let depth = layer_depth(tiled_layer).unwrap_or(
+            DEFAULT_FIRST_LAYER_DEPTH + layer_index as f32 * DEFAULT_LAYER_DEPTH_INCREMENT,
+        );
+        let mut layer = TileLayer::new(
+            &tiled_layer.name,
+            tiled_layer.width,
+            tiled_layer.height,
+            depth,
+        );
+        layer.offset = Vec2::new(tiled_layer.offsetx, tiled_layer.offsety);
+        layer.opacity = tiled_layer.opacity;
+        layer.is_visible = tiled_layer.visible;
+
+        for (cell, &global_tile_id) in layer.cells.iter_mut().zip(tiled_layer.data.iter()) {
+            // NOTE: We draw flipped and rotated tiles without their transformation
+            let global_tile_id = global_tile_id & !TILED_TILE_FLAGS_MASK;
+            if global_tile_id == 0 {
+                continue;
+            }
+            let (firstgid, first_index, tilecount) = tileset_first_indices
+                .iter()
+                .rev()
+                .find(|(firstgid, _, _)| *firstgid <= global_tile_id)
+                .ok_or_else(|| {
+                    failure::err_msg(format!(
+                        "Layer '{}' contains tile {} which belongs to no tileset",
+                        tiled_layer.name, global_tile_id
+                    ))
+                })?;
+            if global_tile_id - firstgid >= *tilecount {
+                return Err(failure::err_msg(format!(
+                    "Layer '{}' contains tile {} which is beyond the {} tiles of its tileset",
+                    tiled_layer.name, global_tile_id, tilecount
+                )));
+            }
+            *cell = Some(first_index + global_tile_id - firstgid);
+        }
+        tilemap.layers.push(layer);
+    }
+
+    Ok(tilemap)
+}
+
+fn pack_tileset(
+    packer: &mut AtlasPacker,
+    tileset: &TiledTileset,
+    tilemap_dir: &Path,
+) -> Result<Vec<Tile>, Error> {
+    if let Some(source) = &tileset.source {
+        return Err(failure::err_msg(format!(
+            "External tileset '{}' is not supported - please embed it into the map",
+            source
+        )));
+    }
+
+    if tileset.columns == 0 {
+        return Err(failure::err_msg("Tileset has no columns"));
+    }
+
+    let image_filepath = tilemap_dir.join(&tileset.image);
+    let mut image = image::open(&image_filepath)
+        .context(format!(
+            "Could not open tileset image '{}'",
+            image_filepath.display()
+        ))?
+        .to_rgba();
+
+    let mut tiles = Vec::with_capacity(tileset.tilecount as usize);
+    for tile_id in 0..tileset.tilecount {
+        let column = tile_id % tileset.columns;
+        let row = tile_id / tileset.columns;
+        let x = tileset.margin + column * (tileset.tilewidth + tileset.spacing);
+        let y = tileset.margin + row * (tileset.tileheight + tileset.spacing);
+        if x + tileset.tilewidth > image.width() || y + tileset.tileheight > image.height() {
+            return Err(failure::err_msg(format!(
+                "Tile {} does not fit into the tileset image of size {}x{}",
+                tile_id,
+                image.width(),
+                image.height()
+            )));
+        }
+
+        let tile_image =
+            image::imageops::crop(&mut image, x, y, tileset.tilewidth, tileset.tileheight)
+                .to_image();
+        let region = packer.pack_image(tile_image);
+        tiles.push(Tile {
+            sprite: region.to_sprite(packer.atlas_size, Vec2::zero()),
+            animation: None,
+        });
+    }
+
+    for tiled_tile in tileset
+        .tiles
+        .iter()
+        .filter(|tile| !tile.animation.is_empty())
+    {
+        let mut animation = Animation::default();
+        for frame in &tiled_tile.animation {
+            let frame_tile = tiles.get(frame.tileid as usize).ok_or_else(|| {
+                failure::err_msg(format!(
+                    "Animation of tile {} references the non-existing tile {}",
+                    tiled_tile.id, frame.tileid
+                ))
+            })?;
+            animation.frames.push(frame_tile.sprite);
+            animation.frame_durations.push(frame.duration as f32);
+        }
+        let tile = tiles.get_mut(tiled_tile.id as usize).ok_or_else(|| {
+            failure::err_msg(format!(
+                "Tileset has an animation for the non-existing tile {}",
+                tiled_tile.id
+            ))
+        })?;
+        tile.animation = Some(animation);
+    }
+
+    Ok(tiles)
+}
+
+fn layer_depth(tiled_layer: &TiledLayer) -> Option<f32> {
+    tiled_layer
+        .properties
+        .iter()
+        .find(|property| property.name == "depth")
+        .and_then(|property| property.value.as_f64())
+        .map(|depth| depth as f32)
+}
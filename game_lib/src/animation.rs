@@ -0,0 +1,239 @@
+use crate::draw::{Animation, AnimationHandle};
+
+// NOTE: The frame durations of our animations are given in milliseconds as this is what aseprite
+//       uses. Everything else in the game works with seconds.
+const MILLISECONDS_PER_SECOND: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationPlaybackMode {
+    /// Plays all frames and then starts again from the first frame
+    Loop,
+    /// Plays all frames forwards and then backwards again
+    PingPong,
+    /// Plays all frames once and then stays on the last frame
+    OneShot,
+}
+
+//==================================================================================================
+// Animation
+//==================================================================================================
+//
+
+impl Animation {
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the duration of the given frame in seconds
+    pub fn frame_duration(&self, frame_index: usize) -> f32 {
+        self.frame_durations[frame_index] / MILLISECONDS_PER_SECOND
+    }
+
+    /// Returns the duration of one cycle of the animation in seconds. For ping-pong playback this
+    /// is the time it takes to play forwards and backwards again.
+    pub fn cycle_duration(&self, mode: AnimationPlaybackMode) -> f32 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+        (0..self.num_steps_per_cycle(mode))
+            .map(|step| self.frame_duration(self.frame_index_for_step(step, mode)))
+            .sum()
+    }
+
+    /// Returns the index of the frame that is shown at the given time in seconds since the start
+    /// of the animation. This is zero for animations without frames.
+    ///
+    /// # Examples
+    /// ```
+    /// # use game_lib::{Animation, Sprite};
+    /// # use game_lib::animation::AnimationPlaybackMode;
+    /// let animation = Animation {
+    ///     frame_durations: vec![100.0, 100.0, 100.0],
+    ///     frames: vec![Sprite::default(); 3],
+    /// };
+    ///
+    /// assert_eq!(animation.frame_index_at_time(0.35, AnimationPlaybackMode::Loop), 0);
+    /// assert_eq!(animation.frame_index_at_time(0.35, AnimationPlaybackMode::PingPong), 1);
+    /// assert_eq!(animation.frame_index_at_time(0.35, AnimationPlaybackMode::OneShot), 2);
+    ///
+    /// let empty_animation = Animation::default();
+    /// assert_eq!(empty_animation.frame_index_at_time(0.35, AnimationPlaybackMode::PingPong), 0);
+    /// ```
+    pub fn frame_index_at_time(&self, time: f32, mode: AnimationPlaybackMode) -> usize {
+        if self.frames.is_empty() {
+            return 0;
+        }
+        let step = self.step_at_time(time, mode);
+        self.frame_index_for_step(step % self.num_steps_per_cycle(mode), mode)
+    }
+
+    /// The number of frames we show in one cycle. Ping-pong playback does not repeat the first
+    /// and last frame when changing directions.
+    fn num_steps_per_cycle(&self, mode: AnimationPlaybackMode) -> usize {
+        let num_frames = self.num_frames();
+        match mode {
+            AnimationPlaybackMode::PingPong if num_frames > 1 => 2 * num_frames - 2,
+            _ => usize::max(num_frames, 1),
+        }
+    }
+
+    fn frame_index_for_step(&self, step_in_cycle: usize, mode: AnimationPlaybackMode) -> usize {
+        let num_frames = self.num_frames();
+        match mode {
+            AnimationPlaybackMode::PingPong if step_in_cycle >= num_frames => {
+                2 * num_frames - 2 - step_in_cycle
+            }
+            _ => step_in_cycle,
+        }
+    }
+
+    /// Returns the number of frame changes since the start of the animation. This keeps counting
+    /// over multiple cycles and therefore allows us to determine which frames were entered
+    /// between two points in time.
+    fn step_at_time(&self, time: f32, mode: AnimationPlaybackMode) -> usize {
+        if self.frames.is_empty() {
+            return 0;
+        }
+        let num_steps_per_cycle = self.num_steps_per_cycle(mode);
+        let cycle_duration = self.cycle_duration(mode);
+        if time <= 0.0 || cycle_duration <= 0.0 {
+            return 0;
+        }
+        if mode == AnimationPlaybackMode::OneShot && time >= cycle_duration {
+            return num_steps_per_cycle - 1;
+        }
+
+        let num_cycles = (time / cycle_duration).floor();
+        let mut time_in_cycle = time - num_cycles * cycle_duration;
+        let mut step_in_cycle = 0;
+        while step_in_cycle < num_steps_per_cycle - 1 {
+            let frame_duration =
+                self.frame_duration(self.frame_index_for_step(step_in_cycle, mode));
+            if time_in_cycle < frame_duration {
+                break;
+            }
+            time_in_cycle -= frame_duration;
+            step_in_cycle += 1;
+        }
+        num_cycles as usize * num_steps_per_cycle + step_in_cycle
+    }
+}
+
+//==================================================================================================
+// AnimationPlayer
+//==================================================================================================
+//
+
+#[derive(Debug, Clone)]
+struct AnimationFrameEvent {
+    frame_index: usize,
+    name: String,
+}
+
+/// Keeps track of the playback state of an animation in the atlas. The animation itself is only
+/// referenced by its handle so that the player stays valid when the assets are reloaded.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    pub animation: AnimationHandle,
+    pub mode: AnimationPlaybackMode,
+    /// Factor by which the playback is sped up. Negative values are treated as zero.
+    pub speed: f32,
+    pub is_paused: bool,
+
+    elapsed_time: f32,
+    has_started: bool,
+    frame_events: Vec<AnimationFrameEvent>,
+    triggered_events: Vec<String>,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: AnimationHandle, mode: AnimationPlaybackMode) -> AnimationPlayer {
+        AnimationPlayer {
+            animation,
+            mode,
+            speed: 1.0,
+            is_paused: false,
+            elapsed_time: 0.0,
+            has_started: false,
+            frame_events: Vec::new(),
+            triggered_events: Vec::new(),
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> AnimationPlayer {
+        self.speed = speed;
+        self
+    }
+
+    /// Registers an event that is triggered everytime the given frame is entered
+    pub fn with_frame_event(mut self, frame_index: usize, name: &str) -> AnimationPlayer {
+        self.frame_events.push(AnimationFrameEvent {
+            frame_index,
+            name: String::from(name),
+        });
+        self
+    }
+
+    /// Advances the playback by the given time in seconds and collects the events of all frames
+    /// that were entered since the last update
+    pub fn update(&mut self, delta_time: f32, animation: &Animation) {
+        self.triggered_events.clear();
+        if self.is_paused || animation.frames.is_empty() {
+            return;
+        }
+
+        let previous_step = animation.step_at_time(self.elapsed_time, self.mode);
+        self.elapsed_time += f32::max(self.speed, 0.0) * delta_time;
+        let current_step = animation.step_at_time(self.elapsed_time, self.mode);
+
+        // NOTE: If we skipped more than a whole cycle in one update we only trigger the events of
+        //       the last cycle
+        let num_steps_per_cycle = animation.num_steps_per_cycle(self.mode);
+        let first_step_of_last_cycle = current_step.saturating_sub(num_steps_per_cycle - 1);
+        let first_new_step = if self.has_started {
+            previous_step + 1
+        } else {
+            self.has_started = true;
+            previous_step
+        };
+        for step in usize::max(first_new_step, first_step_of_last_cycle)..=current_step {
+            let frame_index = animation.frame_index_for_step(step % num_steps_per_cycle, self.mode);
+            for event in &self.frame_events {
+                if event.frame_index == frame_index {
+                    self.triggered_events.push(event.name.clone());
+                }
+            }
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.elapsed_time = 0.0;
+        self.has_started = false;
+        self.triggered_events.clear();
+    }
+
+    /// Returns the time in seconds since the start of the playback
+    pub fn elapsed_time(&self) -> f32 {
+        self.elapsed_time
+    }
+
+    pub fn current_frame_index(&self, animation: &Animation) -> usize {
+        animation.frame_index_at_time(self.elapsed_time, self.mode)
+    }
+
+    /// Returns true if a one-shot animation has reached the end of its last frame. Looping and
+    /// ping-pong animations never finish.
+    pub fn is_finished(&self, animation: &Animation) -> bool {
+        self.mode == AnimationPlaybackMode::OneShot
+            && self.elapsed_time >= animation.cycle_duration(self.mode)
+    }
+
+    /// Returns the names of all events that were triggered in the last update
+    pub fn triggered_events(&self) -> &[String] {
+        &self.triggered_events
+    }
+
+    pub fn had_event(&self, name: &str) -> bool {
+        self.triggered_events.iter().any(|event| event == name)
+    }
+}
@@ -0,0 +1,294 @@
+use crate::math::{clamp, Camera, Rect, Vec2, WorldPoint, WorldVec};
+
+const SHAKE_NOISE_FREQUENCY: f32 = 25.0;
+const ZOOM_SNAP_DISTANCE: f32 = 0.001;
+
+/// A camera position and zoom level that the [`CameraController`] can transition to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPreset {
+    pub pos: WorldPoint,
+    pub zoom_level: f32,
+}
+
+impl CameraPreset {
+    pub fn new(pos: WorldPoint, zoom_level: f32) -> CameraPreset {
+        CameraPreset { pos, zoom_level }
+    }
+
+    fn from_camera(cam: &Camera) -> CameraPreset {
+        CameraPreset::new(cam.pos(), cam.zoom_level)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CameraTransition {
+    start: CameraPreset,
+    end: CameraPreset,
+    duration: f32,
+    time_elapsed: f32,
+}
+
+impl CameraTransition {
+    fn is_finished(&self) -> bool {
+        self.time_elapsed >= self.duration
+    }
+
+    fn current_preset(&self) -> CameraPreset {
+        let percentage = if self.duration > 0.0 {
+            clamp(self.time_elapsed / self.duration, 0.0, 1.0)
+        } else {
+            1.0
+        };
+        // NOTE: Smoothstep so that the camera accelerates and decelerates gently
+        let t = percentage * percentage * (3.0 - 2.0 * percentage);
+        CameraPreset {
+            pos: self.start.pos + t * (self.end.pos - self.start.pos),
+            zoom_level: self.start.zoom_level + t * (self.end.zoom_level - self.start.zoom_level),
+        }
+    }
+}
+
+/// Moves a [`Camera`] over time. It can follow a target, keep the camera inside world bounds,
+/// zoom smoothly, shake the camera and transition between presets.
+///
+/// The camera is only moved by the controller inside of `update`. Changes to the camera that
+/// happen outside of `update` (like mouse panning) are respected by the controller.
+///
+/// # Example
+/// ```
+/// # use game_lib::math::*;
+/// # use game_lib::camera::*;
+/// let mut cam = Camera::new(Point::zero(), 320.0, 180.0, -1.0, 1.0);
+/// let mut controller = CameraController::new();
+///
+/// controller.follow(Point::new(100.0, 0.0));
+/// for _frame in 0..600 {
+///     controller.update(&mut cam, 1.0 / 60.0);
+/// }
+/// assert!(cam.pos().distance_to(Point::new(100.0, 0.0)) < 1.0);
+///
+/// // The camera cannot look outside of the world bounds
+/// controller.bounds = Some(Rect::from_bounds(-200.0, 200.0, 100.0, -100.0));
+/// controller.update(&mut cam, 1.0 / 60.0);
+/// assert_eq!(cam.frustum().right, 200.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    target: Option<WorldPoint>,
+    /// Half the size of the area around the camera center in which the target can move freely
+    /// without the camera following it
+    pub dead_zone: WorldVec,
+    /// How fast the camera catches up with its target. Higher values result in a stiffer camera.
+    pub follow_speed: f32,
+
+    /// The camera frustum is kept inside of these bounds. If the frustum is larger than the
+    /// bounds the camera is centered on them instead.
+    pub bounds: Option<Rect>,
+
+    target_zoom_level: Option<f32>,
+    /// How fast the camera approaches its target zoom level
+    pub zoom_speed: f32,
+
+    transition: Option<CameraTransition>,
+
+    /// Amount of shake in the range [0, 1]. The shake intensity grows quadratically with it.
+    trauma: f32,
+    /// Trauma that is removed per second
+    pub trauma_decay: f32,
+    /// Offset in canvas pixels at full trauma
+    pub max_shake_offset: f32,
+    shake_time: f32,
+    shake_offset: WorldVec,
+}
+
+impl Default for CameraController {
+    fn default() -> CameraController {
+        CameraController {
+            target: None,
+            dead_zone: WorldVec::zero(),
+            follow_speed: 5.0,
+            bounds: None,
+            target_zoom_level: None,
+            zoom_speed: 8.0,
+            transition: None,
+            trauma: 0.0,
+            trauma_decay: 1.5,
+            max_shake_offset: 8.0,
+            shake_time: 0.0,
+            shake_offset: WorldVec::zero(),
+        }
+    }
+}
+
+impl CameraController {
+    pub fn new() -> CameraController {
+        Default::default()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Configuration
+    //
+    pub fn follow(&mut self, target: WorldPoint) {
+        self.target = Some(target);
+    }
+
+    pub fn stop_following(&mut self) {
+        self.target = None;
+    }
+
+    pub fn zoom_to(&mut self, zoom_level: f32) {
+        self.target_zoom_level = Some(zoom_level);
+    }
+
+    /// Adds trauma which makes the camera shake. Trauma is capped at 1.0.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = clamp(self.trauma + amount, 0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Smoothly moves the camera to the given preset. Following and zooming are suspended until
+    /// the transition has finished.
+    pub fn transition_to(&mut self, cam: &Camera, preset: CameraPreset, duration: f32) {
+        let mut start = CameraPreset::from_camera(cam);
+        start.pos -= self.shake_offset;
+        self.transition = Some(CameraTransition {
+            start,
+            end: preset,
+            duration,
+            time_elapsed: 0.0,
+        });
+        self.target_zoom_level = None;
+    }
+
+    /// Moves the camera to the given preset immediately
+    pub fn jump_to(&mut self, cam: &mut Camera, preset: CameraPreset) {
+        self.transition = None;
+        self.target_zoom_level = None;
+        self.shake_offset = WorldVec::zero();
+        cam.zoom_level = preset.zoom_level;
+        cam.set_pos(preset.pos);
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Update
+    //
+    pub fn update(&mut self, cam: &mut Camera, delta_time: f32) {
+        // NOTE: We remove last frame's shake first so that it does not accumulate
+        let mut pos = cam.pos() - self.shake_offset;
+        let mut zoom_level = cam.zoom_level;
+
+        if let Some(mut transition) = self.transition.take() {
+            transition.time_elapsed += delta_time;
+            let preset = transition.current_preset();
+            pos = preset.pos;
+            zoom_level = preset.zoom_level;
+            if !transition.is_finished() {
+                self.transition = Some(transition);
+            }
+        } else {
+            if let Some(target_zoom_level) = self.target_zoom_level {
+                zoom_level += (target_zoom_level - zoom_level)
+                    * damping_percentage(self.zoom_speed, delta_time);
+                if f32::abs(target_zoom_level - zoom_level) < ZOOM_SNAP_DISTANCE {
+                    zoom_level = target_zoom_level;
+                    self.target_zoom_level = None;
+                }
+            }
+
+            if let Some(target) = self.target {
+                let desired_pos = pos_with_target_in_dead_zone(pos, target, self.dead_zone);
+                pos += (desired_pos - pos) * damping_percentage(self.follow_speed, delta_time);
+            }
+        }
+
+        cam.zoom_level = zoom_level;
+        if let Some(bounds) = self.bounds {
+            pos = pos_with_frustum_in_bounds(pos, cam.dim_zoomed(), bounds);
+        }
+
+        // Screen shake
+        self.trauma = f32::max(0.0, self.trauma - self.trauma_decay * delta_time);
+        self.shake_time += delta_time;
+        let shake = self.trauma * self.trauma;
+        let noise_time = SHAKE_NOISE_FREQUENCY * self.shake_time;
+        let shake_offset_canvas =
+            self.max_shake_offset * shake * Vec2::new(noise(0, noise_time), noise(1, noise_time));
+        // NOTE: The shake offset is rounded to whole canvas pixels so that the picture stays crisp
+        self.shake_offset = cam.canvas_vec_to_world_vec(Vec2::new(
+            shake_offset_canvas.x.round(),
+            shake_offset_canvas.y.round(),
+        ));
+
+        cam.set_pos(pos + self.shake_offset);
+    }
+}
+
+/// Returns the percentage of the remaining distance that an exponentially damped value covers
+/// in the given time. This makes damping independent of the framerate.
+fn damping_percentage(speed: f32, delta_time: f32) -> f32 {
+    1.0 - f32::exp(-speed * delta_time)
+}
+
+fn pos_with_target_in_dead_zone(
+    pos: WorldPoint,
+    target: WorldPoint,
+    dead_zone: WorldVec,
+) -> WorldPoint {
+    let distance = target - pos;
+    let outside_distance = |distance: f32, half_extent: f32| {
+        if distance > half_extent {
+            distance - half_extent
+        } else if distance < -half_extent {
+            distance + half_extent
+        } else {
+            0.0
+        }
+    };
+    pos + Vec2::new(
+        outside_distance(distance.x, dead_zone.x),
+        outside_distance(distance.y, dead_zone.y),
+    )
+}
+
+fn pos_with_frustum_in_bounds(pos: WorldPoint, frustum_dim: WorldVec, bounds: Rect) -> WorldPoint {
+    let clamp_axis = |pos: f32, half_extent: f32, min: f32, max: f32| {
+        if max - min <= 2.0 * half_extent {
+            0.5 * (min + max)
+        } else {
+            clamp(pos, min + half_extent, max - half_extent)
+        }
+    };
+    WorldPoint::new(
+        clamp_axis(pos.x, 0.5 * frustum_dim.x, bounds.left, bounds.right),
+        clamp_axis(pos.y, 0.5 * frustum_dim.y, bounds.top, bounds.bottom),
+    )
+}
+
+/// Smooth one dimensional value noise in the range [-1, 1]. Different seeds result in
+/// uncorrelated noise.
+fn noise(seed: u32, time: f32) -> f32 {
+    let lattice_value = |index: i32| {
+        // NOTE: Integer hash from https://nullprogram.com/blog/2018/07/31/
+        let mut hash = (index as u32) ^ seed.wrapping_mul(0x9e37_79b9);
+        hash ^= hash >> 16;
+        hash = hash.wrapping_mul(0x7feb_352d);
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(0x846c_a68b);
+        hash ^= hash >> 16;
+        2.0 * (hash as f32 / std::u32::MAX as f32) - 1.0
+    };
+
+    let index = time.floor();
+    let percentage = time - index;
+    let t = percentage * percentage * (3.0 - 2.0 * percentage);
+    let start = lattice_value(index as i32);
+    let end = lattice_value(index as i32 + 1);
+    start + t * (end - start)
+}
@@ -0,0 +1,528 @@
+use crate::math::{clamp, Color};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Is returned for color names that do not exist in a palette so that they stand out
+const PALETTE_FALLBACK_COLOR: Color = Color {
+    x: 1.0,
+    y: 0.0,
+    z: 1.0,
+    w: 1.0,
+};
+
+//==================================================================================================
+// Conversion
+//==================================================================================================
+//
+
+/// Creates a color from a hue in degrees and a saturation and value in \[0, 1\]. Hues outside of
+/// \[0, 360\[ wrap around.
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::math::*;
+/// assert_eq!(color_from_hsv(0.0, 1.0, 1.0, 1.0), Color::new(1.0, 0.0, 0.0, 1.0));
+/// assert_eq!(color_from_hsv(480.0, 1.0, 0.5, 1.0), Color::new(0.0, 0.5, 0.0, 1.0));
+///
+/// let (hue, saturation, value) = color_to_hsv(Color::new(0.0, 0.5, 0.0, 1.0));
+/// assert_eq!((hue, saturation, value), (120.0, 1.0, 0.5));
+/// ```
+pub fn color_from_hsv(hue: f32, saturation: f32, value: f32, alpha: f32) -> Color {
+    let chroma = value * saturation;
+    let (r, g, b) = rgb_from_hue_and_chroma(hue, chroma);
+    let lightness_offset = value - chroma;
+    Color::new(
+        r + lightness_offset,
+        g + lightness_offset,
+        b + lightness_offset,
+        alpha,
+    )
+}
+
+/// Returns the hue in degrees and the saturation and value of a color. The alpha channel is
+/// ignored.
+pub fn color_to_hsv(color: Color) -> (f32, f32, f32) {
+    let (hue, min, max) = hue_min_max_from_rgb(color);
+    let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+    (hue, saturation, max)
+}
+
+/// Creates a color from a hue in degrees and a saturation and lightness in \[0, 1\]. Hues outside
+/// of \[0, 360\[ wrap around.
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::math::*;
+/// assert_eq!(color_from_hsl(240.0, 1.0, 0.5, 1.0), Color::new(0.0, 0.0, 1.0, 1.0));
+/// assert_eq!(color_from_hsl(0.0, 0.0, 1.0, 0.5), Color::new(1.0, 1.0, 1.0, 0.5));
+///
+/// let (hue, saturation, lightness) = color_to_hsl(Color::new(0.0, 0.0, 1.0, 1.0));
+/// assert_eq!((hue, saturation, lightness), (240.0, 1.0, 0.5));
+/// ```
+pub fn color_from_hsl(hue: f32, saturation: f32, lightness: f32, alpha: f32) -> Color {
+    let chroma = (1.0 - f32::abs(2.0 * lightness - 1.0)) * saturation;
+    let (r, g, b) = rgb_from_hue_and_chroma(hue, chroma);
+    let lightness_offset = lightness - 0.5 * chroma;
+    Color::new(
+        r + lightness_offset,
+        g + lightness_offset,
+        b + lightness_offset,
+        alpha,
+    )
+}
+
+/// Returns the hue in degrees and the saturation and lightness of a color. The alpha channel is
+/// ignored.
+pub fn color_to_hsl(color: Color) -> (f32, f32, f32) {
+    let (hue, min, max) = hue_min_max_from_rgb(color);
+    let lightness = 0.5 * (max + min);
+    let saturation = if max > min {
+        (max - min) / (1.0 - f32::abs(2.0 * lightness - 1.0))
+    } else {
+        0.0
+    };
+    (hue, saturation, lightness)
+}
+
+/// Converts a color from sRGB to linear space. The alpha channel is left untouched.
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::math::*;
+/// let color = Color::new(0.2, 0.5, 0.8, 0.5);
+/// let linear = color_srgb_to_linear(color);
+/// assert!(linear.x < color.x && linear.w == color.w);
+/// assert!((color_linear_to_srgb(linear) - color).magnitude() < 0.0001);
+/// ```
+pub fn color_srgb_to_linear(color: Color) -> Color {
+    let to_linear = |channel: f32| {
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            f32::powf((channel + 0.055) / 1.055, 2.4)
+        }
+    };
+    Color::new(
+        to_linear(color.x),
+        to_linear(color.y),
+        to_linear(color.z),
+        color.w,
+    )
+}
+
+/// Converts a color from linear to sRGB space. The alpha channel is left untouched.
+pub fn color_linear_to_srgb(color: Color) -> Color {
+    let to_srgb = |channel: f32| {
+        if channel <= 0.003_130_8 {
+            channel * 12.92
+        } else {
+            1.055 * f32::powf(channel, 1.0 / 2.4) - 0.055
+        }
+    };
+    Color::new(
+        to_srgb(color.x),
+        to_srgb(color.y),
+        to_srgb(color.z),
+        color.w,
+    )
+}
+
+/// Returns the red, green and blue channels of a color with the given hue and chroma where the
+/// smallest channel is zero
+fn rgb_from_hue_and_chroma(hue: f32, chroma: f32) -> (f32, f32, f32) {
+    let hue_sector = f32::rem_euclid(hue, 360.0) / 60.0;
+    let second_largest = chroma * (1.0 - f32::abs(f32::rem_euclid(hue_sector, 2.0) - 1.0));
+    match hue_sector as usize {
+        0 => (chroma, second_largest, 0.0),
+        1 => (second_largest, chroma, 0.0),
+        2 => (0.0, chroma, second_largest),
+        3 => (0.0, second_largest, chroma),
+        4 => (second_largest, 0.0, chroma),
+        _ => (chroma, 0.0, second_largest),
+    }
+}
+
+/// Returns the hue in degrees and the smallest and largest of the red, green and blue channels
+fn hue_min_max_from_rgb(color: Color) -> (f32, f32, f32) {
+    let (r, g, b) = (color.x, color.y, color.z);
+    let max = f32::max(r, f32::max(g, b));
+    let min = f32::min(r, f32::min(g, b));
+    let chroma = max - min;
+
+    let hue_sector = if chroma <= 0.0 {
+        0.0
+    } else if max == r {
+        f32::rem_euclid((g - b) / chroma, 6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (60.0 * hue_sector, min, max)
+}
+
+//==================================================================================================
+// Hex strings
+//==================================================================================================
+//
+
+/// Parses colors of the form `RRGGBB` or `RRGGBBAA` with an optional leading `#`
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::math::*;
+/// assert_eq!(parse_hex_color("#ff0000"), Some(Color::new(1.0, 0.0, 0.0, 1.0)));
+/// assert_eq!(parse_hex_color("00ff0000"), Some(Color::new(0.0, 1.0, 0.0, 0.0)));
+/// assert_eq!(parse_hex_color("#ff00"), None);
+/// assert_eq!(parse_hex_color("#ff00zz"), None);
+///
+/// let color = parse_hex_color("#336699ff").unwrap();
+/// assert_eq!(color_to_hex(color), "#336699ff");
+/// ```
+pub fn parse_hex_color(hex: &str) -> Option<Color> {
+    let hex = if hex.starts_with('#') { &hex[1..] } else { hex };
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |index: usize| {
+        hex.get(2 * index..2 * index + 2)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .map(|value| f32::from(value) / 255.0)
+    };
+    let alpha = if hex.len() == 8 { channel(3)? } else { 1.0 };
+    Some(Color::new(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
+
+/// Formats a color as `#rrggbbaa`. Channels are clamped to \[0, 1\].
+pub fn color_to_hex(color: Color) -> String {
+    let channel = |value: f32| (255.0 * clamp(value, 0.0, 1.0)).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        channel(color.x),
+        channel(color.y),
+        channel(color.z),
+        channel(color.w)
+    )
+}
+
+//==================================================================================================
+// Blending
+//==================================================================================================
+//
+
+/// Linearly interpolates between two colors where `percent` is clamped to \[0, 1\]
+pub fn lerp_color(start: Color, end: Color, percent: f32) -> Color {
+    let percent = clamp(percent, 0.0, 1.0);
+    start + (end - start) * percent
+}
+
+/// Returns the color as it ends up in the framebuffer when drawn with the given additivity. This
+/// matches what the fragment shader does with vertex colors: The color channels are multiplied
+/// by alpha and the resulting alpha is reduced by the additivity.
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::draw::*;
+/// # use game_lib::math::*;
+/// let color = Color::new(1.0, 0.5, 0.0, 0.5);
+/// assert_eq!(color_premultiplied(color, ADDITIVITY_NONE), Color::new(0.5, 0.25, 0.0, 0.5));
+/// assert_eq!(color_premultiplied(color, ADDITIVITY_MAX), Color::new(0.5, 0.25, 0.0, 0.0));
+/// assert_eq!(color_unpremultiplied(Color::new(0.5, 0.25, 0.0, 0.5)), color);
+/// ```
+pub fn color_premultiplied(color: Color, additivity: f32) -> Color {
+    Color::new(
+        color.x * color.w,
+        color.y * color.w,
+        color.z * color.w,
+        color.w * (1.0 - additivity),
+    )
+}
+
+/// Reverts `color_premultiplied` for colors drawn without additivity. Fully transparent colors
+/// stay black because their original color channels are lost.
+pub fn color_unpremultiplied(color: Color) -> Color {
+    if color.w <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0, 0.0);
+    }
+    Color::new(
+        color.x / color.w,
+        color.y / color.w,
+        color.z / color.w,
+        color.w,
+    )
+}
+
+//==================================================================================================
+// Gradient
+//==================================================================================================
+//
+
+/// Maps percentages in \[0, 1\] to colors by linearly interpolating between color stops
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::math::*;
+/// let black = Color::new(0.0, 0.0, 0.0, 1.0);
+/// let white = Color::new(1.0, 1.0, 1.0, 1.0);
+/// let red = Color::new(1.0, 0.0, 0.0, 1.0);
+///
+/// let gradient = Gradient::new(black, white).with_stop(0.5, red);
+/// assert_eq!(gradient.sample(0.0), black);
+/// assert_eq!(gradient.sample(0.25), Color::new(0.5, 0.0, 0.0, 1.0));
+/// assert_eq!(gradient.sample(0.5), red);
+/// assert_eq!(gradient.sample(2.0), white);
+///
+/// let evenly_spaced = Gradient::from_colors(&[black, red, white]);
+/// assert_eq!(evenly_spaced, gradient);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// Pairs of percentage and color sorted by percentage
+    stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    pub fn new(start: Color, end: Color) -> Gradient {
+        Gradient {
+            stops: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    /// Spreads the given colors evenly over the gradient
+    pub fn from_colors(colors: &[Color]) -> Gradient {
+        assert!(!colors.is_empty(), "A gradient needs at least one color");
+        let max_index = usize::max(1, colors.len() - 1) as f32;
+        Gradient {
+            stops: colors
+                .iter()
+                .enumerate()
+                .map(|(index, color)| (index as f32 / max_index, *color))
+                .collect(),
+        }
+    }
+
+    /// Adds a color stop at the given percentage which is clamped to \[0, 1\]
+    pub fn with_stop(mut self, percent: f32, color: Color) -> Gradient {
+        let percent = clamp(percent, 0.0, 1.0);
+        let insert_index = self
+            .stops
+            .iter()
+            .position(|(stop_percent, _)| *stop_percent > percent)
+            .unwrap_or(self.stops.len());
+        self.stops.insert(insert_index, (percent, color));
+        self
+    }
+
+    pub fn sample(&self, percent: f32) -> Color {
+        let next_index = self
+            .stops
+            .iter()
+            .position(|(stop_percent, _)| *stop_percent > percent);
+        match next_index {
+            Some(0) => self.stops[0].1,
+            Some(index) => {
+                let (start_percent, start_color) = self.stops[index - 1];
+                let (end_percent, end_color) = self.stops[index];
+                lerp_color(
+                    start_color,
+                    end_color,
+                    (percent - start_percent) / (end_percent - start_percent),
+                )
+            }
+            None => self.stops.last().unwrap().1,
+        }
+    }
+}
+
+//==================================================================================================
+// Palette
+//==================================================================================================
+//
+
+/// An indexed list of colors where colors can optionally be looked up by name. Palettes are
+/// loaded from the same files that the art is made with so that art and code share one palette.
+///
+/// Supported are `.hex` files with one `RRGGBB` color per line and `.gpl` files (GIMP palettes)
+/// which can be saved from the palette menu of Aseprite. Only `.gpl` files contain names.
+///
+/// # Example
+/// ```
+/// # use game_lib::color::*;
+/// # use game_lib::math::*;
+/// let palette = Palette::from_gpl_str(
+///     "palette",
+///     "GIMP Palette\nName: test\n#\n255   0   0\tred\n  0   0 255\tdeep blue\n",
+/// )
+/// .unwrap();
+/// assert_eq!(palette.len(), 2);
+/// assert_eq!(palette.color(1), Color::new(0.0, 0.0, 1.0, 1.0));
+/// assert_eq!(palette.color_by_name("red"), Color::new(1.0, 0.0, 0.0, 1.0));
+/// assert_eq!(palette.color_index("deep blue"), Some(1));
+/// assert_eq!(palette.color_by_name("green"), Color::new(1.0, 0.0, 1.0, 1.0));
+///
+/// let palette = Palette::from_hex_str("palette", "ff0000\n0000ff\n").unwrap();
+/// assert_eq!(palette.color(1), Color::new(0.0, 0.0, 1.0, 1.0));
+/// assert!(Palette::from_hex_str("palette", "ff0000\nblue\n").is_err());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<Color>,
+    color_indices: HashMap<String, usize>,
+    /// Color names that were looked up but do not exist. We only warn once about each of them.
+    missing_color_names: RefCell<HashSet<String>>,
+}
+
+impl Palette {
+    /// Loads a `.hex` or `.gpl` palette file depending on its extension
+    pub fn load_from_file(filename: &str) -> Result<Palette, String> {
+        let content = std::fs::read_to_string(filename)
+            .map_err(|error| format!("Could not read palette file '{}' : {}", filename, error))?;
+        let name = std::path::Path::new(filename)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(filename);
+
+        let palette = if filename.ends_with(".hex") {
+            Palette::from_hex_str(name, &content)
+        } else if filename.ends_with(".gpl") {
+            Palette::from_gpl_str(name, &content)
+        } else {
+            Err(String::from("Unknown palette file extension"))
+        };
+        let palette = palette
+            .map_err(|error| format!("Could not load palette file '{}' : {}", filename, error))?;
+        info!(
+            "Loaded palette from '{}', {} colors available",
+            filename,
+            palette.len()
+        );
+        Ok(palette)
+    }
+
+    /// Parses a `.hex` palette with one `RRGGBB` or `RRGGBBAA` color per line
+    pub fn from_hex_str(name: &str, content: &str) -> Result<Palette, String> {
+        let mut palette = Palette {
+            name: String::from(name),
+            ..Default::default()
+        };
+        for (line_index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let color = parse_hex_color(line)
+                .ok_or_else(|| format!("Invalid color '{}' in line {}", line, line_index + 1))?;
+            palette.colors.push(color);
+        }
+        Ok(palette)
+    }
+
+    /// Parses a GIMP palette. Every color line consists of the red, green and blue channels in
+    /// \[0, 255\] followed by an optional name. Aseprite additionally writes an alpha channel when
+    /// the header contains `Channels: RGBA`.
+    pub fn from_gpl_str(name: &str, content: &str) -> Result<Palette, String> {
+        let mut lines = content.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == "GIMP Palette" => {}
+            _ => return Err(String::from("Missing 'GIMP Palette' header")),
+        }
+
+        let mut palette = Palette {
+            name: String::from(name),
+            ..Default::default()
+        };
+        let mut has_alpha_channel = false;
+        for (line_index, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("Name:") {
+                palette.name = String::from(line["Name:".len()..].trim());
+                continue;
+            }
+            if line.starts_with("Channels:") {
+                has_alpha_channel = line["Channels:".len()..].trim() == "RGBA";
+                continue;
+            }
+            if line.starts_with("Columns:") {
+                continue;
+            }
+
+            let num_channels = if has_alpha_channel { 4 } else { 3 };
+            let mut words = line.split_whitespace();
+            let mut channels = [1.0; 4];
+            for channel in channels.iter_mut().take(num_channels) {
+                let value = words
+                    .next()
+                    .and_then(|word| word.parse::<u8>().ok())
+                    .ok_or_else(|| {
+                        format!("Invalid color '{}' in line {}", line, line_index + 1)
+                    })?;
+                *channel = f32::from(value) / 255.0;
+            }
+            let color = Color::from(channels);
+
+            let color_name = words.collect::<Vec<_>>().join(" ");
+            if !color_name.is_empty() {
+                // NOTE: If names are used more than once we keep the first color with that name
+                let color_index = palette.colors.len();
+                palette
+                    .color_indices
+                    .entry(color_name)
+                    .or_insert(color_index);
+            }
+            palette.colors.push(color);
+        }
+        Ok(palette)
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn color(&self, index: usize) -> Color {
+        self.colors[index]
+    }
+
+    pub fn color_index(&self, name: &str) -> Option<usize> {
+        self.color_indices.get(name).copied()
+    }
+
+    /// Returns magenta for names that do not exist in the palette
+    pub fn color_by_name(&self, name: &str) -> Color {
+        match self.color_index(name) {
+            Some(index) => self.colors[index],
+            None => {
+                let is_first_lookup = self
+                    .missing_color_names
+                    .borrow_mut()
+                    .insert(String::from(name));
+                if is_first_lookup {
+                    warn!(
+                        "Palette '{}' does not contain a color named '{}' - using fallback color",
+                        self.name, name
+                    );
+                }
+                PALETTE_FALLBACK_COLOR
+            }
+        }
+    }
+
+    /// Returns the palette colors spread evenly over a gradient
+    pub fn to_gradient(&self) -> Gradient {
+        Gradient::from_colors(&self.colors)
+    }
+}
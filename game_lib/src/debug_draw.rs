@@ -0,0 +1,497 @@
+use crate::draw::{self, CurveDetail, DrawContext, DrawSpace};
+use crate::math::{Camera, CanvasPoint, Circle, Color, Line, Point, Rect, Vec2, WorldPoint};
+
+const OVERLAY_DEPTH_GRID: f32 = -0.3;
+const OVERLAY_DEPTH_SHAPES: f32 = -0.2;
+const OVERLAY_DEPTH_TEXT: f32 = -0.1;
+
+const GRID_BASE_SPACING: f32 = 16.0;
+/// Grid lines are never closer together than this many canvas pixels
+const GRID_MIN_CANVAS_SPACING: f32 = 8.0;
+/// Every n-th grid line is drawn brighter
+const GRID_MAJOR_LINE_INTERVAL: i64 = 4;
+
+// NOTE: The following sizes are given in canvas pixels and stay the same at any zoom level
+const ARROW_HEAD_SIZE: f32 = 4.0;
+const CROSSHAIR_RADIUS: f32 = 6.0;
+const POINT_RADIUS: f32 = 1.0;
+const LABEL_OFFSET: Vec2 = Vec2 { x: 4.0, y: -2.0 };
+
+const COLOR_GRID_MINOR: Color = Color {
+    x: 1.0,
+    y: 1.0,
+    z: 1.0,
+    w: 0.1,
+};
+const COLOR_GRID_MAJOR: Color = Color {
+    x: 1.0,
+    y: 1.0,
+    z: 1.0,
+    w: 0.25,
+};
+const COLOR_GRID_AXIS_X: Color = Color {
+    x: 1.0,
+    y: 0.2,
+    z: 0.2,
+    w: 0.6,
+};
+const COLOR_GRID_AXIS_Y: Color = Color {
+    x: 0.2,
+    y: 1.0,
+    z: 0.2,
+    w: 0.6,
+};
+
+pub const NUM_DEBUG_OVERLAYS: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOverlay {
+    /// A world grid whose spacing adapts to the zoom level of the camera
+    Grid,
+    /// The unzoomed view of the camera and all frustums added via `DebugDraw::camera_frustum`
+    CameraFrustums,
+    /// A crosshair at the mouse cursor and at the camera position as well as all crosshairs
+    /// added via `DebugDraw::crosshair`
+    Crosshairs,
+    /// All points added via `DebugDraw::point`
+    LabeledPoints,
+    /// All lines, arrows, rects and circles added via `DebugDraw`
+    Shapes,
+    /// Replaces the world and canvas with a visualization of their depth values
+    DepthBuffer,
+}
+
+impl DebugOverlay {
+    pub const ALL: [DebugOverlay; NUM_DEBUG_OVERLAYS] = [
+        DebugOverlay::Grid,
+        DebugOverlay::CameraFrustums,
+        DebugOverlay::Crosshairs,
+        DebugOverlay::LabeledPoints,
+        DebugOverlay::Shapes,
+        DebugOverlay::DepthBuffer,
+    ];
+
+    /// The toggle input action that enables the overlay
+    pub fn input_action(self) -> &'static str {
+        match self {
+            DebugOverlay::Grid => "debug_overlay_grid_toggle",
+            DebugOverlay::CameraFrustums => "debug_overlay_camera_frustums_toggle",
+            DebugOverlay::Crosshairs => "debug_overlay_crosshairs_toggle",
+            DebugOverlay::LabeledPoints => "debug_overlay_labeled_points_toggle",
+            DebugOverlay::Shapes => "debug_overlay_shapes_toggle",
+            DebugOverlay::DepthBuffer => "debug_overlay_depth_buffer_toggle",
+        }
+    }
+}
+
+enum DebugShape {
+    Line(Line),
+    Arrow { start: WorldPoint, end: WorldPoint },
+    Rect(Rect),
+    Circle(Circle),
+    CameraFrustum { frustum: Rect, label: String },
+    Crosshair(WorldPoint),
+    Point { pos: WorldPoint, label: String },
+}
+
+impl DebugShape {
+    fn overlay(&self) -> DebugOverlay {
+        match self {
+            DebugShape::Line(_)
+            | DebugShape::Arrow { .. }
+            | DebugShape::Rect(_)
+            | DebugShape::Circle(_) => DebugOverlay::Shapes,
+            DebugShape::CameraFrustum { .. } => DebugOverlay::CameraFrustums,
+            DebugShape::Crosshair(_) => DebugOverlay::Crosshairs,
+            DebugShape::Point { .. } => DebugOverlay::LabeledPoints,
+        }
+    }
+}
+
+struct TimedDebugShape {
+    shape: DebugShape,
+    color: Color,
+    /// Shapes are drawn at least once even if this is zero
+    time_left: f32,
+}
+
+//==================================================================================================
+// DebugDraw
+//==================================================================================================
+//
+
+/// Collects debug shapes that are given in world space and draws them in debug space. This way
+/// their positions follow the camera while line thicknesses, arrowheads and labels keep the same
+/// size at any zoom level. Shapes stay visible for the given duration in seconds.
+///
+/// # Example
+/// ```
+/// # use game_lib::math::*;
+/// # use game_lib::draw::*;
+/// # use game_lib::debug_draw::*;
+/// let mut debug_draw = DebugDraw::default();
+/// debug_draw.set_overlay_enabled(DebugOverlay::LabeledPoints, true);
+///
+/// // Shows the point and its label for the next two seconds
+/// debug_draw.point(WorldPoint::new(10.0, 20.0), "collision", COLOR_RED, 2.0);
+/// // Only shows the arrow in the next drawn frame
+/// debug_draw.arrow(WorldPoint::zero(), WorldPoint::new(5.0, 0.0), COLOR_GREEN, 0.0);
+///
+/// assert!(debug_draw.is_overlay_enabled(DebugOverlay::LabeledPoints));
+/// assert!(!debug_draw.is_overlay_enabled(DebugOverlay::Shapes));
+/// ```
+#[derive(Default)]
+pub struct DebugDraw {
+    enabled_overlays: [bool; NUM_DEBUG_OVERLAYS],
+    shapes: Vec<TimedDebugShape>,
+}
+
+impl DebugDraw {
+    pub fn is_overlay_enabled(&self, overlay: DebugOverlay) -> bool {
+        self.enabled_overlays[overlay as usize]
+    }
+
+    pub fn set_overlay_enabled(&mut self, overlay: DebugOverlay, is_enabled: bool) {
+        self.enabled_overlays[overlay as usize] = is_enabled;
+    }
+
+    pub fn line(&mut self, line: Line, color: Color, duration: f32) {
+        self.add_shape(DebugShape::Line(line), color, duration);
+    }
+
+    pub fn arrow(&mut self, start: WorldPoint, end: WorldPoint, color: Color, duration: f32) {
+        self.add_shape(DebugShape::Arrow { start, end }, color, duration);
+    }
+
+    pub fn rect(&mut self, rect: Rect, color: Color, duration: f32) {
+        self.add_shape(DebugShape::Rect(rect), color, duration);
+    }
+
+    pub fn circle(&mut self, circle: Circle, color: Color, duration: f32) {
+        self.add_shape(DebugShape::Circle(circle), color, duration);
+    }
+
+    /// Adds the outline of the area that the given camera currently shows
+    pub fn camera_frustum(&mut self, cam: &Camera, label: &str, color: Color, duration: f32) {
+        let frustum = cam.frustum();
+        let label = String::from(label);
+        self.add_shape(
+            DebugShape::CameraFrustum { frustum, label },
+            color,
+            duration,
+        );
+    }
+
+    pub fn crosshair(&mut self, pos: WorldPoint, color: Color, duration: f32) {
+        self.add_shape(DebugShape::Crosshair(pos), color, duration);
+    }
+
+    pub fn point(&mut self, pos: WorldPoint, label: &str, color: Color, duration: f32) {
+        let label = String::from(label);
+        self.add_shape(DebugShape::Point { pos, label }, color, duration);
+    }
+
+    fn add_shape(&mut self, shape: DebugShape, color: Color, duration: f32) {
+        self.shapes.push(TimedDebugShape {
+            shape,
+            color,
+            time_left: duration,
+        });
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Drawing
+    //
+
+    /// Draws all enabled overlays and removes the shapes whose duration ran out. Shapes of
+    /// disabled overlays expire all the same.
+    pub fn draw(
+        &mut self,
+        dc: &mut DrawContext,
+        cam: &Camera,
+        canvas_rect: Rect,
+        mouse_pos_world: WorldPoint,
+        delta_time: f32,
+    ) {
+        dc.set_depth_visualization_enabled(self.is_overlay_enabled(DebugOverlay::DepthBuffer));
+
+        if self.is_overlay_enabled(DebugOverlay::Grid) {
+            draw_grid(dc, cam, canvas_rect);
+        }
+        if self.is_overlay_enabled(DebugOverlay::CameraFrustums) && cam.zoom_level != 1.0 {
+            let unzoomed_frustum =
+                Rect::from_point(cam.pos(), canvas_rect.width(), canvas_rect.height()).centered();
+            draw_frustum(dc, cam, unzoomed_frustum, "camera", draw::COLOR_YELLOW);
+        }
+        if self.is_overlay_enabled(DebugOverlay::Crosshairs) {
+            draw_mouse_crosshair(dc, cam, canvas_rect, mouse_pos_world);
+            draw_crosshair(
+                dc,
+                cam.world_point_to_canvas_point(cam.pos()),
+                draw::COLOR_YELLOW,
+            );
+        }
+
+        for timed_shape in &self.shapes {
+            if self.is_overlay_enabled(timed_shape.shape.overlay()) {
+                draw_shape(dc, cam, &timed_shape.shape, timed_shape.color);
+            }
+        }
+        for timed_shape in &mut self.shapes {
+            timed_shape.time_left -= delta_time;
+        }
+        self.shapes
+            .retain(|timed_shape| timed_shape.time_left > 0.0);
+    }
+}
+
+/// Returns the distance between grid lines in world space. Starting from the given base spacing
+/// the distance is doubled or halved until neighbouring lines are at least the given number of
+/// canvas pixels apart but not more than twice that distance. The spacing never gets smaller
+/// than one world unit.
+///
+/// # Example
+/// ```
+/// # use game_lib::debug_draw::*;
+/// assert_eq!(adaptive_grid_spacing(16.0, 1.0, 8.0), 8.0);
+/// assert_eq!(adaptive_grid_spacing(16.0, 0.25, 8.0), 32.0);
+/// assert_eq!(adaptive_grid_spacing(16.0, 64.0, 8.0), 1.0);
+/// ```
+pub fn adaptive_grid_spacing(base_spacing: f32, zoom_level: f32, min_canvas_spacing: f32) -> f32 {
+    let mut spacing = base_spacing;
+    while spacing * zoom_level < min_canvas_spacing {
+        spacing *= 2.0;
+    }
+    while spacing > 1.0 && 0.5 * spacing * zoom_level >= min_canvas_spacing {
+        spacing *= 0.5;
+    }
+    spacing
+}
+
+//==================================================================================================
+// Helper functions
+//==================================================================================================
+//
+
+fn draw_grid(dc: &mut DrawContext, cam: &Camera, canvas_rect: Rect) {
+    let spacing = adaptive_grid_spacing(GRID_BASE_SPACING, cam.zoom_level, GRID_MIN_CANVAS_SPACING);
+    let frustum = cam.frustum();
+    let grid_line_color = |index: i64, axis_color: Color| {
+        if index == 0 {
+            axis_color
+        } else if index % GRID_MAJOR_LINE_INTERVAL == 0 {
+            COLOR_GRID_MAJOR
+        } else {
+            COLOR_GRID_MINOR
+        }
+    };
+
+    let first_column = (frustum.left / spacing).ceil() as i64;
+    let last_column = (frustum.right / spacing).floor() as i64;
+    for column in first_column..=last_column {
+        let x = cam
+            .world_point_to_canvas_point(Point::new(column as f32 * spacing, 0.0))
+            .x
+            .round();
+        dc.draw_line(
+            Line::new(
+                Point::new(x, canvas_rect.top),
+                Point::new(x, canvas_rect.bottom),
+            ),
+            OVERLAY_DEPTH_GRID,
+            grid_line_color(column, COLOR_GRID_AXIS_Y),
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        );
+    }
+
+    let first_row = (frustum.top / spacing).ceil() as i64;
+    let last_row = (frustum.bottom / spacing).floor() as i64;
+    for row in first_row..=last_row {
+        let y = cam
+            .world_point_to_canvas_point(Point::new(0.0, row as f32 * spacing))
+            .y
+            .round();
+        dc.draw_line(
+            Line::new(
+                Point::new(canvas_rect.left, y),
+                Point::new(canvas_rect.right, y),
+            ),
+            OVERLAY_DEPTH_GRID,
+            grid_line_color(row, COLOR_GRID_AXIS_X),
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        );
+    }
+}
+
+fn draw_shape(dc: &mut DrawContext, cam: &Camera, shape: &DebugShape, color: Color) {
+    let to_canvas = |pos: WorldPoint| cam.world_point_to_canvas_point(pos);
+    match shape {
+        DebugShape::Line(line) => dc.draw_line(
+            Line::new(to_canvas(line.start), to_canvas(line.end)),
+            OVERLAY_DEPTH_SHAPES,
+            color,
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        ),
+        DebugShape::Arrow { start, end } => {
+            draw_arrow(dc, to_canvas(*start), to_canvas(*end), color);
+        }
+        DebugShape::Rect(rect) => dc.draw_rect(
+            world_rect_to_canvas_rect(cam, *rect),
+            OVERLAY_DEPTH_SHAPES,
+            color,
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        ),
+        DebugShape::Circle(circle) => dc.draw_circle(
+            Circle::new(to_canvas(circle.center), circle.radius * cam.zoom_level),
+            1.0,
+            CurveDetail::default(),
+            OVERLAY_DEPTH_SHAPES,
+            color,
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        ),
+        DebugShape::CameraFrustum { frustum, label } => {
+            draw_frustum(dc, cam, *frustum, label, color);
+        }
+        DebugShape::Crosshair(pos) => draw_crosshair(dc, to_canvas(*pos), color),
+        DebugShape::Point { pos, label } => {
+            let pos = to_canvas(*pos).pixel_snapped();
+            dc.draw_rect_filled(
+                Rect::from_point(pos, 2.0 * POINT_RADIUS + 1.0, 2.0 * POINT_RADIUS + 1.0)
+                    .centered(),
+                OVERLAY_DEPTH_SHAPES,
+                color,
+                draw::ADDITIVITY_NONE,
+                DrawSpace::Debug,
+            );
+            draw_label(dc, pos + LABEL_OFFSET, label, color);
+        }
+    }
+}
+
+fn draw_frustum(dc: &mut DrawContext, cam: &Camera, frustum: Rect, label: &str, color: Color) {
+    let frustum = world_rect_to_canvas_rect(cam, frustum);
+    dc.draw_rect(
+        frustum,
+        OVERLAY_DEPTH_SHAPES,
+        color,
+        draw::ADDITIVITY_NONE,
+        DrawSpace::Debug,
+    );
+    draw_label(
+        dc,
+        Point::new(frustum.left, frustum.top) + Vec2::new(1.0, -1.0),
+        label,
+        color,
+    );
+}
+
+/// Draws an arrow whose head has the same size regardless of the length of the arrow
+fn draw_arrow(dc: &mut DrawContext, start: CanvasPoint, end: CanvasPoint, color: Color) {
+    dc.draw_line(
+        Line::new(start, end),
+        OVERLAY_DEPTH_SHAPES,
+        color,
+        draw::ADDITIVITY_NONE,
+        DrawSpace::Debug,
+    );
+
+    let dir = end - start;
+    if dir.magnitude() < ARROW_HEAD_SIZE {
+        return;
+    }
+    let dir = dir.normalized();
+    let head_left = end + ARROW_HEAD_SIZE * (0.5 * dir.perpendicular() - dir);
+    let head_right = end + ARROW_HEAD_SIZE * (-0.5 * dir.perpendicular() - dir);
+    dc.draw_lines(
+        &[Line::new(head_left, end), Line::new(head_right, end)],
+        OVERLAY_DEPTH_SHAPES,
+        color,
+        draw::ADDITIVITY_NONE,
+        DrawSpace::Debug,
+    );
+}
+
+fn draw_crosshair(dc: &mut DrawContext, pos: CanvasPoint, color: Color) {
+    let pos = pos.pixel_snapped();
+    dc.draw_lines(
+        &[
+            Line::new(
+                pos - Vec2::new(CROSSHAIR_RADIUS, 0.0),
+                pos + Vec2::new(CROSSHAIR_RADIUS, 0.0),
+            ),
+            Line::new(
+                pos - Vec2::new(0.0, CROSSHAIR_RADIUS),
+                pos + Vec2::new(0.0, CROSSHAIR_RADIUS),
+            ),
+        ],
+        OVERLAY_DEPTH_SHAPES,
+        color,
+        draw::ADDITIVITY_NONE,
+        DrawSpace::Debug,
+    );
+}
+
+/// Draws lines through the mouse cursor across the whole canvas and labels them with the world
+/// and canvas position of the cursor
+fn draw_mouse_crosshair(
+    dc: &mut DrawContext,
+    cam: &Camera,
+    canvas_rect: Rect,
+    mouse_pos_world: WorldPoint,
+) {
+    let pos = cam
+        .world_point_to_canvas_point(mouse_pos_world)
+        .pixel_snapped();
+    dc.draw_lines(
+        &[
+            Line::new(
+                Point::new(canvas_rect.left, pos.y),
+                Point::new(canvas_rect.right, pos.y),
+            ),
+            Line::new(
+                Point::new(pos.x, canvas_rect.top),
+                Point::new(pos.x, canvas_rect.bottom),
+            ),
+        ],
+        OVERLAY_DEPTH_SHAPES,
+        draw::COLOR_CYAN,
+        draw::ADDITIVITY_NONE,
+        DrawSpace::Debug,
+    );
+    draw_label(
+        dc,
+        pos + LABEL_OFFSET,
+        &format!(
+            "world: {:.1}, {:.1} canvas: {}, {}",
+            mouse_pos_world.x, mouse_pos_world.y, pos.x, pos.y
+        ),
+        draw::COLOR_CYAN,
+    );
+}
+
+fn draw_label(dc: &mut DrawContext, pos: CanvasPoint, text: &str, color: Color) {
+    if text.is_empty() {
+        return;
+    }
+    let font = dc.font_handle(draw::DEBUG_FONT_RESOURCE_PATH);
+    dc.draw_text(
+        pos.pixel_snapped(),
+        text,
+        font,
+        OVERLAY_DEPTH_TEXT,
+        color,
+        draw::ADDITIVITY_NONE,
+        DrawSpace::Debug,
+    );
+}
+
+fn world_rect_to_canvas_rect(cam: &Camera, rect: Rect) -> Rect {
+    let top_left = cam.world_point_to_canvas_point(Point::new(rect.left, rect.top));
+    let bottom_right = cam.world_point_to_canvas_point(Point::new(rect.right, rect.bottom));
+    Rect::from_bounds(top_left.x, bottom_right.x, bottom_right.y, top_left.y)
+}
@@ -1,4 +1,11 @@
-use crate::math::{CanvasPoint, Color, Line, Mat4, Mat4Helper, Point, Rect, Vec2, WorldPoint};
+use crate::animation::AnimationPlayer;
+use crate::math::{
+    clamp, is_effectively_zero, CanvasPoint, Circle, Color, Line, Mat4, Mat4Helper, Point, Rect,
+    Vec2, WorldPoint,
+};
+use crate::postprocess::{ColorGradingLut, PostProcessEffect, PostProcessSettings};
+use crate::text::{parse_text_markup, TextAlignment, TextLayout, TextRun};
+use crate::tilemap::{visible_cell_range, Tilemap};
 use crate::utility;
 
 use lodepng;
@@ -6,12 +13,18 @@ use rgb;
 pub use rgb::ComponentBytes;
 
 use std;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::f32::consts::PI;
 
 pub type Pixel = rgb::RGBA8;
 pub type VertexIndex = u16;
 
-#[derive(Debug, Clone, Copy)]
+/// A `VertexIndex` can only address this many vertices. Draw batches that need more vertices are
+/// split into multiple meshes which are drawn with separate draw calls.
+pub const MAX_VERTICES_PER_MESH: usize = VertexIndex::max_value() as usize + 1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Vertex {
     pub pos: [f32; 4],
     pub uv: [f32; 3],
@@ -94,6 +107,218 @@ pub enum DrawSpace {
     Debug,
 }
 
+/// Layers are drawn in the order they are declared here. Everything in a layer is drawn on top of
+/// all previous layers of the same draw space regardless of its depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderLayer {
+    Background,
+    Default,
+    Foreground,
+    Ui,
+    /// Used for things like screen fades that need to cover everything else
+    Overlay,
+}
+
+pub const NUM_RENDER_LAYERS: usize = 5;
+
+impl Default for RenderLayer {
+    fn default() -> RenderLayer {
+        RenderLayer::Default
+    }
+}
+
+/// Determines how drawn pixels are combined with the pixels already in the framebuffer. All colors
+/// are premultiplied by their alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// Draws translucent pixels on top of the framebuffer
+    Alpha,
+    /// Adds the drawn colors to the framebuffer which is useful for glowing things
+    Additive,
+    /// Multiplies the framebuffer with the drawn colors which is useful for shadows and tinting
+    Multiply,
+}
+
+impl Default for BlendMode {
+    fn default() -> BlendMode {
+        BlendMode::Alpha
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DepthMode {
+    /// Only draws pixels that are not behind the depthbuffer and writes their depth
+    TestAndWrite,
+    /// Only draws pixels that are not behind the depthbuffer but leaves the depthbuffer untouched
+    Test,
+    /// Draws all pixels and leaves the depthbuffer untouched
+    Disabled,
+}
+
+impl Default for DepthMode {
+    fn default() -> DepthMode {
+        DepthMode::TestAndWrite
+    }
+}
+
+pub const NUM_SHADER_UNIFORM_PARAMS: usize = 4;
+
+/// Values that custom shaders can read via the uniforms `u_params_0` to `u_params_3`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ShaderUniforms {
+    pub params: [[f32; 4]; NUM_SHADER_UNIFORM_PARAMS],
+}
+
+impl ShaderUniforms {
+    pub fn new() -> ShaderUniforms {
+        Default::default()
+    }
+
+    pub fn with_param(mut self, index: usize, value: [f32; 4]) -> ShaderUniforms {
+        self.params[index] = value;
+        self
+    }
+}
+
+/// Describes how the draw calls of a batch are rendered. Everything that is drawn while a render
+/// state is set goes into a batch with that render state.
+///
+/// # Example
+/// ```
+/// # use game_lib::draw::*;
+/// let glow_shader = ShaderInfo {
+///     id: 0,
+///     name: String::from("glow"),
+/// };
+/// let render_state = RenderState::default()
+///     .with_shader(glow_shader, ShaderUniforms::new().with_param(0, [1.0, 0.5, 0.0, 1.0]))
+///     .with_blend_mode(BlendMode::Additive)
+///     .with_depth_mode(DepthMode::Test);
+/// assert_eq!(render_state.uniforms.params[0], [1.0, 0.5, 0.0, 1.0]);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderState {
+    /// The shader created with `DrawContext::create_shader` or `None` for the default shader
+    pub shader: Option<ShaderInfo>,
+    pub uniforms: ShaderUniforms,
+    pub blend_mode: BlendMode,
+    pub depth_mode: DepthMode,
+}
+
+impl RenderState {
+    pub fn with_shader(mut self, shader: ShaderInfo, uniforms: ShaderUniforms) -> RenderState {
+        self.shader = Some(shader);
+        self.uniforms = uniforms;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> RenderState {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn with_depth_mode(mut self, depth_mode: DepthMode) -> RenderState {
+        self.depth_mode = depth_mode;
+        self
+    }
+}
+
+/// Determines how the segments of a thick polyline are connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Extends the outer edges of two segments until they meet. Falls back to `Bevel` for very
+    /// sharp angles.
+    Miter,
+    /// Connects the outer edges of two segments with a straight edge
+    Bevel,
+}
+
+/// Determines how the open ends of a thick line are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    /// The line ends exactly at its endpoints
+    Butt,
+    /// The line is extended by half its thickness beyond its endpoints
+    Square,
+    /// The line ends in a half circle around its endpoints
+    Round,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineStyle {
+    pub thickness: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+}
+
+impl LineStyle {
+    pub fn new(thickness: f32) -> LineStyle {
+        LineStyle {
+            thickness,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+        }
+    }
+
+    pub fn with_join(self, join: LineJoin) -> LineStyle {
+        LineStyle { join, ..self }
+    }
+
+    pub fn with_cap(self, cap: LineCap) -> LineStyle {
+        LineStyle { cap, ..self }
+    }
+}
+
+impl Default for LineStyle {
+    fn default() -> LineStyle {
+        LineStyle::new(1.0)
+    }
+}
+
+/// Determines how curved shapes like circles and rounded rects are turned into geometry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveDetail {
+    /// Approximates a full circle with the given number of straight segments
+    Segments(usize),
+    /// Rasterizes the shape into pixel-aligned rows which looks like hand-drawn pixel-art. This
+    /// assumes that one unit in the draw space equals one pixel.
+    Pixelated,
+}
+
+impl Default for CurveDetail {
+    fn default() -> CurveDetail {
+        CurveDetail::Segments(32)
+    }
+}
+
+// NOTE: Fragments whose texel and vertex color alpha multiply to zero are discarded just like in
+//       the default shader so that they do not show up in the depthbuffer
+const DEPTH_VISUALIZATION_FRAGMENT_SHADER_SOURCE: &str = r#"
+#version 150 core
+
+uniform sampler2D u_sampler;
+uniform sampler2DArray u_sampler_array;
+uniform int u_use_texture_array;
+
+in vec4 v_color_modulate;
+in vec3 v_uv;
+
+out vec4 out_color_0;
+
+void main() {
+    float tex_alpha;
+    if (u_use_texture_array == 1) {
+        tex_alpha = texture(u_sampler_array, v_uv).a;
+    } else {
+        tex_alpha = texture(u_sampler, vec2(v_uv.x, v_uv.y)).a;
+    }
+    if (tex_alpha * v_color_modulate.a == 0.0) {
+        discard;
+    }
+    out_color_0 = vec4(vec3(gl_FragCoord.z), 1.0);
+}
+"#;
+
 // TODO(JaSc): Change screen color based on debug/release to better see
 //             letterboxing in windowed mode
 const CLEAR_COLOR_SCREEN: [f32; 4] = [0.2, 0.9, 0.4, 1.0];
@@ -104,17 +329,48 @@ pub struct DrawContext<'drawcontext> {
     atlas: AtlasMeta,
     atlas_texture_array: Option<TextureArrayInfo>,
 
-    canvas_framebuffer: Option<FramebufferInfo>,
+    sprite_table: ResourceTable<Sprite>,
+    font_table: ResourceTable<Font>,
+    animation_table: ResourceTable<Animation>,
+    nine_slice_table: ResourceTable<NineSlice>,
+    tilemap_table: ResourceTable<Tilemap>,
 
-    debug_lines: LineMesh,
-    world_lines: LineMesh,
-    canvas_lines: LineMesh,
+    plain_sprite: SpriteHandle,
+    textured_sprite: SpriteHandle,
+    default_font: FontHandle,
+    debug_circle_animation: AnimationHandle,
+    debug_cursor_animation: AnimationHandle,
 
-    debug_polygons: PolygonMesh,
-    world_polygons: PolygonMesh,
-    canvas_polygons: PolygonMesh,
+    canvas_framebuffer: Option<FramebufferInfo>,
+    /// Canvas sized framebuffers that the post-processing passes render into alternately
+    postprocess_framebuffers: Vec<FramebufferInfo>,
+    color_grading_lut: Option<(TextureArrayInfo, ColorGradingLut)>,
+    render_targets: Vec<RenderTarget>,
+    active_render_target: Option<RenderTargetHandle>,
+
+    next_shader_id: u32,
+    /// Shaders are deleted at the end of the frame because draw batches of the current frame may
+    /// still use them
+    shaders_pending_deletion: Vec<ShaderInfo>,
+    /// Is created when the depth visualization is enabled for the first time
+    depth_visualization_shader: Option<ShaderInfo>,
+    is_depth_visualization_enabled: bool,
+
+    render_layer: RenderLayer,
+    render_state: RenderState,
+    // NOTE: Every render layer holds one batch per render state that was used in the current frame
+    debug_batches: [Vec<DrawBatch>; NUM_RENDER_LAYERS],
+    world_batches: [Vec<DrawBatch>; NUM_RENDER_LAYERS],
+    canvas_batches: [Vec<DrawBatch>; NUM_RENDER_LAYERS],
 
     debug_text_origin: CanvasPoint,
+    /// The number of additional meshes that were needed in the last frame because draw batches
+    /// exceeded `MAX_VERTICES_PER_MESH`
+    num_mesh_splits: usize,
+    stats: DrawStats,
+    /// Summaries are only created on request as they need to look at every drawn vertex
+    is_recording_command_summaries: bool,
+    command_summaries: Vec<DrawCommandSummary>,
     pub draw_commands: Vec<DrawCommand<'drawcontext>>,
 }
 
@@ -131,12 +387,11 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         additivity: f32,
         draw_space: DrawSpace,
     ) {
-        // TODO(JaSc): Cache the plain texture uv for reuse
-        let sprite = self.atlas.sprites["images/plain"];
+        let sprite = self.sprite(self.plain_sprite);
         let line_uv = rect_uv_to_line_uv(sprite.uv_bounds);
-        let mesh = self.line_mesh_by_draw_space(draw_space);
+        let batch = self.draw_batch_by_draw_space(draw_space);
         for line in lines {
-            mesh.push_line(*line, line_uv, sprite.atlas_index, depth, color, additivity);
+            batch.push_line(*line, line_uv, sprite.atlas_index, depth, color, additivity);
         }
     }
 
@@ -148,11 +403,80 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         additivity: f32,
         draw_space: DrawSpace,
     ) {
-        // TODO(JaSc): Cache the plain texture uv for reuse
-        let sprite = self.atlas.sprites["images/plain"];
+        let sprite = self.sprite(self.plain_sprite);
         let line_uv = rect_uv_to_line_uv(sprite.uv_bounds);
-        let mesh = self.line_mesh_by_draw_space(draw_space);
-        mesh.push_line(line, line_uv, sprite.atlas_index, depth, color, additivity);
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        batch.push_line(line, line_uv, sprite.atlas_index, depth, color, additivity);
+    }
+
+    /// Draws a thick line or polyline. Lines with a thickness of one or less are drawn as line
+    /// primitives which are always exactly one pixel wide and therefore stay pixel-perfect.
+    pub fn draw_line_thick(
+        &mut self,
+        line: Line,
+        style: LineStyle,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_polyline(
+            &[line.start, line.end],
+            false,
+            style,
+            depth,
+            color,
+            additivity,
+            draw_space,
+        );
+    }
+
+    pub fn draw_lines_thick(
+        &mut self,
+        lines: &[Line],
+        style: LineStyle,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        for line in lines {
+            self.draw_line_thick(*line, style, depth, color, additivity, draw_space);
+        }
+    }
+
+    /// Draws connected line segments through the given points. If `is_closed` is true the last
+    /// point is connected to the first point and no caps are drawn.
+    pub fn draw_polyline(
+        &mut self,
+        points: &[Point],
+        is_closed: bool,
+        style: LineStyle,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        if style.thickness <= 1.0 {
+            let mut lines: Vec<Line> = points
+                .windows(2)
+                .map(|segment| Line::new(segment[0], segment[1]))
+                .collect();
+            if is_closed && points.len() > 2 {
+                lines.push(Line::new(points[points.len() - 1], points[0]));
+            }
+            self.draw_lines(&lines, depth, color, additivity, draw_space);
+            return;
+        }
+
+        let sprite = self.sprite(self.plain_sprite);
+        let uv = sprite.uv_bounds.center();
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        batch.push_shape(depth, is_translucent(color, additivity), |mesh| {
+            polyline_to_convex_polygons(points, is_closed, style, |polygon| {
+                mesh.push_convex_polygon(polygon, uv, sprite.atlas_index, depth, color, additivity)
+            });
+        });
     }
 
     pub fn draw_arrow(
@@ -162,31 +486,54 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         length: f32,
         depth: f32,
         color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_arrow_thick(
+            pos,
+            dir,
+            length,
+            LineStyle::new(1.0),
+            depth,
+            color,
+            additivity,
+            draw_space,
+        );
+    }
 
+    pub fn draw_arrow_thick(
+        &mut self,
+        pos: Point,
+        dir: Vec2,
+        length: f32,
+        style: LineStyle,
+        depth: f32,
+        color: Color,
         additivity: f32,
         draw_space: DrawSpace,
     ) {
         let end_point = pos + length * dir;
         let head_dimensions = length * 0.1;
+        let head_base = end_point - 2.0 * head_dimensions * dir;
         let head_left_part = (dir.perpendicular() - 2.0 * dir) * head_dimensions;
         let head_right_part = (-dir.perpendicular() - 2.0 * dir) * head_dimensions;
 
-        self.draw_line(
-            Line::new(pos, end_point),
-            depth,
-            color,
-            additivity,
-            draw_space,
-        );
-        self.draw_line(
-            Line::new(end_point, end_point + head_left_part),
+        // NOTE: The shaft ends at the base of the head without a cap so that translucent arrows
+        //       do not blend twice where the shaft and the head would overlap
+        self.draw_line_thick(
+            Line::new(pos, head_base),
+            style.with_cap(LineCap::Butt),
             depth,
             color,
             additivity,
             draw_space,
         );
-        self.draw_line(
-            Line::new(end_point, end_point + head_right_part),
+        self.draw_triangle_filled(
+            [
+                end_point + head_left_part,
+                end_point,
+                end_point + head_right_part,
+            ],
             depth,
             color,
             additivity,
@@ -202,9 +549,8 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         additivity: f32,
         draw_space: DrawSpace,
     ) {
-        // TODO(JaSc): Cache the plain texture uv for reuse
-        let sprite = self.atlas.sprites["images/plain"];
-        let mesh = self.polygon_mesh_by_draw_space(draw_space);
+        let sprite = self.sprite(self.plain_sprite);
+        let mesh = self.draw_batch_by_draw_space(draw_space);
         mesh.push_quad(
             rect,
             sprite.uv_bounds,
@@ -240,8 +586,8 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         additivity: f32,
         draw_space: DrawSpace,
     ) {
-        let sprite = self.atlas.sprites["images/textured"];
-        let mesh = self.polygon_mesh_by_draw_space(draw_space);
+        let sprite = self.sprite(self.textured_sprite);
+        let mesh = self.draw_batch_by_draw_space(draw_space);
         mesh.push_quad(
             rect,
             sprite.uv_bounds,
@@ -260,9 +606,9 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         additivity: f32,
         draw_space: DrawSpace,
     ) {
-        let sprite = self.atlas.animations["images/test"].frames[0];
+        let sprite = self.animation(self.debug_circle_animation).frames[0];
         let vertex_bounds = sprite.vertex_bounds.translated_by(pos);
-        let mesh = self.polygon_mesh_by_draw_space(draw_space);
+        let mesh = self.draw_batch_by_draw_space(draw_space);
         mesh.push_quad(
             vertex_bounds,
             sprite.uv_bounds,
@@ -280,9 +626,10 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         additivity: f32,
         draw_space: DrawSpace,
     ) {
-        let sprite = self.atlas.animations["images/cursor_test"].frames[1];
+        let animation = self.animation(self.debug_cursor_animation);
+        let sprite = animation.frames[1 % animation.frames.len()];
         let vertex_bounds = sprite.vertex_bounds.translated_by(pos);
-        let mesh = self.polygon_mesh_by_draw_space(draw_space);
+        let mesh = self.draw_batch_by_draw_space(draw_space);
         mesh.push_quad(
             vertex_bounds,
             sprite.uv_bounds,
@@ -303,7 +650,7 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         draw_space: DrawSpace,
     ) {
         let vertex_bounds = sprite.vertex_bounds.translated_by(pos);
-        let mesh = self.polygon_mesh_by_draw_space(draw_space);
+        let mesh = self.draw_batch_by_draw_space(draw_space);
         mesh.push_quad(
             vertex_bounds,
             sprite.uv_bounds,
@@ -314,109 +661,785 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         );
     }
 
-    pub fn get_text_dimensions(&self, text: &str) -> Vec2 {
-        let font = &self.atlas.fonts["fonts/default"];
-        let mut offset = Vec2::zero();
-        let mut dim = Vec2::zero();
+    /// Draws a sprite that is scaled, flipped and rotated around its pivot
+    pub fn draw_sprite_transformed(
+        &mut self,
+        sprite: &Sprite,
+        transform: SpriteTransform,
+        depth: f32,
+        colors: QuadColors,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let vertices = sprite.into_vertices(transform, depth, colors, additivity);
+        let is_translucent = colors
+            .to_array()
+            .iter()
+            .any(|&color| is_translucent(color, additivity));
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        batch.push_shape(depth, is_translucent, |mesh| {
+            mesh.push_triangles(&vertices, &[0, 1, 2, 2, 3, 0])
+        });
+    }
+
+    /// Draws the current frame of the given animation player. Animations without frames are not
+    /// drawn.
+    pub fn draw_animation(
+        &mut self,
+        player: &AnimationPlayer,
+        pos: Point,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let animation = self.animation(player.animation);
+        let sprite = match animation.frames.get(player.current_frame_index(animation)) {
+            Some(sprite) => *sprite,
+            None => return,
+        };
+        self.draw_sprite(&sprite, pos, depth, color, additivity, draw_space);
+    }
+
+    pub fn draw_animation_transformed(
+        &mut self,
+        player: &AnimationPlayer,
+        transform: SpriteTransform,
+        depth: f32,
+        colors: QuadColors,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let animation = self.animation(player.animation);
+        let sprite = match animation.frames.get(player.current_frame_index(animation)) {
+            Some(sprite) => *sprite,
+            None => return,
+        };
+        self.draw_sprite_transformed(&sprite, transform, depth, colors, additivity, draw_space);
+    }
+
+    /// Draws a nine-slice sprite that covers the given rect. The corners keep their size while the
+    /// edges and the center are stretched or tiled to fill the remaining space. If the rect is
+    /// smaller than the corners, the corners are shrunk proportionally.
+    pub fn draw_nine_slice(
+        &mut self,
+        rect: Rect,
+        handle: NineSliceHandle,
+        mode: NineSliceMode,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let nine_slice = self.nine_slice(handle);
+        let sprite = nine_slice.sprite;
+        let insets = nine_slice.insets;
+        let columns = nine_slice_segments(
+            (rect.left, rect.right),
+            (sprite.uv_bounds.left, sprite.uv_bounds.right),
+            sprite.vertex_bounds.width(),
+            (insets.left, insets.right),
+            mode,
+        );
+        let rows = nine_slice_segments(
+            (rect.top, rect.bottom),
+            (sprite.uv_bounds.top, sprite.uv_bounds.bottom),
+            sprite.vertex_bounds.height(),
+            (insets.top, insets.bottom),
+            mode,
+        );
 
-        // TODO(JaSc): Maybe we can make a 'font-iterator' that takes a lambda so we do not need to
-        //             repeat this for loop in multiple methods
-        for c in text.chars() {
-            if c == '\n' {
-                offset.x = 0.0;
-                offset.y += font.vertical_advance;
-            } else {
-                let glyph = font.glyphs[(c as u8) as usize];
-                offset.x += glyph.horizontal_advance;
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        batch.push_shape(depth, is_translucent(color, additivity), |mesh| {
+            for row in &rows {
+                for column in &columns {
+                    mesh.push_quad(
+                        Rect::from_bounds(column.start, column.end, row.end, row.start),
+                        Rect::from_bounds(column.uv_start, column.uv_end, row.uv_end, row.uv_start),
+                        sprite.atlas_index,
+                        depth,
+                        color,
+                        additivity,
+                    );
+                }
+            }
+        });
+    }
 
-                dim.x = f32::max(dim.x, offset.x);
-                dim.y = f32::max(dim.y, offset.y + font.font_height);
+    /// Draws all visible layers of a tilemap with its top-left corner at the given position. Only
+    /// tiles that overlap `visible_rect` are drawn which usually is the camera frustum. Animated
+    /// tiles show the frame for the given time in seconds.
+    pub fn draw_tilemap(
+        &mut self,
+        handle: TilemapHandle,
+        pos: Point,
+        visible_rect: Rect,
+        time: f32,
+        draw_space: DrawSpace,
+    ) {
+        // NOTE: We collect the quads of a layer first because the tilemap is borrowed from `self`
+        //       and we cannot get the draw batch from `self` at the same time
+        let mut layer_quads = Vec::new();
+        let tilemap = self.tilemap_table.get(handle.0);
+        for layer in tilemap.layers.iter().filter(|layer| layer.is_visible) {
+            let layer_pos = pos + layer.offset;
+            let (columns, rows) = visible_cell_range(
+                layer_pos,
+                tilemap.tile_dim,
+                layer.num_columns,
+                layer.num_rows,
+                visible_rect,
+            );
+
+            let mut quads = Vec::with_capacity(columns.len() * rows.len());
+            for row in rows {
+                for column in columns.clone() {
+                    let tile = layer
+                        .cell(column, row)
+                        .and_then(|tile_index| tilemap.tiles.get(tile_index as usize));
+                    if let Some(tile) = tile {
+                        let tile_pos =
+                            layer_pos + Vec2::new(column as f32, row as f32) * tilemap.tile_dim;
+                        let sprite = tile.sprite_at_time(time);
+                        quads.push((sprite.vertex_bounds.translated_by(tile_pos), sprite));
+                    }
+                }
             }
+            let color = Color::new(1.0, 1.0, 1.0, layer.opacity);
+            layer_quads.push((layer.depth, color, quads));
+        }
+
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        for (depth, color, quads) in layer_quads {
+            batch.push_shape(depth, is_translucent(color, ADDITIVITY_NONE), |mesh| {
+                for (rect, sprite) in quads {
+                    mesh.push_quad(
+                        rect,
+                        sprite.uv_bounds,
+                        sprite.atlas_index,
+                        depth,
+                        color,
+                        ADDITIVITY_NONE,
+                    );
+                }
+            });
         }
-        dim
     }
 
+    // ---------------------------------------------------------------------------------------------
+    // Text
+    //
+    pub fn layout_text(
+        &self,
+        runs: &[TextRun],
+        alignment: TextAlignment,
+        max_width: Option<f32>,
+    ) -> TextLayout {
+        TextLayout::new(runs, |handle| self.font(handle), alignment, max_width)
+    }
+
+    pub fn get_text_dimensions(&self, text: &str, font: FontHandle) -> Vec2 {
+        let runs = [TextRun::new(text, Color::new(1.0, 1.0, 1.0, 1.0), font)];
+        self.layout_text(&runs, TextAlignment::Left, None)
+            .dimensions
+    }
+
+    /// Draws the given text and returns the offset to the position where the text would continue
     pub fn draw_text(
         &mut self,
         origin: Point,
         text: &str,
+        font: FontHandle,
         depth: f32,
         color: Color,
         additivity: f32,
         draw_space: DrawSpace,
     ) -> Vec2 {
-        let font = &self.atlas.fonts["fonts/default"];
-        let origin = origin.pixel_snapped();
-        let mut offset = Vec2::zero();
-
-        // NOTE: We cannot call polymesh_by_draw_space here because the borrowchecker won't let us
-        let mesh = match draw_space {
-            DrawSpace::World => &mut self.world_polygons,
-            DrawSpace::Canvas => &mut self.canvas_polygons,
-            DrawSpace::Debug => &mut self.debug_polygons,
-        };
+        let runs = [TextRun::new(text, color, font)];
+        let layout = self.layout_text(&runs, TextAlignment::Left, None);
+        self.draw_text_layout(&layout, origin, None, depth, additivity, draw_space);
+        layout.end_pos
+    }
 
-        for c in text.chars() {
-            if c == '\n' {
-                offset.x = 0.0;
-                offset.y += font.vertical_advance;
-            } else {
-                let glyph = font.glyphs[(c as u8) as usize];
-                let sprite = glyph.sprite;
+    /// Draws text containing style tags (see `text::parse_text_markup`) and returns its layout
+    pub fn draw_text_markup(
+        &mut self,
+        origin: Point,
+        markup: &str,
+        font: FontHandle,
+        alignment: TextAlignment,
+        max_width: Option<f32>,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) -> TextLayout {
+        let runs = parse_text_markup(markup, color, font, |font_resource_path| {
+            self.font_handle(font_resource_path)
+        });
+        let layout = self.layout_text(&runs, alignment, max_width);
+        self.draw_text_layout(&layout, origin, None, depth, additivity, draw_space);
+        layout
+    }
 
-                let vertex_bounds = sprite.vertex_bounds.translated_by(origin + offset);
-                mesh.push_quad(
-                    vertex_bounds,
-                    sprite.uv_bounds,
-                    sprite.atlas_index,
-                    depth,
-                    color,
-                    additivity,
-                );
-                offset.x += glyph.horizontal_advance;
-            }
+    /// Draws previously laid out text. Glyphs are cut off at the border of the clipping rect.
+    pub fn draw_text_layout(
+        &mut self,
+        layout: &TextLayout,
+        origin: Point,
+        clip_rect: Option<Rect>,
+        depth: f32,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let origin = origin.pixel_snapped();
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        for (glyph, rect, uv) in layout.glyph_quads(origin, clip_rect) {
+            batch.num_glyphs += 1;
+            batch.push_quad(
+                rect,
+                uv,
+                glyph.sprite.atlas_index,
+                depth,
+                glyph.color,
+                additivity,
+            );
         }
-        offset
     }
 
     // ---------------------------------------------------------------------------------------------
-    // Debug drawing
+    // Shapes
     //
-    pub fn debug_draw_text(&mut self, text: &str, color: Color) {
-        let draw_offset = self.draw_text(
-            self.debug_text_origin,
-            &(String::from("\n") + text),
-            0.0,
+    pub fn draw_circle_filled(
+        &mut self,
+        circle: Circle,
+        detail: CurveDetail,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_ellipse_filled(
+            circle.center,
+            Vec2::ones() * circle.radius,
+            detail,
+            depth,
             color,
-            0.0,
-            DrawSpace::Debug,
+            additivity,
+            draw_space,
         );
-        self.debug_text_origin.y += draw_offset.y;
     }
 
-    // ---------------------------------------------------------------------------------------------
-    // State
-    //
-    pub fn start_drawing(&mut self) {
-        self.world_polygons.clear();
-        self.world_lines.clear();
-
-        self.debug_polygons.clear();
-        self.debug_lines.clear();
-
-        self.canvas_polygons.clear();
-        self.canvas_lines.clear();
-
-        self.debug_text_origin = Vec2::new(8.0, 0.0);
+    /// Draws the outline of a circle. The outline lies completely inside the circle.
+    pub fn draw_circle(
+        &mut self,
+        circle: Circle,
+        thickness: f32,
+        detail: CurveDetail,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_ellipse(
+            circle.center,
+            Vec2::ones() * circle.radius,
+            thickness,
+            detail,
+            depth,
+            color,
+            additivity,
+            draw_space,
+        );
     }
 
-    // TODO(JaSc): Get rid of screen_rect/canvas_rect here
-    pub fn finish_drawing(
-        &'drawcontext mut self,
-        transform: Mat4,
-        canvas_rect: Rect,
-        canvas_blit_rect: Rect,
+    pub fn draw_ellipse_filled(
+        &mut self,
+        center: Point,
+        radii: Vec2,
+        detail: CurveDetail,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
     ) {
+        match detail {
+            CurveDetail::Segments(num_segments) => {
+                let points = ellipse_points(center, radii, num_segments);
+                self.draw_polygon_filled(&points, depth, color, additivity, draw_space);
+            }
+            CurveDetail::Pixelated => self.draw_rasterized_shape(
+                RasterShape::Ellipse { center, radii },
+                None,
+                depth,
+                color,
+                additivity,
+                draw_space,
+            ),
+        }
+    }
+
+    /// Draws the outline of an ellipse. The outline lies completely inside the ellipse.
+    pub fn draw_ellipse(
+        &mut self,
+        center: Point,
+        radii: Vec2,
+        thickness: f32,
+        detail: CurveDetail,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        match detail {
+            CurveDetail::Segments(num_segments) => {
+                let half_thickness = Vec2::ones() * 0.5 * thickness;
+                let points = ellipse_points(center, radii - half_thickness, num_segments);
+                self.draw_polygon(
+                    &points,
+                    LineStyle::new(thickness),
+                    depth,
+                    color,
+                    additivity,
+                    draw_space,
+                );
+            }
+            CurveDetail::Pixelated => self.draw_rasterized_shape(
+                RasterShape::Ellipse { center, radii },
+                Some(thickness),
+                depth,
+                color,
+                additivity,
+                draw_space,
+            ),
+        }
+    }
+
+    pub fn draw_rounded_rect_filled(
+        &mut self,
+        rect: Rect,
+        corner_radius: f32,
+        detail: CurveDetail,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        match detail {
+            CurveDetail::Segments(num_segments) => {
+                let points = rounded_rect_points(rect, corner_radius, num_segments);
+                self.draw_polygon_filled(&points, depth, color, additivity, draw_space);
+            }
+            CurveDetail::Pixelated => self.draw_rasterized_shape(
+                RasterShape::RoundedRect {
+                    rect,
+                    corner_radius,
+                },
+                None,
+                depth,
+                color,
+                additivity,
+                draw_space,
+            ),
+        }
+    }
+
+    /// Draws the outline of a rounded rect. The outline lies completely inside the rect.
+    pub fn draw_rounded_rect(
+        &mut self,
+        rect: Rect,
+        corner_radius: f32,
+        thickness: f32,
+        detail: CurveDetail,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        match detail {
+            CurveDetail::Segments(num_segments) => {
+                let half_thickness = 0.5 * thickness;
+                let points = rounded_rect_points(
+                    rect.extended_uniformly_by(-half_thickness),
+                    corner_radius - half_thickness,
+                    num_segments,
+                );
+                self.draw_polygon(
+                    &points,
+                    LineStyle::new(thickness),
+                    depth,
+                    color,
+                    additivity,
+                    draw_space,
+                );
+            }
+            CurveDetail::Pixelated => self.draw_rasterized_shape(
+                RasterShape::RoundedRect {
+                    rect,
+                    corner_radius,
+                },
+                Some(thickness),
+                depth,
+                color,
+                additivity,
+                draw_space,
+            ),
+        }
+    }
+
+    pub fn draw_triangle_filled(
+        &mut self,
+        triangle: [Point; 3],
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_polygon_filled(&triangle, depth, color, additivity, draw_space);
+    }
+
+    pub fn draw_triangle(
+        &mut self,
+        triangle: [Point; 3],
+        style: LineStyle,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_polygon(&triangle, style, depth, color, additivity, draw_space);
+    }
+
+    /// Fills a convex polygon. The points can be given in clockwise or counter-clockwise order.
+    pub fn draw_polygon_filled(
+        &mut self,
+        points: &[Point],
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let sprite = self.sprite(self.plain_sprite);
+        let uv = sprite.uv_bounds.center();
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        batch.push_shape(depth, is_translucent(color, additivity), |mesh| {
+            mesh.push_convex_polygon(points, uv, sprite.atlas_index, depth, color, additivity)
+        });
+    }
+
+    pub fn draw_polygon(
+        &mut self,
+        points: &[Point],
+        style: LineStyle,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        self.draw_polyline(points, true, style, depth, color, additivity, draw_space);
+    }
+
+    fn draw_rasterized_shape(
+        &mut self,
+        shape: RasterShape,
+        outline_thickness: Option<f32>,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        let sprite = self.sprite(self.plain_sprite);
+        let batch = self.draw_batch_by_draw_space(draw_space);
+        batch.push_shape(depth, is_translucent(color, additivity), |mesh| {
+            rasterized_shape_to_rects(shape, outline_thickness, |rect| {
+                mesh.push_quad(
+                    rect,
+                    sprite.uv_bounds,
+                    sprite.atlas_index,
+                    depth,
+                    color,
+                    additivity,
+                )
+            });
+        });
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Debug drawing
+    //
+    pub fn debug_draw_text(&mut self, text: &str, font: FontHandle, color: Color) {
+        let draw_offset = self.draw_text(
+            self.debug_text_origin,
+            &(String::from("\n") + text),
+            font,
+            0.0,
+            color,
+            0.0,
+            DrawSpace::Debug,
+        );
+        self.debug_text_origin.y += draw_offset.y;
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // State
+    //
+    pub fn start_drawing(&mut self) {
+        for batch in self
+            .world_batches
+            .iter_mut()
+            .chain(self.canvas_batches.iter_mut())
+            .chain(self.debug_batches.iter_mut())
+            .flat_map(|layer_batches| layer_batches.iter_mut())
+        {
+            batch.clear();
+        }
+        for render_target in self.render_targets.iter_mut() {
+            render_target.is_drawn_to = false;
+            for batch in render_target.batches.iter_mut().flatten() {
+                batch.clear();
+            }
+        }
+        self.active_render_target = None;
+        self.render_layer = RenderLayer::Default;
+        self.render_state = RenderState::default();
+
+        self.debug_text_origin = Vec2::new(8.0, 0.0);
+    }
+
+    /// Sets the layer that all following draw calls go into. The layer is reset to
+    /// `RenderLayer::Default` at the start of each frame.
+    pub fn set_render_layer(&mut self, render_layer: RenderLayer) {
+        self.render_layer = render_layer;
+    }
+
+    pub fn render_layer(&self) -> RenderLayer {
+        self.render_layer
+    }
+
+    /// Sets the shader, blend mode and depth mode that all following draw calls use. The render
+    /// state is reset to its default at the start of each frame.
+    ///
+    /// Translucent shapes are only sorted by depth against shapes with the same render state in
+    /// the same render layer. Shapes with different render states are drawn in the order in which
+    /// their render states were first used in the frame.
+    pub fn set_render_state(&mut self, render_state: RenderState) {
+        self.render_state = render_state;
+    }
+
+    pub fn render_state(&self) -> &RenderState {
+        &self.render_state
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Shaders
+    //
+
+    /// Creates a shader that can be selected via `set_render_state`. If no vertex shader source is
+    /// given the default vertex shader is used.
+    ///
+    /// Custom shaders get the same vertex attributes and uniforms as the default shader
+    /// (`a_pos`, `a_uv`, `a_color_modulate`, `a_additivity`, `u_transform`, `u_sampler_array`)
+    /// plus the `vec4` uniforms `u_params_0` to `u_params_3` of their `ShaderUniforms`.
+    pub fn create_shader(
+        &mut self,
+        name: &str,
+        vertex_shader_source: Option<&str>,
+        fragment_shader_source: &str,
+    ) -> ShaderInfo {
+        let shader_info = ShaderInfo {
+            id: self.next_shader_id,
+            name: String::from(name),
+        };
+        self.next_shader_id += 1;
+
+        self.draw_commands.push(DrawCommand::CreateShader {
+            shader_info: shader_info.clone(),
+            vertex_shader_source: vertex_shader_source.map(String::from),
+            fragment_shader_source: String::from(fragment_shader_source),
+        });
+        shader_info
+    }
+
+    /// Deletes the shader after everything that was drawn with it in the current frame. The
+    /// shader must not be used in following frames.
+    pub fn delete_shader(&mut self, shader_info: ShaderInfo) {
+        if !self.shaders_pending_deletion.contains(&shader_info) {
+            self.shaders_pending_deletion.push(shader_info);
+        }
+    }
+
+    /// Draws the world and canvas in grayscale where the brightness of a pixel is the value that
+    /// it wrote into the depthbuffer. Post-processing is skipped while this is enabled.
+    pub fn set_depth_visualization_enabled(&mut self, is_enabled: bool) {
+        if is_enabled && self.depth_visualization_shader.is_none() {
+            self.depth_visualization_shader = Some(self.create_shader(
+                "debug_depth_visualization",
+                None,
+                DEPTH_VISUALIZATION_FRAGMENT_SHADER_SOURCE,
+            ));
+        }
+        self.is_depth_visualization_enabled = is_enabled;
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Render targets
+    //
+
+    /// Returns the offscreen render target with the given name and creates it if it does not exist
+    /// yet. If the target exists with a different size it is recreated and loses its content.
+    pub fn render_target(&mut self, name: &str, width: u16, height: u16) -> RenderTargetHandle {
+        let index = match self
+            .render_targets
+            .iter()
+            .position(|render_target| render_target.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.render_targets.push(RenderTarget::new(name));
+                self.render_targets.len() - 1
+            }
+        };
+
+        let render_target = &mut self.render_targets[index];
+        let needs_recreation = match &render_target.framebuffer_info {
+            Some(framebuffer_info) => {
+                framebuffer_info.width != width || framebuffer_info.height != height
+            }
+            None => true,
+        };
+        if needs_recreation {
+            if let Some(old_framebuffer_info) = render_target.framebuffer_info.take() {
+                self.draw_commands.push(DrawCommand::DeleteFramebuffer {
+                    framebuffer_info: old_framebuffer_info,
+                });
+            }
+            let framebuffer_info = FramebufferInfo {
+                id: FIRST_RENDER_TARGET_FRAMEBUFFER_ID + index as u32,
+                width,
+                height,
+                name: String::from(name),
+            };
+            render_target.framebuffer_info = Some(framebuffer_info.clone());
+            self.draw_commands.push(DrawCommand::CreateFramebuffer {
+                framebuffer_info: framebuffer_info.clone(),
+            });
+            // NOTE: The content of a new framebuffer is undefined and render targets that are not
+            //       cleared every frame would show it
+            self.draw_commands.push(DrawCommand::Clear {
+                framebuffer: FramebufferTarget::Offscreen(framebuffer_info),
+                color: Color::new(0.0, 0.0, 0.0, 0.0),
+                depth: DEFAULT_CANVAS_ZFAR,
+            });
+        }
+
+        RenderTargetHandle(index)
+    }
+
+    pub fn delete_render_target(&mut self, handle: RenderTargetHandle) {
+        if self.active_render_target == Some(handle) {
+            self.active_render_target = None;
+        }
+        if let Some(framebuffer_info) = self.render_targets[handle.0].framebuffer_info.take() {
+            self.draw_commands
+                .push(DrawCommand::DeleteFramebuffer { framebuffer_info });
+        }
+    }
+
+    /// Returns zero for deleted render targets
+    pub fn render_target_dim(&self, handle: RenderTargetHandle) -> Vec2 {
+        match self.render_target_framebuffer(handle) {
+            Some(framebuffer_info) => Vec2::new(
+                f32::from(framebuffer_info.width),
+                f32::from(framebuffer_info.height),
+            ),
+            None => Vec2::zero(),
+        }
+    }
+
+    /// Redirects all following draw calls into the given render target until `end_render_target`
+    /// is called. The draw space of the draw calls is ignored and `transform` is used instead.
+    ///
+    /// If `clear_color` is `None` the content of the previous frames is kept which allows to
+    /// accumulate things like trails over time. Newly created render targets start out fully
+    /// transparent.
+    ///
+    /// Render targets are drawn before the render targets that sample them. If render targets
+    /// sample each other in a cycle, one of them sees the content of the previous frame.
+    pub fn begin_render_target(
+        &mut self,
+        handle: RenderTargetHandle,
+        transform: Mat4,
+        clear_color: Option<Color>,
+    ) {
+        let render_target = &mut self.render_targets[handle.0];
+        assert!(
+            render_target.framebuffer_info.is_some(),
+            "Render target '{}' was deleted",
+            render_target.name
+        );
+        // NOTE: We only clear once per frame so that a render target can be drawn to in multiple
+        //       passes
+        if !render_target.is_drawn_to {
+            render_target.clear_color = clear_color;
+        }
+        render_target.transform = transform;
+        render_target.is_drawn_to = true;
+        self.active_render_target = Some(handle);
+    }
+
+    pub fn end_render_target(&mut self) {
+        self.active_render_target = None;
+    }
+
+    /// Draws the content of a render target into the given rect like a sprite
+    pub fn draw_render_target(
+        &mut self,
+        handle: RenderTargetHandle,
+        rect: Rect,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+        draw_space: DrawSpace,
+    ) {
+        if self.active_render_target == Some(handle) {
+            warn!(
+                "Cannot draw render target '{}' into itself",
+                self.render_targets[handle.0].name
+            );
+            return;
+        }
+
+        let framebuffer_info = match self.render_target_framebuffer(handle) {
+            Some(framebuffer_info) => framebuffer_info.clone(),
+            None => return,
+        };
+        // NOTE: Framebuffers are stored bottom to top so we need to flip the texture coordinates
+        let uv = Rect::from_bounds(0.0, 1.0, 0.0, 1.0);
+        self.draw_batch(draw_space, Some(&framebuffer_info))
+            .push_quad(rect, uv, 0, depth, color, additivity);
+    }
+
+    fn render_target_framebuffer(&self, handle: RenderTargetHandle) -> Option<&FramebufferInfo> {
+        let render_target = self.render_targets.get(handle.0)?;
+        if render_target.framebuffer_info.is_none() {
+            warn!("Render target '{}' was deleted", render_target.name);
+        }
+        render_target.framebuffer_info.as_ref()
+    }
+
+    // TODO(JaSc): Get rid of screen_rect/canvas_rect here
+    pub fn finish_drawing(
+        &'drawcontext mut self,
+        transform: Mat4,
+        screen_rect: Rect,
+        canvas_rect: Rect,
+        canvas_blit_rect: Rect,
+        postprocess: &PostProcessSettings,
+    ) {
+        if postprocess.enabled && postprocess.color_grading.enabled {
+            self.update_color_grading_lut(postprocess.color_grading.lut);
+        }
+
         let canvas_framebuffer = self
             .canvas_framebuffer
             .clone()
@@ -438,19 +1461,93 @@ impl<'drawcontext> DrawContext<'drawcontext> {
             depth: DEFAULT_CANVAS_ZFAR,
         });
 
+        // NOTE: We gather the stats before sorting as sorting can rebuild the translucent meshes
+        self.stats = DrawStats {
+            world: draw_space_stats(&self.world_batches),
+            canvas: draw_space_stats(&self.canvas_batches),
+            debug: draw_space_stats(&self.debug_batches),
+            render_targets: self
+                .render_targets
+                .iter()
+                .map(|render_target| draw_space_stats(&render_target.batches))
+                .fold(DrawSpaceStats::default(), |sum, stats| sum + stats),
+            ..Default::default()
+        };
+
+        let mut num_mesh_splits = 0;
+        for batch in self
+            .world_batches
+            .iter_mut()
+            .chain(self.canvas_batches.iter_mut())
+            .chain(self.debug_batches.iter_mut())
+            .chain(
+                self.render_targets
+                    .iter_mut()
+                    .flat_map(|render_target| render_target.batches.iter_mut()),
+            )
+            .flat_map(|layer_batches| layer_batches.iter_mut())
+        {
+            batch.sort_translucent_shapes();
+            num_mesh_splits += batch.num_mesh_splits();
+            if !batch.is_empty() {
+                self.stats.num_batches += 1;
+            }
+        }
+        if num_mesh_splits > 0 && self.num_mesh_splits == 0 {
+            debug!(
+                "Draw batches exceeded {} vertices and were split into {} additional meshes",
+                MAX_VERTICES_PER_MESH, num_mesh_splits
+            );
+        }
+        self.num_mesh_splits = num_mesh_splits;
+
+        // Render target draw batches
+        for render_target_index in render_target_draw_order(&self.render_targets) {
+            let render_target = &self.render_targets[render_target_index];
+            let framebuffer_info = match &render_target.framebuffer_info {
+                Some(framebuffer_info) if render_target.is_drawn_to => framebuffer_info,
+                _ => continue,
+            };
+            let framebuffer = FramebufferTarget::Offscreen(framebuffer_info.clone());
+            match render_target.clear_color {
+                Some(color) => self.draw_commands.push(DrawCommand::Clear {
+                    framebuffer: framebuffer.clone(),
+                    color,
+                    depth: DEFAULT_CANVAS_ZFAR,
+                }),
+                None => self.draw_commands.push(DrawCommand::ClearDepth {
+                    framebuffer: framebuffer.clone(),
+                    depth: DEFAULT_CANVAS_ZFAR,
+                }),
+            }
+            push_draw_batch_commands(
+                &mut self.draw_commands,
+                &render_target.batches,
+                render_target.transform,
+                &texture_atlas,
+                framebuffer,
+                DEFAULT_CANVAS_ZFAR,
+                None,
+            );
+        }
+
+        let depth_visualization_render_state = match &self.depth_visualization_shader {
+            Some(shader) if self.is_depth_visualization_enabled => {
+                Some(RenderState::default().with_shader(shader.clone(), ShaderUniforms::new()))
+            }
+            _ => None,
+        };
+
         // World draw batches
-        self.draw_commands.push(DrawCommand::DrawPolys {
+        push_draw_batch_commands(
+            &mut self.draw_commands,
+            &self.world_batches,
             transform,
-            texture_array_info: texture_atlas.clone(),
-            framebuffer: FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
-            mesh: &self.world_polygons,
-        });
-        self.draw_commands.push(DrawCommand::DrawLines {
-            transform,
-            texture_array_info: texture_atlas.clone(),
-            framebuffer: FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
-            mesh: &self.world_lines,
-        });
+            &texture_atlas,
+            FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
+            DEFAULT_WORLD_ZFAR,
+            depth_visualization_render_state.as_ref(),
+        );
 
         // Canvas draw batches
         let canvas_transform = Mat4::ortho_origin_top_left(
@@ -463,44 +1560,76 @@ impl<'drawcontext> DrawContext<'drawcontext> {
             framebuffer: FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
             depth: DEFAULT_CANVAS_ZFAR,
         });
-        self.draw_commands.push(DrawCommand::DrawPolys {
-            transform: canvas_transform,
-            texture_array_info: texture_atlas.clone(),
-            framebuffer: FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
-            mesh: &self.canvas_polygons,
-        });
-        self.draw_commands.push(DrawCommand::DrawLines {
-            transform: canvas_transform,
-            texture_array_info: texture_atlas.clone(),
-            framebuffer: FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
-            mesh: &self.canvas_lines,
-        });
+        push_draw_batch_commands(
+            &mut self.draw_commands,
+            &self.canvas_batches,
+            canvas_transform,
+            &texture_atlas,
+            FramebufferTarget::Offscreen(canvas_framebuffer.clone()),
+            DEFAULT_CANVAS_ZFAR,
+            depth_visualization_render_state.as_ref(),
+        );
 
-        // Blit canvas to screen
-        self.draw_commands.push(DrawCommand::BlitFramebuffer {
-            source_framebuffer: canvas_framebuffer.clone(),
-            target_framebuffer: FramebufferTarget::Screen,
-            source_rect: canvas_rect,
-            target_rect: canvas_blit_rect,
-        });
+        // Post-process canvas onto screen
+        let effects = if depth_visualization_render_state.is_some() {
+            Vec::new()
+        } else {
+            postprocess.effects()
+        };
+        if effects.is_empty() {
+            self.draw_commands.push(DrawCommand::BlitFramebuffer {
+                source_framebuffer: canvas_framebuffer.clone(),
+                target_framebuffer: FramebufferTarget::Screen,
+                source_rect: canvas_rect,
+                target_rect: canvas_blit_rect,
+            });
+        } else {
+            push_postprocess_commands(
+                &mut self.draw_commands,
+                &effects,
+                &canvas_framebuffer,
+                &self.postprocess_framebuffers,
+                self.color_grading_lut
+                    .as_ref()
+                    .map(|(texture_array_info, _)| texture_array_info.clone()),
+                canvas_blit_rect,
+            );
+        }
 
         // Screen draw batches
+        let debug_transform = debug_space_transform(screen_rect, canvas_rect, canvas_blit_rect);
         self.draw_commands.push(DrawCommand::ClearDepth {
             framebuffer: FramebufferTarget::Screen,
             depth: DEFAULT_SCREEN_ZFAR,
         });
-        self.draw_commands.push(DrawCommand::DrawPolys {
-            transform: canvas_transform,
-            texture_array_info: texture_atlas.clone(),
-            framebuffer: FramebufferTarget::Screen,
-            mesh: &self.debug_polygons,
-        });
-        self.draw_commands.push(DrawCommand::DrawLines {
-            transform: canvas_transform,
-            texture_array_info: texture_atlas.clone(),
-            framebuffer: FramebufferTarget::Screen,
-            mesh: &self.debug_lines,
-        });
+        push_draw_batch_commands(
+            &mut self.draw_commands,
+            &self.debug_batches,
+            debug_transform,
+            &texture_atlas,
+            FramebufferTarget::Screen,
+            DEFAULT_SCREEN_ZFAR,
+            None,
+        );
+
+        for shader_info in self.shaders_pending_deletion.drain(..) {
+            self.draw_commands
+                .push(DrawCommand::DeleteShader { shader_info });
+        }
+
+        self.stats.count_commands(&self.draw_commands);
+        self.command_summaries.clear();
+        if self.is_recording_command_summaries {
+            self.command_summaries
+                .extend(self.draw_commands.iter().map(|command| {
+                    DrawCommandSummary::new(
+                        command,
+                        &canvas_framebuffer,
+                        canvas_transform,
+                        debug_transform,
+                    )
+                }));
+        }
     }
 
     pub fn reinitialize(&mut self, canvas_width: u16, canvas_height: u16) {
@@ -512,6 +1641,29 @@ impl<'drawcontext> DrawContext<'drawcontext> {
         // Create atlas from metafile
         self.atlas = utility::deserialize_from_binary_file("data/atlas.tex");
 
+        // Resolve all handles that were handed out so far against the new atlas
+        let atlas = &self.atlas;
+        self.sprite_table
+            .resolve_all(|resource_path| atlas.sprite_or_fallback(resource_path));
+        self.font_table
+            .resolve_all(|resource_path| atlas.font_or_fallback(resource_path));
+        self.animation_table
+            .resolve_all(|resource_path| atlas.animation_or_fallback(resource_path));
+        self.nine_slice_table
+            .resolve_all(|resource_path| atlas.nine_slice_or_fallback(resource_path));
+        self.tilemap_table
+            .resolve_all(|resource_path| atlas.tilemap_or_fallback(resource_path));
+
+        self.plain_sprite = self.sprite_handle("images/plain");
+        self.textured_sprite = self.sprite_handle("images/textured");
+        // NOTE: We only set the default font on the first initialization so that reloading the
+        //       assets does not override a font set via `set_default_font`
+        if self.atlas_texture_array.is_none() {
+            self.default_font = self.font_handle(DEFAULT_FONT_RESOURCE_PATH);
+        }
+        self.debug_circle_animation = self.animation_handle("images/test");
+        self.debug_cursor_animation = self.animation_handle("images/cursor_test");
+
         // Delete old atlas textures if they exists
         if let Some(old_atlas_texture_array_info) = self.atlas_texture_array.take() {
             self.draw_commands.push(DrawCommand::DeleteTextureArray {
@@ -523,49 +1675,386 @@ impl<'drawcontext> DrawContext<'drawcontext> {
             load_texture_array(0, "data/atlas", self.atlas.num_atlas_textures);
         self.atlas_texture_array = Some(texture_array_info.clone());
 
-        self.draw_commands.push(DrawCommand::CreateTextureArray {
-            texture_array_info,
-            pixels,
-        });
+        self.draw_commands.push(DrawCommand::CreateTextureArray {
+            texture_array_info,
+            pixels,
+        });
+
+        // -----------------------------------------------------------------------------------------
+        // Framebuffer creation
+        //
+        self.recreate_canvas_framebuffer(canvas_width, canvas_height);
+    }
+
+    /// Recreates the canvas framebuffer if its size differs from the given size
+    pub fn resize_canvas(&mut self, canvas_width: u16, canvas_height: u16) {
+        let needs_resize = self
+            .canvas_framebuffer
+            .as_ref()
+            .map(|framebuffer_info| {
+                framebuffer_info.width != canvas_width || framebuffer_info.height != canvas_height
+            })
+            .unwrap_or(true);
+        if needs_resize {
+            self.recreate_canvas_framebuffer(canvas_width, canvas_height);
+        }
+    }
+
+    fn recreate_canvas_framebuffer(&mut self, canvas_width: u16, canvas_height: u16) {
+        // Delete old framebuffer if it exists
+        if let Some(old_canvas_framebuffer_info) = self.canvas_framebuffer.take() {
+            self.draw_commands.push(DrawCommand::DeleteFramebuffer {
+                framebuffer_info: old_canvas_framebuffer_info,
+            });
+        }
+
+        // Create new canvas framebuffer
+        let framebuffer_info = FramebufferInfo {
+            id: 0,
+            width: canvas_width,
+            height: canvas_height,
+            name: String::from("Canvas"),
+        };
+        self.canvas_framebuffer = Some(framebuffer_info.clone());
+        self.draw_commands
+            .push(DrawCommand::CreateFramebuffer { framebuffer_info });
+
+        // Recreate post-processing framebuffers with the same size as the canvas
+        for old_framebuffer_info in self.postprocess_framebuffers.drain(..) {
+            self.draw_commands.push(DrawCommand::DeleteFramebuffer {
+                framebuffer_info: old_framebuffer_info,
+            });
+        }
+        for index in 0..2 {
+            let framebuffer_info = FramebufferInfo {
+                id: 1 + index,
+                width: canvas_width,
+                height: canvas_height,
+                name: format!("PostProcess{}", index),
+            };
+            self.postprocess_framebuffers.push(framebuffer_info.clone());
+            self.draw_commands
+                .push(DrawCommand::CreateFramebuffer { framebuffer_info });
+        }
+    }
+
+    /// Recreates the color grading lookup texture if its adjustments have changed
+    fn update_color_grading_lut(&mut self, lut: ColorGradingLut) {
+        if let Some((_, current_lut)) = &self.color_grading_lut {
+            if *current_lut == lut {
+                return;
+            }
+        }
+
+        if let Some((old_texture_array_info, _)) = self.color_grading_lut.take() {
+            self.draw_commands.push(DrawCommand::DeleteTextureArray {
+                texture_array_info: old_texture_array_info,
+            });
+        }
+        let (width, height, pixels) = lut.to_pixels();
+        let texture_array_info = TextureArrayInfo {
+            id: 1,
+            width: width as u16,
+            height: height as u16,
+            num_textures: 1,
+            name: String::from("ColorGradingLut"),
+        };
+        self.color_grading_lut = Some((texture_array_info.clone(), lut));
+        self.draw_commands.push(DrawCommand::CreateTextureArray {
+            texture_array_info,
+            pixels: vec![pixels],
+        });
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Utility
+    //
+    pub fn sprite_handle(&mut self, resource_path: &str) -> SpriteHandle {
+        let atlas = &self.atlas;
+        SpriteHandle(
+            self.sprite_table
+                .get_or_insert_with(resource_path, |path| atlas.sprite_or_fallback(path)),
+        )
+    }
+
+    pub fn font_handle(&mut self, resource_path: &str) -> FontHandle {
+        let atlas = &self.atlas;
+        FontHandle(
+            self.font_table
+                .get_or_insert_with(resource_path, |path| atlas.font_or_fallback(path)),
+        )
+    }
+
+    pub fn animation_handle(&mut self, resource_path: &str) -> AnimationHandle {
+        let atlas = &self.atlas;
+        AnimationHandle(
+            self.animation_table
+                .get_or_insert_with(resource_path, |path| atlas.animation_or_fallback(path)),
+        )
+    }
+
+    pub fn nine_slice_handle(&mut self, resource_path: &str) -> NineSliceHandle {
+        let atlas = &self.atlas;
+        NineSliceHandle(
+            self.nine_slice_table
+                .get_or_insert_with(resource_path, |path| atlas.nine_slice_or_fallback(path)),
+        )
+    }
+
+    pub fn tilemap_handle(&mut self, resource_path: &str) -> TilemapHandle {
+        let atlas = &self.atlas;
+        TilemapHandle(
+            self.tilemap_table
+                .get_or_insert_with(resource_path, |path| atlas.tilemap_or_fallback(path)),
+        )
+    }
+
+    /// The font that scenes and widgets use unless they pick a specific one
+    pub fn default_font(&self) -> FontHandle {
+        self.default_font
+    }
+
+    pub fn set_default_font(&mut self, handle: FontHandle) {
+        self.default_font = handle;
+    }
+
+    pub fn sprite(&self, handle: SpriteHandle) -> Sprite {
+        *self.sprite_table.get(handle.0)
+    }
+
+    pub fn font(&self, handle: FontHandle) -> &Font {
+        self.font_table.get(handle.0)
+    }
+
+    pub fn animation(&self, handle: AnimationHandle) -> &Animation {
+        self.animation_table.get(handle.0)
+    }
+
+    pub fn nine_slice(&self, handle: NineSliceHandle) -> NineSlice {
+        *self.nine_slice_table.get(handle.0)
+    }
+
+    pub fn tilemap(&self, handle: TilemapHandle) -> &Tilemap {
+        self.tilemap_table.get(handle.0)
+    }
+
+    /// Returns how many additional meshes were needed in the last frame because draw batches had
+    /// more vertices than a mesh can address. Every additional mesh costs an extra draw call.
+    pub fn num_mesh_splits(&self) -> usize {
+        self.num_mesh_splits
+    }
+
+    /// Returns what was drawn in the last frame
+    pub fn stats(&self) -> &DrawStats {
+        &self.stats
+    }
+
+    /// Enables summaries of all draw commands of a frame which can be inspected in the next frame
+    pub fn set_recording_command_summaries(&mut self, is_recording: bool) {
+        self.is_recording_command_summaries = is_recording;
+    }
+
+    /// Returns the summaries of the draw commands of the last frame if recording is enabled
+    pub fn command_summaries(&self) -> &[DrawCommandSummary] {
+        &self.command_summaries
+    }
+
+    /// Returns `None` if the canvas framebuffer was not created yet
+    pub fn canvas_framebuffer_target(&self) -> Option<FramebufferTarget> {
+        self.canvas_framebuffer
+            .clone()
+            .map(FramebufferTarget::Offscreen)
+    }
+
+    fn draw_batch_by_draw_space(&mut self, draw_space: DrawSpace) -> &mut DrawBatch {
+        self.draw_batch(draw_space, None)
+    }
+
+    /// Returns the batch for the current render layer and render state that samples the given
+    /// framebuffer or the atlas if `framebuffer_texture` is `None`. While a render target is
+    /// active the batch belongs to the render target instead of the given draw space.
+    fn draw_batch(
+        &mut self,
+        draw_space: DrawSpace,
+        framebuffer_texture: Option<&FramebufferInfo>,
+    ) -> &mut DrawBatch {
+        let layer_index = self.render_layer as usize;
+        let layer_batches = match self.active_render_target {
+            Some(handle) => &mut self.render_targets[handle.0].batches[layer_index],
+            None => match draw_space {
+                DrawSpace::World => &mut self.world_batches[layer_index],
+                DrawSpace::Canvas => &mut self.canvas_batches[layer_index],
+                DrawSpace::Debug => &mut self.debug_batches[layer_index],
+            },
+        };
+
+        // NOTE: Batches that were not used so far in this frame are empty and can be reused for
+        //       another render state. This keeps the allocations of their meshes around. We always
+        //       take the first empty batch so that the batches of a layer are ordered by the first
+        //       use of their render state in this frame.
+        let render_state = &self.render_state;
+        let batch_index = layer_batches
+            .iter()
+            .position(|batch| {
+                !batch.is_empty()
+                    && batch.render_state == *render_state
+                    && batch.framebuffer_texture.as_ref() == framebuffer_texture
+            })
+            .or_else(|| layer_batches.iter().position(|batch| batch.is_empty()));
+        let batch_index = match batch_index {
+            Some(batch_index) => batch_index,
+            None => {
+                layer_batches.push(DrawBatch::default());
+                layer_batches.len() - 1
+            }
+        };
+
+        let batch = &mut layer_batches[batch_index];
+        if batch.render_state != *render_state {
+            batch.render_state = render_state.clone();
+        }
+        if batch.framebuffer_texture.as_ref() != framebuffer_texture {
+            batch.framebuffer_texture = framebuffer_texture.cloned();
+        }
+        batch
+    }
+}
 
-        // -----------------------------------------------------------------------------------------
-        // Framebuffer creation
-        //
-        // Delete old framebuffer if it exists
-        if let Some(old_canvas_framebuffer_info) = self.canvas_framebuffer.take() {
-            self.draw_commands.push(DrawCommand::DeleteFramebuffer {
-                framebuffer_info: old_canvas_framebuffer_info,
+/// Pushes the draw commands for all non-empty layers of a draw space. The depthbuffer is cleared
+/// between layers so that each layer is drawn on top of the previous ones. The batches of a layer
+/// are drawn in the order in which their render states were first used in the frame.
+///
+/// NOTE: Translucent shapes are only sorted back-to-front within their batch. Translucent shapes
+///       with different render states in the same layer are not sorted against each other.
+fn push_draw_batch_commands<'drawcontext>(
+    draw_commands: &mut Vec<DrawCommand<'drawcontext>>,
+    layers: &'drawcontext [Vec<DrawBatch>],
+    transform: Mat4,
+    texture_atlas: &TextureArrayInfo,
+    framebuffer: FramebufferTarget,
+    clear_depth: f32,
+    render_state_override: Option<&RenderState>,
+) {
+    let non_empty_layers = layers
+        .iter()
+        .filter(|layer_batches| layer_batches.iter().any(|batch| !batch.is_empty()));
+    for (index, layer_batches) in non_empty_layers.enumerate() {
+        if index > 0 {
+            draw_commands.push(DrawCommand::ClearDepth {
+                framebuffer: framebuffer.clone(),
+                depth: clear_depth,
             });
         }
+        for batch in layer_batches.iter().filter(|batch| !batch.is_empty()) {
+            let texture = match &batch.framebuffer_texture {
+                Some(framebuffer_info) => DrawTexture::Framebuffer(framebuffer_info.clone()),
+                None => DrawTexture::TextureArray(texture_atlas.clone()),
+            };
+            let render_state = render_state_override.unwrap_or(&batch.render_state);
+            let polygon_meshes = batch
+                .opaque_polygons
+                .iter()
+                .chain(batch.translucent_polygons.iter());
+            for mesh in polygon_meshes.filter(|mesh| !mesh.vertices.is_empty()) {
+                draw_commands.push(DrawCommand::DrawPolys {
+                    transform,
+                    texture: texture.clone(),
+                    framebuffer: framebuffer.clone(),
+                    render_state: render_state.clone(),
+                    mesh,
+                });
+            }
+            for mesh in batch.lines.iter().filter(|mesh| !mesh.vertices.is_empty()) {
+                draw_commands.push(DrawCommand::DrawLines {
+                    transform,
+                    texture: texture.clone(),
+                    framebuffer: framebuffer.clone(),
+                    render_state: render_state.clone(),
+                    mesh,
+                });
+            }
+        }
+    }
+}
 
-        // Create new canvas framebuffer
-        let framebuffer_info = FramebufferInfo {
-            id: 0,
-            width: canvas_width,
-            height: canvas_height,
-            name: String::from("Canvas"),
+/// Returns the projection of the debug draw space. Like the canvas draw space it is given in canvas
+/// coordinates but it is drawn onto the screen where it covers the same area as the blitted
+/// canvas.
+fn debug_space_transform(screen_rect: Rect, canvas_rect: Rect, canvas_blit_rect: Rect) -> Mat4 {
+    use cgmath::Vector3;
+
+    let scale = canvas_blit_rect.dim() / canvas_rect.dim();
+    let canvas_to_screen = Mat4::from_translation(Vector3::new(
+        canvas_blit_rect.left,
+        canvas_blit_rect.top,
+        0.0,
+    )) * Mat4::from_nonuniform_scale(scale.x, scale.y, 1.0);
+    let screen_projection = Mat4::ortho_origin_top_left(
+        screen_rect.width(),
+        screen_rect.height(),
+        DEFAULT_CANVAS_ZNEAR,
+        DEFAULT_CANVAS_ZFAR,
+    );
+    screen_projection * canvas_to_screen
+}
+
+/// Pushes the post-processing passes that take the canvas to the screen. All but the last pass
+/// render into the post-processing framebuffers which are used alternately.
+fn push_postprocess_commands<'drawcontext>(
+    draw_commands: &mut Vec<DrawCommand<'drawcontext>>,
+    effects: &[PostProcessEffect],
+    canvas_framebuffer: &FramebufferInfo,
+    postprocess_framebuffers: &[FramebufferInfo],
+    color_grading_lut: Option<TextureArrayInfo>,
+    canvas_blit_rect: Rect,
+) {
+    let mut source_framebuffer = canvas_framebuffer.clone();
+    // NOTE: The bloom passes need to remember the unblurred image that they are composited onto
+    let mut bloom_base_framebuffer = canvas_framebuffer.clone();
+
+    for (pass_index, effect) in effects.iter().enumerate() {
+        let (source, secondary) = match effect {
+            PostProcessEffect::BrightPass { .. } => {
+                bloom_base_framebuffer = source_framebuffer.clone();
+                (source_framebuffer.clone(), None)
+            }
+            PostProcessEffect::BloomComposite { .. } => (
+                bloom_base_framebuffer.clone(),
+                Some(source_framebuffer.clone()),
+            ),
+            _ => (source_framebuffer.clone(), None),
         };
-        self.canvas_framebuffer = Some(framebuffer_info.clone());
-        self.draw_commands
-            .push(DrawCommand::CreateFramebuffer { framebuffer_info });
-    }
 
-    // ---------------------------------------------------------------------------------------------
-    // Utility
-    //
-    fn line_mesh_by_draw_space(&mut self, draw_space: DrawSpace) -> &mut LineMesh {
-        match draw_space {
-            DrawSpace::World => &mut self.world_lines,
-            DrawSpace::Canvas => &mut self.canvas_lines,
-            DrawSpace::Debug => &mut self.debug_lines,
-        }
-    }
-    fn polygon_mesh_by_draw_space(&mut self, draw_space: DrawSpace) -> &mut PolygonMesh {
-        match draw_space {
-            DrawSpace::World => &mut self.world_polygons,
-            DrawSpace::Canvas => &mut self.canvas_polygons,
-            DrawSpace::Debug => &mut self.debug_polygons,
-        }
+        let is_last_pass = pass_index == effects.len() - 1;
+        let (target_framebuffer, target_rect) = if is_last_pass {
+            (FramebufferTarget::Screen, canvas_blit_rect)
+        } else {
+            let target = postprocess_framebuffers
+                .iter()
+                .find(|framebuffer| {
+                    **framebuffer != source && Some(*framebuffer) != secondary.as_ref()
+                })
+                .expect("Not enough post-processing framebuffers")
+                .clone();
+            let target_rect =
+                Rect::from_width_height(f32::from(target.width), f32::from(target.height));
+            source_framebuffer = target.clone();
+            (FramebufferTarget::Offscreen(target), target_rect)
+        };
+
+        let color_grading_lut = match effect {
+            PostProcessEffect::ColorGrading { .. } => color_grading_lut.clone(),
+            _ => None,
+        };
+
+        draw_commands.push(DrawCommand::PostProcess {
+            source_framebuffer: source,
+            secondary_framebuffer: secondary,
+            color_grading_lut,
+            target_framebuffer,
+            target_rect,
+            effect: *effect,
+        });
     }
 }
 
@@ -600,6 +2089,174 @@ fn load_texture_array(
     (texture_array_info, pixels)
 }
 
+//==================================================================================================
+// DrawStats
+//==================================================================================================
+//
+
+/// What was drawn into one draw space in a frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DrawSpaceStats {
+    /// Includes the quads of sprites and glyphs
+    pub num_quads: usize,
+    /// Includes the triangles of quads
+    pub num_triangles: usize,
+    pub num_lines: usize,
+    pub num_glyphs: usize,
+}
+
+impl std::ops::Add for DrawSpaceStats {
+    type Output = DrawSpaceStats;
+
+    fn add(self, other: DrawSpaceStats) -> DrawSpaceStats {
+        DrawSpaceStats {
+            num_quads: self.num_quads + other.num_quads,
+            num_triangles: self.num_triangles + other.num_triangles,
+            num_lines: self.num_lines + other.num_lines,
+            num_glyphs: self.num_glyphs + other.num_glyphs,
+        }
+    }
+}
+
+/// The rendering cost of a frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DrawStats {
+    pub world: DrawSpaceStats,
+    pub canvas: DrawSpaceStats,
+    pub debug: DrawSpaceStats,
+    /// Everything that was drawn into render targets regardless of its draw space
+    pub render_targets: DrawSpaceStats,
+    /// Non-empty draw batches of all draw spaces and render targets
+    pub num_batches: usize,
+    pub num_draw_calls: usize,
+    /// The number of times consecutive draw calls sample from different textures
+    pub num_texture_switches: usize,
+    pub num_framebuffer_clears: usize,
+}
+
+impl DrawStats {
+    fn count_commands(&mut self, draw_commands: &[DrawCommand]) {
+        let mut previous_texture = None;
+        for command in draw_commands {
+            match command {
+                DrawCommand::DrawPolys { texture, .. } | DrawCommand::DrawLines { texture, .. } => {
+                    self.num_draw_calls += 1;
+                    if previous_texture.map_or(false, |previous| previous != texture) {
+                        self.num_texture_switches += 1;
+                    }
+                    previous_texture = Some(texture);
+                }
+                DrawCommand::Clear { .. }
+                | DrawCommand::ClearColor { .. }
+                | DrawCommand::ClearDepth { .. } => self.num_framebuffer_clears += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn draw_space_stats(layers: &[Vec<DrawBatch>]) -> DrawSpaceStats {
+    layers
+        .iter()
+        .flat_map(|layer_batches| layer_batches.iter())
+        .map(DrawBatch::stats)
+        .fold(DrawSpaceStats::default(), |sum, stats| sum + stats)
+}
+
+/// A short description of a draw command that outlives the frame of the command
+#[derive(Debug, Clone)]
+pub struct DrawCommandSummary {
+    pub description: String,
+    pub num_vertices: usize,
+    /// The area that the geometry of the command covers. This is given in the draw space that
+    /// shows the framebuffer that the command draws to. It is `None` for commands that draw
+    /// nothing visible or draw into render targets.
+    pub bounds: Option<(DrawSpace, Rect)>,
+}
+
+impl DrawCommandSummary {
+    fn new(
+        command: &DrawCommand,
+        canvas_framebuffer: &FramebufferInfo,
+        canvas_transform: Mat4,
+        debug_transform: Mat4,
+    ) -> DrawCommandSummary {
+        use cgmath::{SquareMatrix, Vector4};
+
+        let (vertices, transform, framebuffer) = match command {
+            DrawCommand::DrawPolys {
+                mesh,
+                transform,
+                framebuffer,
+                ..
+            } => (mesh.to_vertices_indices().0, *transform, framebuffer),
+            DrawCommand::DrawLines {
+                mesh,
+                transform,
+                framebuffer,
+                ..
+            } => (mesh.to_vertices_indices().0, *transform, framebuffer),
+            _ => {
+                return DrawCommandSummary {
+                    description: command.short_description(),
+                    num_vertices: 0,
+                    bounds: None,
+                }
+            }
+        };
+
+        let draw_space = match framebuffer {
+            FramebufferTarget::Screen => Some((DrawSpace::Debug, debug_transform)),
+            FramebufferTarget::Offscreen(info) if info == canvas_framebuffer => {
+                Some((DrawSpace::Canvas, canvas_transform))
+            }
+            FramebufferTarget::Offscreen(_) => None,
+        };
+        let bounds = draw_space.and_then(|(draw_space, draw_space_transform)| {
+            // NOTE: We map the bounds back from normalized device coordinates with the inverse
+            //       projection of the draw space
+            let inverse_transform = draw_space_transform.invert()?;
+            normalized_device_bounds(vertices, transform).map(|ndc_bounds| {
+                let top_left =
+                    inverse_transform * Vector4::new(ndc_bounds.left, ndc_bounds.top, 0.0, 1.0);
+                let bottom_right =
+                    inverse_transform * Vector4::new(ndc_bounds.right, ndc_bounds.bottom, 0.0, 1.0);
+                let bounds =
+                    Rect::from_bounds(top_left.x, bottom_right.x, top_left.y, bottom_right.y);
+                (draw_space, bounds)
+            })
+        });
+
+        DrawCommandSummary {
+            description: command.short_description(),
+            num_vertices: vertices.len(),
+            bounds,
+        }
+    }
+}
+
+/// Returns the bounds of the given vertices in normalized device coordinates where `top` is the
+/// largest y coordinate
+fn normalized_device_bounds(vertices: &[Vertex], transform: Mat4) -> Option<Rect> {
+    vertices
+        .iter()
+        .map(|vertex| {
+            let pos = transform * cgmath::Vector4::from(vertex.pos);
+            Point::new(pos.x / pos.w, pos.y / pos.w)
+        })
+        .fold(None, |bounds: Option<Rect>, pos| {
+            Some(match bounds {
+                None => Rect::from_bounds(pos.x, pos.x, pos.y, pos.y),
+                Some(bounds) => Rect::from_bounds(
+                    f32::min(bounds.left, pos.x),
+                    f32::max(bounds.right, pos.x),
+                    f32::min(bounds.bottom, pos.y),
+                    f32::max(bounds.top, pos.y),
+                ),
+            })
+        })
+}
+
 //==================================================================================================
 // DrawCommand
 //==================================================================================================
@@ -608,14 +2265,16 @@ pub enum DrawCommand<'drawcontext> {
     DrawLines {
         transform: Mat4,
         mesh: &'drawcontext LineMesh,
-        texture_array_info: TextureArrayInfo,
+        texture: DrawTexture,
         framebuffer: FramebufferTarget,
+        render_state: RenderState,
     },
     DrawPolys {
         transform: Mat4,
         mesh: &'drawcontext PolygonMesh,
-        texture_array_info: TextureArrayInfo,
+        texture: DrawTexture,
         framebuffer: FramebufferTarget,
+        render_state: RenderState,
     },
     Clear {
         framebuffer: FramebufferTarget,
@@ -636,6 +2295,18 @@ pub enum DrawCommand<'drawcontext> {
         source_rect: Rect,
         target_rect: Rect,
     },
+    /// Draws the source framebuffer with a full-screen effect into the target rect of the target
+    /// framebuffer
+    PostProcess {
+        source_framebuffer: FramebufferInfo,
+        /// Additional input for effects that combine two images
+        secondary_framebuffer: Option<FramebufferInfo>,
+        /// Lookup texture for `PostProcessEffect::ColorGrading`
+        color_grading_lut: Option<TextureArrayInfo>,
+        target_framebuffer: FramebufferTarget,
+        target_rect: Rect,
+        effect: PostProcessEffect,
+    },
     CreateFramebuffer {
         framebuffer_info: FramebufferInfo,
     },
@@ -649,6 +2320,95 @@ pub enum DrawCommand<'drawcontext> {
     DeleteTextureArray {
         texture_array_info: TextureArrayInfo,
     },
+    CreateShader {
+        shader_info: ShaderInfo,
+        /// Uses the default vertex shader if `None`
+        vertex_shader_source: Option<String>,
+        fragment_shader_source: String,
+    },
+    DeleteShader {
+        shader_info: ShaderInfo,
+    },
+}
+
+impl<'drawcontext> DrawCommand<'drawcontext> {
+    /// Returns a single line description of the command which is more readable than its `Debug`
+    /// output
+    pub fn short_description(&self) -> String {
+        match self {
+            DrawCommand::DrawLines {
+                mesh,
+                texture,
+                framebuffer,
+                render_state,
+                ..
+            } => format!(
+                "DrawLines {} verts {} -> {} {:?}",
+                mesh.vertices.len(),
+                texture.name(),
+                framebuffer.name(),
+                render_state.blend_mode
+            ),
+            DrawCommand::DrawPolys {
+                mesh,
+                texture,
+                framebuffer,
+                render_state,
+                ..
+            } => format!(
+                "DrawPolys {} verts {} -> {} {:?}",
+                mesh.vertices.len(),
+                texture.name(),
+                framebuffer.name(),
+                render_state.blend_mode
+            ),
+            DrawCommand::Clear { framebuffer, .. } => format!("Clear {}", framebuffer.name()),
+            DrawCommand::ClearColor { framebuffer, .. } => {
+                format!("ClearColor {}", framebuffer.name())
+            }
+            DrawCommand::ClearDepth { framebuffer, .. } => {
+                format!("ClearDepth {}", framebuffer.name())
+            }
+            DrawCommand::BlitFramebuffer {
+                source_framebuffer,
+                target_framebuffer,
+                ..
+            } => format!(
+                "BlitFramebuffer {} -> {}",
+                source_framebuffer.name,
+                target_framebuffer.name()
+            ),
+            DrawCommand::PostProcess {
+                source_framebuffer,
+                target_framebuffer,
+                effect,
+                ..
+            } => format!(
+                "PostProcess {} -> {} {:?}",
+                source_framebuffer.name,
+                target_framebuffer.name(),
+                effect
+            ),
+            DrawCommand::CreateFramebuffer { framebuffer_info } => {
+                format!("CreateFramebuffer {}", framebuffer_info.name)
+            }
+            DrawCommand::DeleteFramebuffer { framebuffer_info } => {
+                format!("DeleteFramebuffer {}", framebuffer_info.name)
+            }
+            DrawCommand::CreateTextureArray {
+                texture_array_info, ..
+            } => format!("CreateTextureArray {}", texture_array_info.name),
+            DrawCommand::DeleteTextureArray { texture_array_info } => {
+                format!("DeleteTextureArray {}", texture_array_info.name)
+            }
+            DrawCommand::CreateShader { shader_info, .. } => {
+                format!("CreateShader {}", shader_info.name)
+            }
+            DrawCommand::DeleteShader { shader_info } => {
+                format!("DeleteShader {}", shader_info.name)
+            }
+        }
+    }
 }
 
 impl<'drawbuffers> std::fmt::Debug for DrawCommand<'drawbuffers> {
@@ -657,30 +2417,32 @@ impl<'drawbuffers> std::fmt::Debug for DrawCommand<'drawbuffers> {
             DrawCommand::DrawLines {
                 transform,
                 mesh,
-                texture_array_info,
+                texture,
                 framebuffer,
-                ..
+                render_state,
             } => write!(
                 f,
-                "\n  DrawLines:\n  {:?}\n  num_verts: {:?}\n  {:?}\n  {:?}",
+                "\n  DrawLines:\n  {:?}\n  num_verts: {:?}\n  {:?}\n  {:?}\n  {:?}",
                 transform,
                 mesh.vertices.len(),
-                texture_array_info,
-                framebuffer
+                texture,
+                framebuffer,
+                render_state
             ),
             DrawCommand::DrawPolys {
                 transform,
                 mesh,
-                texture_array_info,
+                texture,
                 framebuffer,
-                ..
+                render_state,
             } => write!(
                 f,
-                "\n  DrawPolys:\n  {:?}\n  num_verts: {:?}\n  {:?}\n  {:?}",
+                "\n  DrawPolys:\n  {:?}\n  num_verts: {:?}\n  {:?}\n  {:?}\n  {:?}",
                 transform,
                 mesh.vertices.len(),
-                texture_array_info,
-                framebuffer
+                texture,
+                framebuffer,
+                render_state
             ),
             DrawCommand::CreateTextureArray {
                 texture_array_info,
@@ -722,6 +2484,26 @@ impl<'drawbuffers> std::fmt::Debug for DrawCommand<'drawbuffers> {
                 source_rect,
                 target_rect,
             ),
+            DrawCommand::PostProcess {
+                source_framebuffer,
+                secondary_framebuffer,
+                color_grading_lut,
+                target_framebuffer,
+                target_rect,
+                effect,
+            } => write!(
+                f,
+                concat!(
+                    "\n  PostProcess: {:?}\n  source: {:?}\n  secondary: {:?}",
+                    "\n  lut: {:?}\n  target: {:?}\n  target: {:?}"
+                ),
+                effect,
+                source_framebuffer,
+                secondary_framebuffer,
+                color_grading_lut,
+                target_framebuffer,
+                target_rect,
+            ),
             DrawCommand::CreateFramebuffer { framebuffer_info } => {
                 write!(f, "\n  CreateFramebuffer: {:?}", framebuffer_info,)
             }
@@ -731,17 +2513,61 @@ impl<'drawbuffers> std::fmt::Debug for DrawCommand<'drawbuffers> {
             DrawCommand::DeleteTextureArray { texture_array_info } => {
                 write!(f, "\n  DeleteTexture: {:?}", texture_array_info,)
             }
+            DrawCommand::CreateShader {
+                shader_info,
+                vertex_shader_source,
+                fragment_shader_source,
+            } => write!(
+                f,
+                concat!(
+                    "\n  CreateShader: {:?}\n  custom vertex shader: {:?}",
+                    "\n  fragment shader length: {:?}"
+                ),
+                shader_info,
+                vertex_shader_source.is_some(),
+                fragment_shader_source.len()
+            ),
+            DrawCommand::DeleteShader { shader_info } => {
+                write!(f, "\n  DeleteShader: {:?}", shader_info)
+            }
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FramebufferTarget {
     Screen,
     Offscreen(FramebufferInfo),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+impl FramebufferTarget {
+    pub fn name(&self) -> &str {
+        match self {
+            FramebufferTarget::Screen => "Screen",
+            FramebufferTarget::Offscreen(framebuffer_info) => &framebuffer_info.name,
+        }
+    }
+}
+
+/// The texture that the geometry of a draw command samples from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DrawTexture {
+    TextureArray(TextureArrayInfo),
+    /// The color texture of an offscreen framebuffer. The texture coordinates of the geometry
+    /// need to be in the range [0, 1] with (0, 0) being the bottom left of the framebuffer.
+    Framebuffer(FramebufferInfo),
+}
+
+impl DrawTexture {
+    pub fn name(&self) -> &str {
+        match self {
+            DrawTexture::TextureArray(texture_array_info) => &texture_array_info.name,
+            DrawTexture::Framebuffer(framebuffer_info) => &framebuffer_info.name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TextureArrayInfo {
     pub id: u32,
     pub width: u16,
@@ -750,7 +2576,13 @@ pub struct TextureArrayInfo {
     pub name: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ShaderInfo {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FramebufferInfo {
     pub id: u32,
     pub width: u16,
@@ -783,7 +2615,7 @@ pub trait Mesh {
 // -------------------------------------------------------------------------------------------------
 // LineMesh
 //
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct LineMesh {
     vertices: Vec<Vertex>,
     indices: Vec<VertexIndex>,
@@ -842,10 +2674,13 @@ impl LineMesh {
 // -------------------------------------------------------------------------------------------------
 // PolygonMesh
 //
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct PolygonMesh {
     vertices: Vec<Vertex>,
     indices: Vec<VertexIndex>,
+    /// Only used for statistics
+    #[serde(skip)]
+    num_quads: usize,
 }
 
 impl Mesh for PolygonMesh {
@@ -856,6 +2691,7 @@ impl Mesh for PolygonMesh {
     fn clear(&mut self) {
         self.vertices.clear();
         self.indices.clear();
+        self.num_quads = 0;
     }
 
     fn to_vertices_indices(&self) -> (&[Vertex], &[VertexIndex]) {
@@ -902,35 +2738,583 @@ impl PolygonMesh {
             },
         ];
 
-        let quad_vertex_index = self.vertices.len() as VertexIndex;
-        let quad_indices = [
-            quad_vertex_index,
-            quad_vertex_index + 1,
-            quad_vertex_index + 2,
-            quad_vertex_index + 2,
-            quad_vertex_index + 3,
-            quad_vertex_index,
-        ];
+        let quad_vertex_index = self.next_vertex_index();
+        let quad_indices = [
+            quad_vertex_index,
+            quad_vertex_index.wrapping_add(1),
+            quad_vertex_index.wrapping_add(2),
+            quad_vertex_index.wrapping_add(2),
+            quad_vertex_index.wrapping_add(3),
+            quad_vertex_index,
+        ];
+
+        self.vertices.extend_from_slice(&quad_vertices);
+        self.indices.extend(&quad_indices);
+        self.num_quads += 1;
+    }
+
+    /// Pushes triangles given by an index list. The indices are relative to the given vertices,
+    /// where every three consecutive indices form one triangle.
+    pub fn push_triangles(&mut self, vertices: &[Vertex], indices: &[VertexIndex]) {
+        debug_assert!(indices.len() % 3 == 0);
+        debug_assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+
+        let first_vertex_index = self.next_vertex_index();
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(
+            indices
+                .iter()
+                .map(|&index| first_vertex_index.wrapping_add(index)),
+        );
+    }
+
+    /// Pushes a convex polygon as a triangle fan. All vertices share the same uv coordinate which
+    /// is meant for untextured shapes.
+    pub fn push_convex_polygon(
+        &mut self,
+        points: &[Point],
+        uv: Point,
+        atlas_index: u32,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+    ) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let color = color.into();
+        let atlas_index = atlas_index as f32;
+        let first_vertex_index = self.next_vertex_index();
+        self.vertices.extend(points.iter().map(|point| Vertex {
+            pos: [point.x, point.y, depth, 1.0],
+            uv: [uv.x, uv.y, atlas_index],
+            color,
+            additivity,
+        }));
+
+        let num_triangles = points.len() - 2;
+        self.indices.reserve(3 * num_triangles);
+        for index in 1..=num_triangles as VertexIndex {
+            self.indices.extend_from_slice(&[
+                first_vertex_index,
+                first_vertex_index.wrapping_add(index),
+                first_vertex_index.wrapping_add(index + 1),
+            ]);
+        }
+    }
+
+    /// Returns the index of the next pushed vertex. This wraps around if the mesh holds more
+    /// vertices than a `VertexIndex` can address. Draw batches then move the overflowing shape
+    /// into a new mesh and fix up its indices.
+    fn next_vertex_index(&self) -> VertexIndex {
+        (self.vertices.len() % MAX_VERTICES_PER_MESH) as VertexIndex
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// DrawBatch
+//
+
+/// The range of vertices and indices in one of the translucent meshes that belong to one
+/// translucent shape
+#[derive(Debug, Clone, Copy)]
+struct TranslucentShape {
+    depth: f32,
+    mesh_index: usize,
+    first_vertex: usize,
+    num_vertices: usize,
+    first_index: usize,
+    num_indices: usize,
+}
+
+/// Collects the geometry of one render layer of a draw space that uses the same render state.
+///
+/// Opaque shapes are drawn in submission order and rely on the depthbuffer. Translucent shapes
+/// need to blend with everything behind them and are therefore collected separately and sorted
+/// back-to-front before they are drawn on top of the opaque shapes.
+///
+/// The geometry is stored in multiple meshes if it has more vertices than a single mesh can
+/// address with a `VertexIndex`. Each mesh is drawn with its own draw call.
+// NOTE: We only know a shape is translucent by its color and additivity. Our sprites are expected
+//       to be either fully opaque or fully transparent per pixel as fully transparent pixels are
+//       discarded in the shader.
+#[derive(Default)]
+struct DrawBatch {
+    render_state: RenderState,
+    /// Sampled instead of the atlas if set
+    framebuffer_texture: Option<FramebufferInfo>,
+    opaque_polygons: Vec<PolygonMesh>,
+    translucent_polygons: Vec<PolygonMesh>,
+    translucent_shapes: Vec<TranslucentShape>,
+    lines: Vec<LineMesh>,
+    /// Only used for statistics
+    num_glyphs: usize,
+}
+
+impl DrawBatch {
+    /// Clears all geometry but keeps the first mesh of each kind around to reuse its allocations
+    fn clear(&mut self) {
+        self.render_state = RenderState::default();
+        self.framebuffer_texture = None;
+        self.opaque_polygons.truncate(1);
+        self.opaque_polygons.iter_mut().for_each(Mesh::clear);
+        self.translucent_polygons.truncate(1);
+        self.translucent_polygons.iter_mut().for_each(Mesh::clear);
+        self.translucent_shapes.clear();
+        self.lines.truncate(1);
+        self.lines.iter_mut().for_each(Mesh::clear);
+        self.num_glyphs = 0;
+    }
+
+    fn stats(&self) -> DrawSpaceStats {
+        let polygon_meshes = self
+            .opaque_polygons
+            .iter()
+            .chain(self.translucent_polygons.iter());
+        DrawSpaceStats {
+            num_quads: polygon_meshes.clone().map(|mesh| mesh.num_quads).sum(),
+            num_triangles: polygon_meshes.map(|mesh| mesh.indices.len() / 3).sum(),
+            num_lines: self.lines.iter().map(|mesh| mesh.indices.len() / 2).sum(),
+            num_glyphs: self.num_glyphs,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.opaque_polygons
+            .iter()
+            .chain(self.translucent_polygons.iter())
+            .all(|mesh| mesh.vertices.is_empty())
+            && self.lines.iter().all(|mesh| mesh.vertices.is_empty())
+    }
+
+    /// The number of meshes beyond the first mesh of each kind
+    fn num_mesh_splits(&self) -> usize {
+        self.opaque_polygons.len().saturating_sub(1)
+            + self.translucent_polygons.len().saturating_sub(1)
+            + self.lines.len().saturating_sub(1)
+    }
+
+    fn push_line(
+        &mut self,
+        line: Line,
+        line_uv: Line,
+        atlas_index: u32,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+    ) {
+        let has_room = self
+            .lines
+            .last()
+            .map(|mesh| mesh.vertices.len() + 2 <= MAX_VERTICES_PER_MESH)
+            .unwrap_or(false);
+        if !has_room {
+            self.lines.push(LineMesh::new());
+        }
+        let mesh = self.lines.last_mut().expect("Line mesh does not exist");
+        mesh.push_line(line, line_uv, atlas_index, depth, color, additivity);
+    }
+
+    /// Lets the given closure push a single shape into the mesh that matches the translucency of
+    /// the shape
+    fn push_shape<F>(&mut self, depth: f32, is_translucent: bool, push: F)
+    where
+        F: FnOnce(&mut PolygonMesh),
+    {
+        if is_translucent {
+            let (mesh_index, first_vertex, first_index) =
+                push_shape_into_meshes(&mut self.translucent_polygons, push);
+            let mesh = &self.translucent_polygons[mesh_index];
+            self.translucent_shapes.push(TranslucentShape {
+                depth,
+                mesh_index,
+                first_vertex,
+                num_vertices: mesh.vertices.len() - first_vertex,
+                first_index,
+                num_indices: mesh.indices.len() - first_index,
+            });
+        } else {
+            push_shape_into_meshes(&mut self.opaque_polygons, push);
+        }
+    }
+
+    fn push_quad(
+        &mut self,
+        rect: Rect,
+        rect_uv: Rect,
+        atlas_index: u32,
+        depth: f32,
+        color: Color,
+        additivity: f32,
+    ) {
+        self.push_shape(depth, is_translucent(color, additivity), |mesh| {
+            mesh.push_quad(rect, rect_uv, atlas_index, depth, color, additivity)
+        });
+    }
+
+    /// Reorders the translucent shapes so that they are drawn from back to front. Shapes with the
+    /// same depth keep their submission order.
+    fn sort_translucent_shapes(&mut self) {
+        // NOTE: Smaller depth values are further away from the camera
+        self.translucent_shapes
+            .sort_by(|a, b| a.depth.partial_cmp(&b.depth).unwrap_or(Ordering::Equal));
 
-        self.vertices.extend_from_slice(&quad_vertices);
-        self.indices.extend(&quad_indices);
+        if self.translucent_polygons.len() > 1 {
+            self.rebuild_translucent_meshes_in_shape_order();
+            return;
+        }
+
+        // NOTE: With a single mesh it is enough to reorder its indices
+        let mesh = match self.translucent_polygons.first_mut() {
+            Some(mesh) => mesh,
+            None => return,
+        };
+        let num_indices = mesh.indices.len();
+        let unsorted_indices =
+            std::mem::replace(&mut mesh.indices, Vec::with_capacity(num_indices));
+        for shape in &mut self.translucent_shapes {
+            let shape_indices =
+                &unsorted_indices[shape.first_index..shape.first_index + shape.num_indices];
+            shape.first_index = mesh.indices.len();
+            mesh.indices.extend_from_slice(shape_indices);
+        }
+    }
+
+    /// Copies the translucent shapes into new meshes in their current order. This is necessary
+    /// when the shapes are spread over multiple meshes because we draw the meshes one after
+    /// another.
+    fn rebuild_translucent_meshes_in_shape_order(&mut self) {
+        let unsorted_meshes = std::mem::replace(&mut self.translucent_polygons, Vec::new());
+        for shape in &mut self.translucent_shapes {
+            let mesh = &unsorted_meshes[shape.mesh_index];
+            let vertices =
+                &mesh.vertices[shape.first_vertex..shape.first_vertex + shape.num_vertices];
+            let first_vertex_index = shape.first_vertex as VertexIndex;
+            let indices: Vec<VertexIndex> = mesh.indices
+                [shape.first_index..shape.first_index + shape.num_indices]
+                .iter()
+                .map(|&index| index - first_vertex_index)
+                .collect();
+
+            let (mesh_index, first_vertex, first_index) =
+                push_shape_into_meshes(&mut self.translucent_polygons, |mesh| {
+                    mesh.push_triangles(vertices, &indices)
+                });
+            shape.mesh_index = mesh_index;
+            shape.first_vertex = first_vertex;
+            shape.first_index = first_index;
+        }
     }
 }
 
+/// Lets the given closure push a single shape into the last of the given meshes. If the mesh
+/// overflows, the shape is moved into a new mesh. Returns the index of the mesh that holds the
+/// shape and the positions of the first vertex and the first index of the shape in that mesh.
+fn push_shape_into_meshes<F>(meshes: &mut Vec<PolygonMesh>, push: F) -> (usize, usize, usize)
+where
+    F: FnOnce(&mut PolygonMesh),
+{
+    if meshes.is_empty() {
+        meshes.push(PolygonMesh::new());
+    }
+    let mesh = meshes.last_mut().expect("Polygon mesh does not exist");
+    let first_vertex = mesh.vertices.len();
+    let first_index = mesh.indices.len();
+    push(mesh);
+
+    let num_shape_vertices = mesh.vertices.len() - first_vertex;
+    debug_assert!(
+        num_shape_vertices <= MAX_VERTICES_PER_MESH,
+        "Shape with {} vertices does not fit into a single mesh",
+        num_shape_vertices
+    );
+    if mesh.vertices.len() <= MAX_VERTICES_PER_MESH || first_vertex == 0 {
+        return (meshes.len() - 1, first_vertex, first_index);
+    }
+
+    // NOTE: The indices of the shape have wrapped around. Subtracting the wrapped index of its
+    //       first vertex makes them relative to the new mesh again.
+    let vertices = mesh.vertices.split_off(first_vertex);
+    let first_vertex_index = (first_vertex % MAX_VERTICES_PER_MESH) as VertexIndex;
+    let indices = mesh
+        .indices
+        .split_off(first_index)
+        .into_iter()
+        .map(|index| index.wrapping_sub(first_vertex_index))
+        .collect();
+    // NOTE: The quad count of the shape stays with the old mesh which is fine for our statistics
+    meshes.push(PolygonMesh {
+        vertices,
+        indices,
+        num_quads: 0,
+    });
+    (meshes.len() - 1, 0, 0)
+}
+
 //==================================================================================================
 // Sprite
 //==================================================================================================
 //
 
+/// The asset packer generates a magenta checkerboard sprite with this resource path that we show
+/// in place of sprites and animations that do not exist
+pub const MISSING_SPRITE_RESOURCE_PATH: &str = "images/missing";
+pub const DEFAULT_FONT_RESOURCE_PATH: &str = "fonts/default";
+/// The font of the profiler, draw inspector and debug draw overlays
+pub const DEBUG_FONT_RESOURCE_PATH: &str = "fonts/default_borderless";
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct AtlasMeta {
     pub num_atlas_textures: usize,
     pub fonts: HashMap<crate::ResourcePath, Font>,
     pub animations: HashMap<crate::ResourcePath, Animation>,
     pub sprites: HashMap<crate::ResourcePath, Sprite>,
+    pub nine_slices: HashMap<crate::ResourcePath, NineSlice>,
+    pub tilemaps: HashMap<crate::ResourcePath, Tilemap>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+// NOTE: Handles can be requested before the atlas was loaded. In this case we hand out
+//       placeholders without complaining as all handles are resolved again when the atlas is loaded.
+impl AtlasMeta {
+    fn is_loaded(&self) -> bool {
+        self.num_atlas_textures > 0
+    }
+
+    fn missing_sprite(&self) -> Sprite {
+        *self
+            .sprites
+            .get(MISSING_SPRITE_RESOURCE_PATH)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Atlas does not contain the fallback sprite '{}'",
+                    MISSING_SPRITE_RESOURCE_PATH
+                )
+            })
+    }
+
+    fn sprite_or_fallback(&self, resource_path: &str) -> Sprite {
+        if !self.is_loaded() {
+            return Sprite::default();
+        }
+        self.sprites.get(resource_path).cloned().unwrap_or_else(|| {
+            warn!(
+                "Sprite '{}' does not exist - using fallback sprite instead",
+                resource_path
+            );
+            self.missing_sprite()
+        })
+    }
+
+    fn font_or_fallback(&self, resource_path: &str) -> Font {
+        if !self.is_loaded() {
+            return Font::default();
+        }
+        self.fonts.get(resource_path).cloned().unwrap_or_else(|| {
+            warn!(
+                "Font '{}' does not exist - using font '{}' instead",
+                resource_path, DEFAULT_FONT_RESOURCE_PATH
+            );
+            self.fonts
+                .get(DEFAULT_FONT_RESOURCE_PATH)
+                .cloned()
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Atlas does not contain the fallback font '{}'",
+                        DEFAULT_FONT_RESOURCE_PATH
+                    )
+                })
+        })
+    }
+
+    fn animation_or_fallback(&self, resource_path: &str) -> Animation {
+        if !self.is_loaded() {
+            return Animation::default();
+        }
+        self.animations
+            .get(resource_path)
+            .cloned()
+            .unwrap_or_else(|| {
+                warn!(
+                    "Animation '{}' does not exist - using fallback sprite instead",
+                    resource_path
+                );
+                Animation {
+                    frame_durations: vec![1000.0],
+                    frames: vec![self.missing_sprite()],
+                }
+            })
+    }
+
+    fn nine_slice_or_fallback(&self, resource_path: &str) -> NineSlice {
+        if !self.is_loaded() {
+            return NineSlice::default();
+        }
+        self.nine_slices
+            .get(resource_path)
+            .cloned()
+            .unwrap_or_else(|| {
+                warn!(
+                    "Nine-slice '{}' does not exist - using fallback sprite instead",
+                    resource_path
+                );
+                NineSlice {
+                    sprite: self.missing_sprite(),
+                    insets: NineSliceInsets::default(),
+                }
+            })
+    }
+
+    fn tilemap_or_fallback(&self, resource_path: &str) -> Tilemap {
+        if !self.is_loaded() {
+            return Tilemap::default();
+        }
+        self.tilemaps
+            .get(resource_path)
+            .cloned()
+            .unwrap_or_else(|| {
+                warn!(
+                    "Tilemap '{}' does not exist - using empty tilemap instead",
+                    resource_path
+                );
+                Tilemap::default()
+            })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpriteHandle(usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontHandle(usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationHandle(usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NineSliceHandle(usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TilemapHandle(usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderTargetHandle(usize);
+
+// NOTE: The framebuffer ids below are used by the canvas and the post-processing framebuffers
+const FIRST_RENDER_TARGET_FRAMEBUFFER_ID: u32 = 16;
+
+/// An offscreen framebuffer that game code can draw into and then draw like a sprite
+struct RenderTarget {
+    name: String,
+    /// Is `None` if the render target was deleted
+    framebuffer_info: Option<FramebufferInfo>,
+    transform: Mat4,
+    clear_color: Option<Color>,
+    is_drawn_to: bool,
+    batches: [Vec<DrawBatch>; NUM_RENDER_LAYERS],
+}
+
+impl RenderTarget {
+    fn new(name: &str) -> RenderTarget {
+        RenderTarget {
+            name: String::from(name),
+            framebuffer_info: None,
+            transform: Mat4::from_scale(1.0),
+            clear_color: None,
+            is_drawn_to: false,
+            batches: Default::default(),
+        }
+    }
+
+    fn samples_framebuffer(&self, framebuffer_info: &FramebufferInfo) -> bool {
+        self.batches.iter().flatten().any(|batch| {
+            !batch.is_empty() && batch.framebuffer_texture.as_ref() == Some(framebuffer_info)
+        })
+    }
+}
+
+/// Returns the indices of the render targets that were drawn to in this frame. A render target
+/// comes after all render targets that it samples. For render targets that sample each other in a
+/// cycle, the render target that closes the cycle samples the content of the previous frame.
+fn render_target_draw_order(render_targets: &[RenderTarget]) -> Vec<usize> {
+    let mut is_visited = vec![false; render_targets.len()];
+    let mut draw_order = Vec::with_capacity(render_targets.len());
+    for index in 0..render_targets.len() {
+        visit_render_target(index, render_targets, &mut is_visited, &mut draw_order);
+    }
+    draw_order
+}
+
+fn visit_render_target(
+    index: usize,
+    render_targets: &[RenderTarget],
+    is_visited: &mut [bool],
+    draw_order: &mut Vec<usize>,
+) {
+    if is_visited[index] {
+        return;
+    }
+    is_visited[index] = true;
+
+    let render_target = &render_targets[index];
+    if !render_target.is_drawn_to || render_target.framebuffer_info.is_none() {
+        return;
+    }
+    for (sampled_index, sampled_render_target) in render_targets.iter().enumerate() {
+        let is_sampled = match &sampled_render_target.framebuffer_info {
+            Some(framebuffer_info) => render_target.samples_framebuffer(framebuffer_info),
+            None => false,
+        };
+        if is_sampled {
+            visit_render_target(sampled_index, render_targets, is_visited, draw_order);
+        }
+    }
+    draw_order.push(index);
+}
+
+/// Maps resource paths to stable indices. This way our handles stay valid when the atlas is
+/// reloaded and we only need to hash a resource path once when creating a handle.
+#[derive(Default)]
+struct ResourceTable<T> {
+    indices: HashMap<crate::ResourcePath, usize>,
+    resource_paths: Vec<crate::ResourcePath>,
+    resources: Vec<T>,
+}
+
+impl<T> ResourceTable<T> {
+    fn get_or_insert_with<F>(&mut self, resource_path: &str, resolve: F) -> usize
+    where
+        F: FnOnce(&str) -> T,
+    {
+        if let Some(&index) = self.indices.get(resource_path) {
+            return index;
+        }
+        let index = self.resources.len();
+        self.indices.insert(resource_path.to_owned(), index);
+        self.resource_paths.push(resource_path.to_owned());
+        self.resources.push(resolve(resource_path));
+        index
+    }
+
+    fn resolve_all<F>(&mut self, resolve: F)
+    where
+        F: Fn(&str) -> T,
+    {
+        self.resources = self
+            .resource_paths
+            .iter()
+            .map(|resource_path| resolve(resource_path))
+            .collect();
+    }
+
+    fn get(&self, index: usize) -> &T {
+        &self.resources[index]
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct Animation {
     pub frame_durations: Vec<f32>,
     pub frames: Vec<Sprite>,
@@ -940,7 +3324,11 @@ pub struct Animation {
 pub struct Font {
     pub font_height: f32,
     pub vertical_advance: f32,
-    pub glyphs: Vec<Glyph>,
+    /// Distance from the top of a glyph sprite to the baseline of the font
+    pub baseline: f32,
+    pub glyphs: HashMap<char, Glyph>,
+    /// Used for characters that were not baked into the font
+    pub fallback_glyph: Glyph,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -965,52 +3353,196 @@ impl Sprite {
         }
     }
 
+    /// Returns the transformed vertices in the order bottom-left, bottom-right, top-right,
+    /// top-left which matches the order of `PolygonMesh::push_quad`
     pub fn into_vertices(
         self,
-        pos: WorldPoint,
+        transform: SpriteTransform,
         depth: f32,
-        color: Color,
+        colors: QuadColors,
         additivity: f32,
     ) -> [Vertex; 4] {
         let vertex = self.vertex_bounds;
         let uv = self.uv_bounds;
         let atlas_index = self.atlas_index as f32;
-        let color = color.into();
-
-        [
-            Vertex {
-                pos: [pos.x + vertex.left, pos.y + vertex.bottom, depth, 1.0],
-                uv: [uv.left, uv.bottom, atlas_index],
-                color,
-                additivity,
-            },
-            Vertex {
-                pos: [pos.x + vertex.right, pos.y + vertex.bottom, depth, 1.0],
-                uv: [uv.right, uv.bottom, atlas_index],
-                color,
-                additivity,
-            },
+        let corner_vertex = |corner: Point, uv_corner: Point, color: Color| {
+            let pos = transform.transform_point(corner);
             Vertex {
-                pos: [pos.x + vertex.right, pos.y + vertex.top, depth, 1.0],
-                uv: [uv.right, uv.top, atlas_index],
-                color,
-                additivity,
-            },
-            Vertex {
-                pos: [pos.x + vertex.left, pos.y + vertex.top, depth, 1.0],
-                uv: [uv.left, uv.top, atlas_index],
-                color,
+                pos: [pos.x, pos.y, depth, 1.0],
+                uv: [uv_corner.x, uv_corner.y, atlas_index],
+                color: color.into(),
                 additivity,
-            },
+            }
+        };
+
+        [
+            corner_vertex(
+                Point::new(vertex.left, vertex.bottom),
+                Point::new(uv.left, uv.bottom),
+                colors.bottom_left,
+            ),
+            corner_vertex(
+                Point::new(vertex.right, vertex.bottom),
+                Point::new(uv.right, uv.bottom),
+                colors.bottom_right,
+            ),
+            corner_vertex(
+                Point::new(vertex.right, vertex.top),
+                Point::new(uv.right, uv.top),
+                colors.top_right,
+            ),
+            corner_vertex(
+                Point::new(vertex.left, vertex.top),
+                Point::new(uv.left, uv.top),
+                colors.top_left,
+            ),
+        ]
+    }
+}
+
+/// The size of the non-scaling borders of a nine-slice sprite in pixels
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct NineSliceInsets {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct NineSlice {
+    pub sprite: Sprite,
+    pub insets: NineSliceInsets,
+}
+
+/// Determines how the edges and the center of a nine-slice sprite fill their space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NineSliceMode {
+    Stretch,
+    /// Repeats the edges and the center in their original size. The last tile is cut off if
+    /// it does not fit completely.
+    Tile,
+}
+
+/// Places a sprite relative to its pivot. The sprite is first scaled and flipped, then rotated
+/// around its pivot and finally moved to its position.
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteTransform {
+    pub pos: WorldPoint,
+    /// Rotation in radians. Positive angles rotate counter-clockwise on screen.
+    pub rotation: f32,
+    pub scale: Vec2,
+    pub flip_horizontally: bool,
+    pub flip_vertically: bool,
+}
+
+impl Default for SpriteTransform {
+    fn default() -> SpriteTransform {
+        SpriteTransform::from_pos(Point::zero())
+    }
+}
+
+impl SpriteTransform {
+    pub fn from_pos(pos: WorldPoint) -> SpriteTransform {
+        SpriteTransform {
+            pos,
+            rotation: 0.0,
+            scale: Vec2::ones(),
+            flip_horizontally: false,
+            flip_vertically: false,
+        }
+    }
+
+    pub fn with_rotation(self, rotation: f32) -> SpriteTransform {
+        SpriteTransform { rotation, ..self }
+    }
+
+    pub fn with_scale(self, scale: Vec2) -> SpriteTransform {
+        SpriteTransform { scale, ..self }
+    }
+
+    pub fn with_flip(self, flip_horizontally: bool, flip_vertically: bool) -> SpriteTransform {
+        SpriteTransform {
+            flip_horizontally,
+            flip_vertically,
+            ..self
+        }
+    }
+
+    /// Transforms a point given relative to the pivot of a sprite
+    pub fn transform_point(&self, point: Point) -> Point {
+        // NOTE: Flipping is the same as mirroring the sprite on the axes through its pivot
+        let flip = Vec2::new(
+            if self.flip_horizontally { -1.0 } else { 1.0 },
+            if self.flip_vertically { -1.0 } else { 1.0 },
+        );
+        self.pos + (point * self.scale * flip).rotated(self.rotation)
+    }
+}
+
+/// The colors of the corners of a quad relative to the untransformed sprite. The colors are
+/// interpolated between the corners which allows for simple gradients.
+#[derive(Debug, Clone, Copy)]
+pub struct QuadColors {
+    pub top_left: Color,
+    pub top_right: Color,
+    pub bottom_left: Color,
+    pub bottom_right: Color,
+}
+
+impl QuadColors {
+    pub fn uniform(color: Color) -> QuadColors {
+        QuadColors {
+            top_left: color,
+            top_right: color,
+            bottom_left: color,
+            bottom_right: color,
+        }
+    }
+
+    pub fn horizontal_gradient(left: Color, right: Color) -> QuadColors {
+        QuadColors {
+            top_left: left,
+            top_right: right,
+            bottom_left: left,
+            bottom_right: right,
+        }
+    }
+
+    pub fn vertical_gradient(top: Color, bottom: Color) -> QuadColors {
+        QuadColors {
+            top_left: top,
+            top_right: top,
+            bottom_left: bottom,
+            bottom_right: bottom,
+        }
+    }
+
+    pub fn to_array(self) -> [Color; 4] {
+        [
+            self.top_left,
+            self.top_right,
+            self.bottom_left,
+            self.bottom_right,
         ]
     }
 }
 
+impl From<Color> for QuadColors {
+    fn from(color: Color) -> QuadColors {
+        QuadColors::uniform(color)
+    }
+}
+
 //==================================================================================================
 // Helper functions
 //==================================================================================================
 //
 // TODO(JaSc): Find a place for these graphic/geometry helper functions
+fn is_translucent(color: Color, additivity: f32) -> bool {
+    color.w < 1.0 || additivity > ADDITIVITY_NONE
+}
+
 fn rect_uv_to_line_uv(rect_uv: Rect) -> Line {
     // NOTE: We use only the horizontal axis of a sprite's uv
     Line::new(
@@ -1058,6 +3590,417 @@ pub fn vertices_from_rects(
     ]
 }
 
+// NOTE: A miter is replaced by a bevel if it would stick out further than this multiple of
+//       the half line thickness from its joint
+const LINE_MITER_LIMIT: f32 = 4.0;
+const LINE_ROUND_CAP_MAX_SEGMENTS: usize = 16;
+
+/// Converts a thick polyline into convex polygons that are passed to the given closure. These
+/// are the joins followed by the segments and caps.
+///
+/// Adjacent polygons share their edges and do not overlap so that translucent lines are blended
+/// evenly. Only if a segment is too short to fit the inner corner of a sharp join we fall back to
+/// letting the segments overlap at the inner side of that join.
+fn polyline_to_convex_polygons<F>(points: &[Point], is_closed: bool, style: LineStyle, mut emit: F)
+where
+    F: FnMut(&[Point]),
+{
+    // Zero length segments have no direction so we remove them beforehand
+    let mut points: Vec<Point> = points.to_vec();
+    points.dedup_by(|a, b| (*a - *b).is_effectively_zero());
+    if is_closed && points.len() > 2 && (points[0] - points[points.len() - 1]).is_effectively_zero()
+    {
+        points.pop();
+    }
+    let is_closed = is_closed && points.len() > 2;
+    let num_points = points.len();
+    if num_points < 2 {
+        return;
+    }
+
+    let half_thickness = 0.5 * style.thickness;
+    let num_segments = if is_closed {
+        num_points
+    } else {
+        num_points - 1
+    };
+    let segment_dir =
+        |index: usize| (points[(index + 1) % num_points] - points[index]).normalized();
+    let segment_length =
+        |index: usize| (points[(index + 1) % num_points] - points[index]).magnitude();
+
+    // Joins
+    //
+    // NOTE: For every joint we remember on which side of its segments the outer side of the turn
+    //       lies and where the inner edges of its segments intersect. The segments are shortened
+    //       to that intersection on their inner side.
+    let mut joint_inner_corners: Vec<Option<(f32, Point)>> = vec![None; num_points];
+    let (first_joint, last_joint) = if is_closed {
+        (0, num_points)
+    } else {
+        (1, num_points - 1)
+    };
+    for index in first_joint..last_joint {
+        let previous_segment = (index + num_points - 1) % num_points;
+        let previous_dir = segment_dir(previous_segment);
+        let next_dir = segment_dir(index);
+        let joint = points[index];
+
+        let previous_normal = previous_dir.perpendicular();
+        if is_effectively_zero(Vec2::cross(previous_dir, next_dir))
+            && Vec2::dot(previous_dir, next_dir) > 0.0
+        {
+            // The segments are collinear and need no join
+            continue;
+        }
+
+        // The join is only needed on the outer side of the turn
+        let outer_side = if Vec2::dot(next_dir, previous_normal) > 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let previous_offset = outer_side * half_thickness * previous_normal;
+        let next_offset = outer_side * half_thickness * next_dir.perpendicular();
+
+        let miter_dir = previous_offset + next_offset;
+        let (miter_dir, miter_length) = if miter_dir.is_effectively_zero() {
+            (miter_dir, std::f32::INFINITY)
+        } else {
+            let miter_dir = miter_dir.normalized();
+            let miter_length =
+                half_thickness * half_thickness / Vec2::dot(miter_dir, previous_offset);
+            (miter_dir, miter_length)
+        };
+
+        // NOTE: The inner corner must not reach past the middle of the adjacent segments or it
+        //       could collide with the inner corner of the neighbouring joint
+        let inner_corner = joint - miter_length * miter_dir;
+        let inner_corner_fits = miter_length.is_finite()
+            && Vec2::dot(joint - inner_corner, previous_dir)
+                <= 0.5 * segment_length(previous_segment)
+            && Vec2::dot(inner_corner - joint, next_dir) <= 0.5 * segment_length(index);
+        let join_base = if inner_corner_fits {
+            joint_inner_corners[index] = Some((outer_side, inner_corner));
+            inner_corner
+        } else {
+            joint
+        };
+
+        if style.join == LineJoin::Miter
+            && miter_length.is_finite()
+            && miter_length <= LINE_MITER_LIMIT * half_thickness
+        {
+            emit(&[
+                join_base,
+                joint + previous_offset,
+                joint + miter_length * miter_dir,
+                joint + next_offset,
+            ]);
+        } else {
+            emit(&[join_base, joint + previous_offset, joint + next_offset]);
+        }
+    }
+
+    // Segments
+    for index in 0..num_segments {
+        let dir = segment_dir(index);
+        let normal = half_thickness * dir.perpendicular();
+        let end_index = (index + 1) % num_points;
+        let mut start = points[index];
+        let mut end = points[end_index];
+        if !is_closed && style.cap == LineCap::Square {
+            if index == 0 {
+                start -= half_thickness * dir;
+            }
+            if index == num_segments - 1 {
+                end += half_thickness * dir;
+            }
+        }
+
+        let mut polygon = [start - normal, end - normal, end + normal, start + normal];
+        if let Some((outer_side, inner_corner)) = joint_inner_corners[index] {
+            let inner_start_vertex = if outer_side > 0.0 { 0 } else { 3 };
+            polygon[inner_start_vertex] = inner_corner;
+        }
+        if let Some((outer_side, inner_corner)) = joint_inner_corners[end_index] {
+            let inner_end_vertex = if outer_side > 0.0 { 1 } else { 2 };
+            polygon[inner_end_vertex] = inner_corner;
+        }
+        emit(&polygon);
+    }
+
+    // Caps
+    if !is_closed && style.cap == LineCap::Round {
+        let num_cap_segments = usize::min(
+            LINE_ROUND_CAP_MAX_SEGMENTS,
+            usize::max(4, (0.5 * PI * half_thickness).ceil() as usize),
+        );
+        let caps = [
+            (points[0], -segment_dir(0)),
+            (points[num_points - 1], segment_dir(num_segments - 1)),
+        ];
+        for (endpoint, outward_dir) in caps.iter() {
+            let normal = half_thickness * outward_dir.perpendicular();
+            let cap_points: Vec<Point> = (0..=num_cap_segments)
+                .map(|segment_index| {
+                    let angle = PI * segment_index as f32 / num_cap_segments as f32;
+                    *endpoint
+                        + f32::cos(angle) * normal
+                        + f32::sin(angle) * half_thickness * *outward_dir
+                })
+                .collect();
+            emit(&cap_points);
+        }
+    }
+}
+
+/// One piece of a nine-slice sprite along one axis
+#[derive(Debug, Clone, Copy)]
+struct NineSliceSegment {
+    start: f32,
+    end: f32,
+    uv_start: f32,
+    uv_end: f32,
+}
+
+/// Splits a nine-slice sprite along one axis into the pieces that cover the given target range
+fn nine_slice_segments(
+    (target_start, target_end): (f32, f32),
+    (uv_start, uv_end): (f32, f32),
+    source_length: f32,
+    (inset_start, inset_end): (f32, f32),
+    mode: NineSliceMode,
+) -> Vec<NineSliceSegment> {
+    let target_length = target_end - target_start;
+    if target_length <= 0.0 || source_length <= 0.0 {
+        return Vec::new();
+    }
+    let uv_per_pixel = (uv_end - uv_start) / source_length;
+    let source_center_length = source_length - inset_start - inset_end;
+
+    // Shrink the borders if they do not fit into the target
+    let border_scale = f32::min(1.0, target_length / (inset_start + inset_end));
+    let target_inset_start = inset_start * border_scale;
+    let target_inset_end = inset_end * border_scale;
+    let target_center_start = target_start + target_inset_start;
+    let target_center_end = target_end - target_inset_end;
+
+    let uv_center_start = uv_start + inset_start * uv_per_pixel;
+    let uv_center_end = uv_end - inset_end * uv_per_pixel;
+
+    let mut segments = vec![NineSliceSegment {
+        start: target_start,
+        end: target_center_start,
+        uv_start,
+        uv_end: uv_center_start,
+    }];
+    if target_center_end > target_center_start {
+        match mode {
+            NineSliceMode::Tile if source_center_length > 0.0 => {
+                let mut tile_start = target_center_start;
+                while tile_start < target_center_end {
+                    let tile_end = f32::min(tile_start + source_center_length, target_center_end);
+                    segments.push(NineSliceSegment {
+                        start: tile_start,
+                        end: tile_end,
+                        uv_start: uv_center_start,
+                        uv_end: uv_center_start + (tile_end - tile_start) * uv_per_pixel,
+                    });
+                    tile_start = tile_end;
+                }
+            }
+            _ => segments.push(NineSliceSegment {
+                start: target_center_start,
+                end: target_center_end,
+                uv_start: uv_center_start,
+                uv_end: uv_center_end,
+            }),
+        }
+    }
+    segments.push(NineSliceSegment {
+        start: target_center_end,
+        end: target_end,
+        uv_start: uv_center_end,
+        uv_end,
+    });
+
+    segments.retain(|segment| segment.end > segment.start);
+    segments
+}
+
+fn ellipse_points(center: Point, radii: Vec2, num_segments: usize) -> Vec<Point> {
+    let num_segments = usize::max(3, num_segments);
+    (0..num_segments)
+        .map(|index| {
+            let angle = 2.0 * PI * index as f32 / num_segments as f32;
+            center + Vec2::new(radii.x * f32::cos(angle), radii.y * f32::sin(angle))
+        })
+        .collect()
+}
+
+/// Returns the outline of a rounded rect where `num_segments` is the number of segments of all
+/// four corners together
+fn rounded_rect_points(rect: Rect, corner_radius: f32, num_segments: usize) -> Vec<Point> {
+    let corner_radius = clamp(
+        corner_radius,
+        0.0,
+        0.5 * f32::min(rect.width(), rect.height()),
+    );
+    let num_corner_segments = usize::max(1, num_segments / 4);
+    let corners = [
+        (Point::new(rect.left, rect.top), Vec2::new(1.0, 1.0), PI),
+        (
+            Point::new(rect.right, rect.top),
+            Vec2::new(-1.0, 1.0),
+            1.5 * PI,
+        ),
+        (
+            Point::new(rect.right, rect.bottom),
+            Vec2::new(-1.0, -1.0),
+            0.0,
+        ),
+        (
+            Point::new(rect.left, rect.bottom),
+            Vec2::new(1.0, -1.0),
+            0.5 * PI,
+        ),
+    ];
+
+    let mut points = Vec::new();
+    for (corner, inward_dir, start_angle) in corners.iter() {
+        let arc_center = *corner + corner_radius * *inward_dir;
+        for index in 0..=num_corner_segments {
+            let angle = start_angle + 0.5 * PI * index as f32 / num_corner_segments as f32;
+            points.push(arc_center + corner_radius * Vec2::new(f32::cos(angle), f32::sin(angle)));
+        }
+    }
+    points
+}
+
+/// A shape that can be rasterized row by row
+#[derive(Debug, Clone, Copy)]
+enum RasterShape {
+    Ellipse { center: Point, radii: Vec2 },
+    RoundedRect { rect: Rect, corner_radius: f32 },
+}
+
+impl RasterShape {
+    fn vertical_bounds(&self) -> (f32, f32) {
+        match *self {
+            RasterShape::Ellipse { center, radii } => (center.y - radii.y, center.y + radii.y),
+            RasterShape::RoundedRect { rect, .. } => (rect.top, rect.bottom),
+        }
+    }
+
+    /// Returns the left and right border of the shape at the given height if the shape covers it
+    fn horizontal_bounds_at(&self, y: f32) -> Option<(f32, f32)> {
+        match *self {
+            RasterShape::Ellipse { center, radii } => {
+                let dy = (y - center.y) / radii.y;
+                if dy.abs() > 1.0 {
+                    return None;
+                }
+                let half_width = radii.x * f32::sqrt(1.0 - dy * dy);
+                Some((center.x - half_width, center.x + half_width))
+            }
+            RasterShape::RoundedRect {
+                rect,
+                corner_radius,
+            } => {
+                if y < rect.top || y > rect.bottom {
+                    return None;
+                }
+                let corner_radius = clamp(
+                    corner_radius,
+                    0.0,
+                    0.5 * f32::min(rect.width(), rect.height()),
+                );
+                let dy = f32::max(
+                    0.0,
+                    f32::max(
+                        rect.top + corner_radius - y,
+                        y - rect.bottom + corner_radius,
+                    ),
+                );
+                let inset = corner_radius - f32::sqrt(corner_radius * corner_radius - dy * dy);
+                Some((rect.left + inset, rect.right - inset))
+            }
+        }
+    }
+
+    /// Returns the shape with its border moved inwards by the given amount or `None` if nothing
+    /// would be left of the shape
+    fn shrunk_by(&self, amount: f32) -> Option<RasterShape> {
+        match *self {
+            RasterShape::Ellipse { center, radii } => {
+                let radii = radii - Vec2::ones() * amount;
+                if radii.x <= 0.0 || radii.y <= 0.0 {
+                    return None;
+                }
+                Some(RasterShape::Ellipse { center, radii })
+            }
+            RasterShape::RoundedRect {
+                rect,
+                corner_radius,
+            } => {
+                let rect = rect.extended_uniformly_by(-amount);
+                if rect.width() <= 0.0 || rect.height() <= 0.0 {
+                    return None;
+                }
+                Some(RasterShape::RoundedRect {
+                    rect,
+                    corner_radius: f32::max(0.0, corner_radius - amount),
+                })
+            }
+        }
+    }
+}
+
+/// Rasterizes a shape into pixel-aligned rects that are passed to the given closure. A pixel is
+/// covered if its center lies inside the shape. If an outline thickness is given only the pixels
+/// within that distance to the border of the shape are covered.
+fn rasterized_shape_to_rects<F>(shape: RasterShape, outline_thickness: Option<f32>, mut emit: F)
+where
+    F: FnMut(Rect),
+{
+    // Returns the first and last pixel column whose centers lie inside the given bounds
+    let covered_columns =
+        |(left, right): (f32, f32)| ((left - 0.5).ceil() as i32, (right - 0.5).floor() as i32);
+    let mut emit_columns = |first_column: i32, last_column: i32, row: i32| {
+        if first_column <= last_column {
+            emit(Rect::from_bounds(
+                first_column as f32,
+                (last_column + 1) as f32,
+                (row + 1) as f32,
+                row as f32,
+            ));
+        }
+    };
+
+    let inner_shape = outline_thickness.and_then(|thickness| shape.shrunk_by(thickness));
+    let (top, bottom) = shape.vertical_bounds();
+    let first_row = (top - 0.5).ceil() as i32;
+    let last_row = (bottom - 0.5).floor() as i32;
+    for row in first_row..=last_row {
+        let y = row as f32 + 0.5;
+        let (first_column, last_column) = match shape.horizontal_bounds_at(y) {
+            Some(bounds) => covered_columns(bounds),
+            None => continue,
+        };
+        let inner_bounds = inner_shape.and_then(|inner_shape| inner_shape.horizontal_bounds_at(y));
+        match inner_bounds.map(covered_columns) {
+            Some((first_inner_column, last_inner_column))
+                if first_inner_column <= last_inner_column =>
+            {
+                emit_columns(first_column, first_inner_column - 1, row);
+                emit_columns(last_inner_column + 1, last_column, row);
+            }
+            _ => emit_columns(first_column, last_column, row),
+        }
+    }
+}
+
 /// Returns line segments for drawing in the following order left, right, top, bottom.
 fn draw_outlines_from_rect(rect: Rect) -> [Line; 4] {
     [
@@ -1083,3 +4026,68 @@ fn draw_outlines_from_rect(rect: Rect) -> [Line; 4] {
         ),
     ]
 }
+
+//==================================================================================================
+// Unit tests
+//==================================================================================================
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_quads(batch: &mut DrawBatch, num_quads: usize, depth: f32, color: Color) {
+        for _ in 0..num_quads {
+            batch.push_quad(Rect::unit_rect(), Rect::unit_rect(), 0, depth, color, 0.0);
+        }
+    }
+
+    fn assert_indices_in_bounds(meshes: &[PolygonMesh]) {
+        for mesh in meshes {
+            assert!(mesh.vertices.len() <= MAX_VERTICES_PER_MESH);
+            assert!(mesh
+                .indices
+                .iter()
+                .all(|&index| (index as usize) < mesh.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn batches_split_into_multiple_meshes_when_exceeding_the_vertex_limit() {
+        let mut batch = DrawBatch::default();
+        let num_quads = MAX_VERTICES_PER_MESH / 4 + 10;
+        push_quads(&mut batch, num_quads, 0.0, COLOR_WHITE);
+
+        assert_eq!(batch.opaque_polygons.len(), 2);
+        assert_eq!(batch.opaque_polygons[1].vertices.len(), 10 * 4);
+        assert_eq!(batch.num_mesh_splits(), 1);
+        assert_indices_in_bounds(&batch.opaque_polygons);
+
+        batch.clear();
+        assert_eq!(batch.num_mesh_splits(), 0);
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn translucent_shapes_stay_sorted_over_multiple_meshes() {
+        let mut batch = DrawBatch::default();
+        let translucent = Color::new(1.0, 1.0, 1.0, 0.5);
+        let num_quads = MAX_VERTICES_PER_MESH / 4;
+        push_quads(&mut batch, num_quads, -0.1, translucent);
+        push_quads(&mut batch, num_quads, -0.9, translucent);
+        assert_eq!(batch.translucent_polygons.len(), 2);
+
+        batch.sort_translucent_shapes();
+        assert_indices_in_bounds(&batch.translucent_polygons);
+        let depths: Vec<f32> = batch
+            .translucent_polygons
+            .iter()
+            .flat_map(|mesh| {
+                mesh.indices
+                    .iter()
+                    .map(move |&index| mesh.vertices[index as usize])
+            })
+            .map(|vertex| vertex.pos[2])
+            .collect();
+        assert!(depths.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
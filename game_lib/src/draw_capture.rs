@@ -0,0 +1,688 @@
+use crate::draw::{
+    DrawCommand, DrawTexture, FramebufferInfo, FramebufferTarget, LineMesh, Pixel, PolygonMesh,
+    RenderState, ShaderInfo, TextureArrayInfo,
+};
+use crate::math::Rect;
+use crate::postprocess::PostProcessEffect;
+
+use bincode;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+/// Is increased whenever the layout of `DrawCapture` changes so that old captures are rejected
+/// instead of being misinterpreted
+pub const DRAW_CAPTURE_VERSION: u32 = 1;
+
+//==================================================================================================
+// CapturedDrawCommand
+//==================================================================================================
+//
+
+/// A `DrawCommand` that owns all of its data. Matrices and colors are stored as plain arrays
+/// because cgmath types are not serializable.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum CapturedDrawCommand {
+    DrawLines {
+        transform: [[f32; 4]; 4],
+        mesh: LineMesh,
+        texture: DrawTexture,
+        framebuffer: FramebufferTarget,
+        render_state: RenderState,
+    },
+    DrawPolys {
+        transform: [[f32; 4]; 4],
+        mesh: PolygonMesh,
+        texture: DrawTexture,
+        framebuffer: FramebufferTarget,
+        render_state: RenderState,
+    },
+    Clear {
+        framebuffer: FramebufferTarget,
+        color: [f32; 4],
+        depth: f32,
+    },
+    ClearColor {
+        framebuffer: FramebufferTarget,
+        color: [f32; 4],
+    },
+    ClearDepth {
+        framebuffer: FramebufferTarget,
+        depth: f32,
+    },
+    BlitFramebuffer {
+        source_framebuffer: FramebufferInfo,
+        target_framebuffer: FramebufferTarget,
+        source_rect: Rect,
+        target_rect: Rect,
+    },
+    PostProcess {
+        source_framebuffer: FramebufferInfo,
+        secondary_framebuffer: Option<FramebufferInfo>,
+        color_grading_lut: Option<TextureArrayInfo>,
+        target_framebuffer: FramebufferTarget,
+        target_rect: Rect,
+        effect: PostProcessEffect,
+    },
+    CreateFramebuffer {
+        framebuffer_info: FramebufferInfo,
+    },
+    DeleteFramebuffer {
+        framebuffer_info: FramebufferInfo,
+    },
+    CreateTextureArray {
+        texture_array_info: TextureArrayInfo,
+        /// The RGBA components of the pixels of every texture
+        pixels: Vec<Vec<[u8; 4]>>,
+    },
+    DeleteTextureArray {
+        texture_array_info: TextureArrayInfo,
+    },
+    CreateShader {
+        shader_info: ShaderInfo,
+        vertex_shader_source: Option<String>,
+        fragment_shader_source: String,
+    },
+    DeleteShader {
+        shader_info: ShaderInfo,
+    },
+}
+
+impl CapturedDrawCommand {
+    pub fn from_draw_command(command: &DrawCommand) -> CapturedDrawCommand {
+        match command {
+            DrawCommand::DrawLines {
+                transform,
+                mesh,
+                texture,
+                framebuffer,
+                render_state,
+            } => CapturedDrawCommand::DrawLines {
+                transform: (*transform).into(),
+                mesh: (*mesh).clone(),
+                texture: texture.clone(),
+                framebuffer: framebuffer.clone(),
+                render_state: render_state.clone(),
+            },
+            DrawCommand::DrawPolys {
+                transform,
+                mesh,
+                texture,
+                framebuffer,
+                render_state,
+            } => CapturedDrawCommand::DrawPolys {
+                transform: (*transform).into(),
+                mesh: (*mesh).clone(),
+                texture: texture.clone(),
+                framebuffer: framebuffer.clone(),
+                render_state: render_state.clone(),
+            },
+            DrawCommand::Clear {
+                framebuffer,
+                color,
+                depth,
+            } => CapturedDrawCommand::Clear {
+                framebuffer: framebuffer.clone(),
+                color: (*color).into(),
+                depth: *depth,
+            },
+            DrawCommand::ClearColor { framebuffer, color } => CapturedDrawCommand::ClearColor {
+                framebuffer: framebuffer.clone(),
+                color: (*color).into(),
+            },
+            DrawCommand::ClearDepth { framebuffer, depth } => CapturedDrawCommand::ClearDepth {
+                framebuffer: framebuffer.clone(),
+                depth: *depth,
+            },
+            DrawCommand::BlitFramebuffer {
+                source_framebuffer,
+                target_framebuffer,
+                source_rect,
+                target_rect,
+            } => CapturedDrawCommand::BlitFramebuffer {
+                source_framebuffer: source_framebuffer.clone(),
+                target_framebuffer: target_framebuffer.clone(),
+                source_rect: *source_rect,
+                target_rect: *target_rect,
+            },
+            DrawCommand::PostProcess {
+                source_framebuffer,
+                secondary_framebuffer,
+                color_grading_lut,
+                target_framebuffer,
+                target_rect,
+                effect,
+            } => CapturedDrawCommand::PostProcess {
+                source_framebuffer: source_framebuffer.clone(),
+                secondary_framebuffer: secondary_framebuffer.clone(),
+                color_grading_lut: color_grading_lut.clone(),
+                target_framebuffer: target_framebuffer.clone(),
+                target_rect: *target_rect,
+                effect: *effect,
+            },
+            DrawCommand::CreateFramebuffer { framebuffer_info } => {
+                CapturedDrawCommand::CreateFramebuffer {
+                    framebuffer_info: framebuffer_info.clone(),
+                }
+            }
+            DrawCommand::DeleteFramebuffer { framebuffer_info } => {
+                CapturedDrawCommand::DeleteFramebuffer {
+                    framebuffer_info: framebuffer_info.clone(),
+                }
+            }
+            DrawCommand::CreateTextureArray {
+                texture_array_info,
+                pixels,
+            } => CapturedDrawCommand::CreateTextureArray {
+                texture_array_info: texture_array_info.clone(),
+                pixels: pixels
+                    .iter()
+                    .map(|texture_pixels| {
+                        texture_pixels
+                            .iter()
+                            .map(|pixel| [pixel.r, pixel.g, pixel.b, pixel.a])
+                            .collect()
+                    })
+                    .collect(),
+            },
+            DrawCommand::DeleteTextureArray { texture_array_info } => {
+                CapturedDrawCommand::DeleteTextureArray {
+                    texture_array_info: texture_array_info.clone(),
+                }
+            }
+            DrawCommand::CreateShader {
+                shader_info,
+                vertex_shader_source,
+                fragment_shader_source,
+            } => CapturedDrawCommand::CreateShader {
+                shader_info: shader_info.clone(),
+                vertex_shader_source: vertex_shader_source.clone(),
+                fragment_shader_source: fragment_shader_source.clone(),
+            },
+            DrawCommand::DeleteShader { shader_info } => CapturedDrawCommand::DeleteShader {
+                shader_info: shader_info.clone(),
+            },
+        }
+    }
+
+    /// Creates a `DrawCommand` that borrows the meshes of this command and can be processed by
+    /// any rendering backend
+    pub fn to_draw_command(&self) -> DrawCommand {
+        match self {
+            CapturedDrawCommand::DrawLines {
+                transform,
+                mesh,
+                texture,
+                framebuffer,
+                render_state,
+            } => DrawCommand::DrawLines {
+                transform: (*transform).into(),
+                mesh,
+                texture: texture.clone(),
+                framebuffer: framebuffer.clone(),
+                render_state: render_state.clone(),
+            },
+            CapturedDrawCommand::DrawPolys {
+                transform,
+                mesh,
+                texture,
+                framebuffer,
+                render_state,
+            } => DrawCommand::DrawPolys {
+                transform: (*transform).into(),
+                mesh,
+                texture: texture.clone(),
+                framebuffer: framebuffer.clone(),
+                render_state: render_state.clone(),
+            },
+            CapturedDrawCommand::Clear {
+                framebuffer,
+                color,
+                depth,
+            } => DrawCommand::Clear {
+                framebuffer: framebuffer.clone(),
+                color: (*color).into(),
+                depth: *depth,
+            },
+            CapturedDrawCommand::ClearColor { framebuffer, color } => DrawCommand::ClearColor {
+                framebuffer: framebuffer.clone(),
+                color: (*color).into(),
+            },
+            CapturedDrawCommand::ClearDepth { framebuffer, depth } => DrawCommand::ClearDepth {
+                framebuffer: framebuffer.clone(),
+                depth: *depth,
+            },
+            CapturedDrawCommand::BlitFramebuffer {
+                source_framebuffer,
+                target_framebuffer,
+                source_rect,
+                target_rect,
+            } => DrawCommand::BlitFramebuffer {
+                source_framebuffer: source_framebuffer.clone(),
+                target_framebuffer: target_framebuffer.clone(),
+                source_rect: *source_rect,
+                target_rect: *target_rect,
+            },
+            CapturedDrawCommand::PostProcess {
+                source_framebuffer,
+                secondary_framebuffer,
+                color_grading_lut,
+                target_framebuffer,
+                target_rect,
+                effect,
+            } => DrawCommand::PostProcess {
+                source_framebuffer: source_framebuffer.clone(),
+                secondary_framebuffer: secondary_framebuffer.clone(),
+                color_grading_lut: color_grading_lut.clone(),
+                target_framebuffer: target_framebuffer.clone(),
+                target_rect: *target_rect,
+                effect: *effect,
+            },
+            CapturedDrawCommand::CreateFramebuffer { framebuffer_info } => {
+                DrawCommand::CreateFramebuffer {
+                    framebuffer_info: framebuffer_info.clone(),
+                }
+            }
+            CapturedDrawCommand::DeleteFramebuffer { framebuffer_info } => {
+                DrawCommand::DeleteFramebuffer {
+                    framebuffer_info: framebuffer_info.clone(),
+                }
+            }
+            CapturedDrawCommand::CreateTextureArray {
+                texture_array_info,
+                pixels,
+            } => DrawCommand::CreateTextureArray {
+                texture_array_info: texture_array_info.clone(),
+                pixels: pixels
+                    .iter()
+                    .map(|texture_pixels| {
+                        texture_pixels
+                            .iter()
+                            .map(|&[r, g, b, a]| Pixel::new(r, g, b, a))
+                            .collect()
+                    })
+                    .collect(),
+            },
+            CapturedDrawCommand::DeleteTextureArray { texture_array_info } => {
+                DrawCommand::DeleteTextureArray {
+                    texture_array_info: texture_array_info.clone(),
+                }
+            }
+            CapturedDrawCommand::CreateShader {
+                shader_info,
+                vertex_shader_source,
+                fragment_shader_source,
+            } => DrawCommand::CreateShader {
+                shader_info: shader_info.clone(),
+                vertex_shader_source: vertex_shader_source.clone(),
+                fragment_shader_source: fragment_shader_source.clone(),
+            },
+            CapturedDrawCommand::DeleteShader { shader_info } => DrawCommand::DeleteShader {
+                shader_info: shader_info.clone(),
+            },
+        }
+    }
+
+    /// Returns true if both commands create the same resource
+    fn creates_same_resource_as(&self, other: &CapturedDrawCommand) -> bool {
+        match (self, other) {
+            (
+                CapturedDrawCommand::CreateFramebuffer { framebuffer_info },
+                CapturedDrawCommand::CreateFramebuffer {
+                    framebuffer_info: other_info,
+                },
+            ) => framebuffer_info == other_info,
+            (
+                CapturedDrawCommand::CreateTextureArray {
+                    texture_array_info, ..
+                },
+                CapturedDrawCommand::CreateTextureArray {
+                    texture_array_info: other_info,
+                    ..
+                },
+            ) => texture_array_info == other_info,
+            (
+                CapturedDrawCommand::CreateShader { shader_info, .. },
+                CapturedDrawCommand::CreateShader {
+                    shader_info: other_info,
+                    ..
+                },
+            ) => shader_info == other_info,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this command creates the resource that the given command deletes
+    fn creates_resource_deleted_by(&self, command: &DrawCommand) -> bool {
+        match (self, command) {
+            (
+                CapturedDrawCommand::CreateFramebuffer { framebuffer_info },
+                DrawCommand::DeleteFramebuffer {
+                    framebuffer_info: deleted_info,
+                },
+            ) => framebuffer_info == deleted_info,
+            (
+                CapturedDrawCommand::CreateTextureArray {
+                    texture_array_info, ..
+                },
+                DrawCommand::DeleteTextureArray {
+                    texture_array_info: deleted_info,
+                },
+            ) => texture_array_info == deleted_info,
+            (
+                CapturedDrawCommand::CreateShader { shader_info, .. },
+                DrawCommand::DeleteShader {
+                    shader_info: deleted_info,
+                },
+            ) => shader_info == deleted_info,
+            _ => false,
+        }
+    }
+}
+
+//==================================================================================================
+// DrawCapture
+//==================================================================================================
+//
+
+/// The draw commands of consecutive frames together with the commands that create all resources
+/// that were alive before or created during the capture. This makes a capture replayable on its
+/// own.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DrawCapture {
+    pub version: u32,
+    pub resource_commands: Vec<CapturedDrawCommand>,
+    pub frames: Vec<Vec<CapturedDrawCommand>>,
+}
+
+impl DrawCapture {
+    pub fn new(resource_commands: Vec<CapturedDrawCommand>) -> DrawCapture {
+        DrawCapture {
+            version: DRAW_CAPTURE_VERSION,
+            resource_commands,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<DrawCapture, bincode::Error> {
+        let capture: DrawCapture = bincode::deserialize(bytes)?;
+        capture.check_version()?;
+        Ok(capture)
+    }
+
+    pub fn save_to_file(&self, filename: &str) -> Result<(), bincode::Error> {
+        let file = File::create(filename)?;
+        bincode::serialize_into(BufWriter::new(file), self)
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<DrawCapture, bincode::Error> {
+        let file = File::open(filename)?;
+        let capture: DrawCapture = bincode::deserialize_from(BufReader::new(file))?;
+        capture.check_version()?;
+        Ok(capture)
+    }
+
+    fn check_version(&self) -> Result<(), bincode::Error> {
+        if self.version != DRAW_CAPTURE_VERSION {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "Draw capture has version {} but expected version {}",
+                self.version, DRAW_CAPTURE_VERSION
+            ))));
+        }
+        Ok(())
+    }
+}
+
+//==================================================================================================
+// DrawCaptureRecorder
+//==================================================================================================
+//
+
+/// Sees the draw commands of every frame and keeps track of all resources that are alive. When a
+/// capture is started it records the requested number of frames.
+#[derive(Default)]
+pub struct DrawCaptureRecorder {
+    resource_commands: Vec<CapturedDrawCommand>,
+    capture: Option<DrawCapture>,
+    num_frames_left: usize,
+}
+
+impl DrawCaptureRecorder {
+    pub fn new() -> DrawCaptureRecorder {
+        Default::default()
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Captures the given number of frames starting with the next call to `record_frame`
+    pub fn start_capture(&mut self, num_frames: usize) {
+        if self.is_capturing() || num_frames == 0 {
+            return;
+        }
+        self.capture = Some(DrawCapture::new(self.resource_commands.clone()));
+        self.num_frames_left = num_frames;
+    }
+
+    /// Needs to be called with the draw commands of every frame, even when not capturing.
+    /// Returns the finished capture after its last frame was recorded.
+    pub fn record_frame(&mut self, draw_commands: &[DrawCommand]) -> Option<DrawCapture> {
+        if let Some(capture) = &mut self.capture {
+            // NOTE: Resources created while capturing are moved in front of the first frame and
+            //       are never deleted. Otherwise looping the replay would create them twice or
+            //       draw with them after they were deleted. A resource that is re-created while
+            //       capturing replaces its previous version.
+            let mut frame = Vec::with_capacity(draw_commands.len());
+            for command in draw_commands {
+                match command {
+                    DrawCommand::CreateFramebuffer { .. }
+                    | DrawCommand::CreateTextureArray { .. }
+                    | DrawCommand::CreateShader { .. } => {
+                        let resource = CapturedDrawCommand::from_draw_command(command);
+                        match capture
+                            .resource_commands
+                            .iter_mut()
+                            .find(|existing| existing.creates_same_resource_as(&resource))
+                        {
+                            Some(existing) => *existing = resource,
+                            None => capture.resource_commands.push(resource),
+                        }
+                    }
+                    DrawCommand::DeleteFramebuffer { .. }
+                    | DrawCommand::DeleteTextureArray { .. }
+                    | DrawCommand::DeleteShader { .. } => {}
+                    _ => frame.push(CapturedDrawCommand::from_draw_command(command)),
+                }
+            }
+            capture.frames.push(frame);
+            self.num_frames_left -= 1;
+        }
+
+        for command in draw_commands {
+            match command {
+                DrawCommand::CreateFramebuffer { .. }
+                | DrawCommand::CreateTextureArray { .. }
+                | DrawCommand::CreateShader { .. } => self
+                    .resource_commands
+                    .push(CapturedDrawCommand::from_draw_command(command)),
+                DrawCommand::DeleteFramebuffer { .. }
+                | DrawCommand::DeleteTextureArray { .. }
+                | DrawCommand::DeleteShader { .. } => self
+                    .resource_commands
+                    .retain(|resource| !resource.creates_resource_deleted_by(command)),
+                _ => {}
+            }
+        }
+
+        if self.num_frames_left == 0 {
+            self.capture.take()
+        } else {
+            None
+        }
+    }
+}
+
+//==================================================================================================
+// DrawCaptureReplayer
+//==================================================================================================
+//
+
+/// Plays back the frames of a capture in a loop
+pub struct DrawCaptureReplayer {
+    capture: DrawCapture,
+    next_frame_index: usize,
+    resources_were_created: bool,
+}
+
+impl DrawCaptureReplayer {
+    pub fn new(capture: DrawCapture) -> DrawCaptureReplayer {
+        DrawCaptureReplayer {
+            capture,
+            next_frame_index: 0,
+            resources_were_created: false,
+        }
+    }
+
+    /// Returns the draw commands of the next frame. The first frame is preceded by the commands
+    /// that create the resources of the capture.
+    pub fn next_frame(&mut self) -> Vec<DrawCommand> {
+        let mut draw_commands = Vec::new();
+        if !self.resources_were_created {
+            self.resources_were_created = true;
+            draw_commands.extend(
+                self.capture
+                    .resource_commands
+                    .iter()
+                    .map(CapturedDrawCommand::to_draw_command),
+            );
+        }
+        if let Some(frame) = self.capture.frames.get(self.next_frame_index) {
+            draw_commands.extend(frame.iter().map(CapturedDrawCommand::to_draw_command));
+            self.next_frame_index = (self.next_frame_index + 1) % self.capture.frames.len();
+        }
+        draw_commands
+    }
+}
+
+//==================================================================================================
+// Unit tests
+//==================================================================================================
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw::Mesh;
+    use crate::math::{Color, Line, Mat4, Point};
+
+    #[test]
+    fn captured_frame_survives_encoding_and_replay() {
+        let texture_array_info = TextureArrayInfo {
+            id: 0,
+            width: 1,
+            height: 1,
+            num_textures: 1,
+            name: String::from("atlas"),
+        };
+        let mut mesh = LineMesh::new();
+        mesh.push_line(
+            Line::new(Point::zero(), Point::new(10.0, 5.0)),
+            Line::new(Point::zero(), Point::zero()),
+            0,
+            -0.5,
+            Color::new(1.0, 0.5, 0.25, 1.0),
+            0.0,
+        );
+        let transform = Mat4::from_scale(2.0);
+        let draw_commands = vec![
+            DrawCommand::CreateTextureArray {
+                texture_array_info: texture_array_info.clone(),
+                pixels: vec![vec![Pixel::new(1, 2, 3, 4)]],
+            },
+            DrawCommand::DrawLines {
+                transform,
+                mesh: &mesh,
+                texture: DrawTexture::TextureArray(texture_array_info.clone()),
+                framebuffer: FramebufferTarget::Screen,
+                render_state: RenderState::default(),
+            },
+        ];
+
+        let mut recorder = DrawCaptureRecorder::new();
+        recorder.record_frame(&draw_commands[..1]);
+        recorder.start_capture(1);
+        let capture = recorder.record_frame(&draw_commands[1..]).unwrap();
+        assert!(!recorder.is_capturing());
+
+        let capture = DrawCapture::decode(&capture.encode().unwrap()).unwrap();
+        let mut replayer = DrawCaptureReplayer::new(capture);
+        let replayed_commands = replayer.next_frame();
+        assert_eq!(replayed_commands.len(), 2);
+        match &replayed_commands[0] {
+            DrawCommand::CreateTextureArray { pixels, .. } => {
+                assert_eq!(pixels[0][0], Pixel::new(1, 2, 3, 4))
+            }
+            _ => panic!("Expected the texture array to be created first"),
+        }
+        match &replayed_commands[1] {
+            DrawCommand::DrawLines {
+                transform: replayed_transform,
+                mesh: replayed_mesh,
+                ..
+            } => {
+                assert_eq!(*replayed_transform, transform);
+                let (vertices, indices) = replayed_mesh.to_vertices_indices();
+                assert_eq!(indices, mesh.to_vertices_indices().1);
+                assert_eq!(vertices[1].pos, [10.0, 5.0, -0.5, 1.0]);
+            }
+            _ => panic!("Expected the lines to be drawn after creating the texture array"),
+        }
+
+        // NOTE: Resources are only created once while the frames are looped
+        assert_eq!(replayer.next_frame().len(), 1);
+    }
+
+    #[test]
+    fn resources_created_while_capturing_are_created_before_the_first_frame() {
+        let texture_array_info = TextureArrayInfo {
+            id: 0,
+            width: 1,
+            height: 1,
+            num_textures: 1,
+            name: String::from("atlas"),
+        };
+        let create_command = |pixel| DrawCommand::CreateTextureArray {
+            texture_array_info: texture_array_info.clone(),
+            pixels: vec![vec![pixel]],
+        };
+        let delete_command = DrawCommand::DeleteTextureArray {
+            texture_array_info: texture_array_info.clone(),
+        };
+
+        let mut recorder = DrawCaptureRecorder::new();
+        recorder.start_capture(2);
+        recorder.record_frame(&[create_command(Pixel::new(1, 2, 3, 4))]);
+        let capture = recorder
+            .record_frame(&[delete_command, create_command(Pixel::new(5, 6, 7, 8))])
+            .unwrap();
+        assert_eq!(capture.resource_commands.len(), 1);
+        assert!(capture.frames.iter().all(|frame| frame.is_empty()));
+
+        let mut replayer = DrawCaptureReplayer::new(capture);
+        let replayed_commands = replayer.next_frame();
+        assert_eq!(replayed_commands.len(), 1);
+        match &replayed_commands[0] {
+            DrawCommand::CreateTextureArray { pixels, .. } => {
+                assert_eq!(pixels[0][0], Pixel::new(5, 6, 7, 8))
+            }
+            _ => panic!("Expected the re-created texture array"),
+        }
+        for _ in 0..3 {
+            assert!(replayer.next_frame().is_empty());
+        }
+    }
+}
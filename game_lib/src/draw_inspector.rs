@@ -0,0 +1,186 @@
+use crate::draw::{self, DrawCommandSummary, DrawContext, DrawSpace, DrawSpaceStats, DrawStats};
+use crate::math::{Color, Point, Rect};
+use crate::GameInput;
+
+const OVERLAY_DEPTH_BACKGROUND: f32 = -0.3;
+const OVERLAY_DEPTH_SELECTION: f32 = -0.2;
+const OVERLAY_DEPTH_TEXT: f32 = 0.0;
+const OVERLAY_DEPTH_HIGHLIGHT: f32 = 0.0;
+
+const OVERLAY_COLOR_BACKGROUND: Color = Color {
+    x: 0.0,
+    y: 0.0,
+    z: 0.0,
+    w: 0.7,
+};
+const OVERLAY_COLOR_SELECTION: Color = Color {
+    x: 0.2,
+    y: 0.4,
+    z: 0.8,
+    w: 1.0,
+};
+const OVERLAY_COLOR_HIGHLIGHT: Color = Color {
+    x: 1.0,
+    y: 0.0,
+    z: 1.0,
+    w: 1.0,
+};
+
+/// Shows the rendering stats and the draw command stream of a frame. A frame can be frozen to
+/// step through its draw commands while the area that each command drew to is highlighted.
+#[derive(Default)]
+pub struct DrawInspector {
+    is_visible: bool,
+    is_frozen: bool,
+    stats: DrawStats,
+    command_summaries: Vec<DrawCommandSummary>,
+    selected_command_index: Option<usize>,
+}
+
+impl DrawInspector {
+    pub fn is_visible(&self) -> bool {
+        self.is_visible
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.is_frozen
+    }
+
+    /// Takes over the stats and draw commands of the last frame unless the inspected frame is
+    /// frozen. This needs to be called before anything is drawn in the current frame.
+    pub fn update(&mut self, input: &GameInput, dc: &mut DrawContext) {
+        self.is_visible = input.is_pressed("debug_draw_inspector_toggle");
+        self.is_frozen = self.is_visible && input.is_pressed("debug_draw_inspector_freeze_toggle");
+        dc.set_recording_command_summaries(self.is_visible);
+
+        if !self.is_frozen {
+            self.stats = *dc.stats();
+            self.command_summaries.clear();
+            self.command_summaries
+                .extend_from_slice(dc.command_summaries());
+            self.selected_command_index = None;
+            return;
+        }
+
+        let num_commands = self.command_summaries.len();
+        if num_commands == 0 {
+            return;
+        }
+        if input.had_press_event("debug_draw_inspector_next_oneshot") {
+            self.selected_command_index = Some(match self.selected_command_index {
+                Some(index) => (index + 1) % num_commands,
+                None => 0,
+            });
+        }
+        if input.had_press_event("debug_draw_inspector_previous_oneshot") {
+            self.selected_command_index = Some(match self.selected_command_index {
+                Some(index) => (index + num_commands - 1) % num_commands,
+                None => num_commands - 1,
+            });
+        }
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Debug drawing
+    //
+
+    /// Draws the stats and as many draw commands as fit into the given rect. The selected command
+    /// is kept in view and the area it drew to is outlined.
+    pub fn debug_draw_overlay(&self, dc: &mut DrawContext, rect: Rect) {
+        dc.draw_rect_filled(
+            rect,
+            OVERLAY_DEPTH_BACKGROUND,
+            OVERLAY_COLOR_BACKGROUND,
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        );
+
+        let stats = &self.stats;
+        let mut lines = vec![
+            format!(
+                "{} - {} commands",
+                if self.is_frozen {
+                    "frozen frame"
+                } else {
+                    "last frame"
+                },
+                self.command_summaries.len()
+            ),
+            format!(
+                "batches: {} draw calls: {} texture switches: {} clears: {}",
+                stats.num_batches,
+                stats.num_draw_calls,
+                stats.num_texture_switches,
+                stats.num_framebuffer_clears
+            ),
+            draw_space_stats_line("world", &stats.world),
+            draw_space_stats_line("canvas", &stats.canvas),
+            draw_space_stats_line("debug", &stats.debug),
+            draw_space_stats_line("targets", &stats.render_targets),
+        ];
+        let num_stats_lines = lines.len();
+
+        let font = dc.font_handle(draw::DEBUG_FONT_RESOURCE_PATH);
+        let line_height = dc.font(font).vertical_advance;
+        let max_num_lines = (rect.height() / line_height) as usize;
+        let max_num_command_lines = max_num_lines.saturating_sub(num_stats_lines);
+
+        // NOTE: We scroll the command list so that the selected command stays in the lower half
+        let first_command_index = self
+            .selected_command_index
+            .map(|index| index.saturating_sub(max_num_command_lines / 2))
+            .unwrap_or(0);
+        lines.extend(
+            self.command_summaries
+                .iter()
+                .enumerate()
+                .skip(first_command_index)
+                .take(max_num_command_lines)
+                .map(|(index, summary)| format!("{:4} {}", index, summary.description)),
+        );
+
+        for (line_index, line) in lines.iter().enumerate() {
+            let line_top = rect.top + line_index as f32 * line_height;
+            let command_index = (line_index + first_command_index).checked_sub(num_stats_lines);
+            if line_index >= num_stats_lines && command_index == self.selected_command_index {
+                dc.draw_rect_filled(
+                    Rect::from_bounds(rect.left, rect.right, line_top + line_height, line_top),
+                    OVERLAY_DEPTH_SELECTION,
+                    OVERLAY_COLOR_SELECTION,
+                    draw::ADDITIVITY_NONE,
+                    DrawSpace::Debug,
+                );
+            }
+            dc.draw_text(
+                Point::new(rect.left + 1.0, line_top),
+                line,
+                font,
+                OVERLAY_DEPTH_TEXT,
+                draw::COLOR_WHITE,
+                draw::ADDITIVITY_NONE,
+                DrawSpace::Debug,
+            );
+        }
+
+        let selected_bounds = self
+            .selected_command_index
+            .and_then(|index| self.command_summaries.get(index))
+            .and_then(|summary| summary.bounds);
+        if let Some((draw_space, bounds)) = selected_bounds {
+            dc.draw_rect(
+                bounds,
+                OVERLAY_DEPTH_HIGHLIGHT,
+                OVERLAY_COLOR_HIGHLIGHT,
+                draw::ADDITIVITY_NONE,
+                draw_space,
+            );
+        }
+    }
+}
+
+fn draw_space_stats_line(name: &str, stats: &DrawSpaceStats) -> String {
+    format!(
+        "{}: quads: {} tris: {} lines: {} glyphs: {}",
+        name, stats.num_quads, stats.num_triangles, stats.num_lines, stats.num_glyphs
+    )
+}
@@ -73,6 +73,7 @@ impl GuiContext {
         &mut self,
         id: ElemId,
         label: &str,
+        font: FontHandle,
         button_rect: Rect,
         depth: f32,
         dc: &mut DrawContext,
@@ -116,10 +117,11 @@ impl GuiContext {
 
         // Draw button text
         let text_rect =
-            Rect::from_dimension(dc.get_text_dimensions(label)).centered_in_rect(button_rect);
+            Rect::from_dimension(dc.get_text_dimensions(label, font)).centered_in_rect(button_rect);
         dc.draw_text(
             text_rect.pos(),
             label,
+            font,
             depth,
             COLOR_WHITE,
             ADDITIVITY_NONE,
@@ -19,20 +19,42 @@ extern crate serde;
 
 #[macro_use]
 pub mod utility;
+pub mod animation;
 mod audio;
+pub mod camera;
 pub mod collision;
+pub mod color;
+pub mod debug_draw;
 pub mod draw;
+pub mod draw_capture;
+pub mod draw_inspector;
 pub mod gui;
 pub mod math;
+pub mod particles;
+pub mod postprocess;
+pub mod profiler;
 mod scenes;
+pub mod text;
+pub mod tilemap;
 
 pub type ResourcePath = String;
 
+pub use crate::animation::*;
 use crate::audio::*;
+pub use crate::camera::*;
 pub use crate::collision::*;
+pub use crate::color::*;
+pub use crate::debug_draw::*;
 pub use crate::draw::*;
+pub use crate::draw_capture::*;
+use crate::draw_inspector::DrawInspector;
 pub use crate::math::*;
+pub use crate::particles::*;
+pub use crate::postprocess::*;
+use crate::profiler::Profiler;
 use crate::scenes::*;
+pub use crate::text::*;
+pub use crate::tilemap::*;
 use std::collections::HashMap;
 
 //==================================================================================================
@@ -40,9 +62,34 @@ use std::collections::HashMap;
 
 pub enum SystemCommand {
     EnableRelativeMouseMovementCapture(bool),
+    /// Reads back the content of the given framebuffer at the end of the frame and saves it as
+    /// a timestamped png image
+    TakeScreenshot(FramebufferTarget),
+    /// Reads back the content of the given framebuffer at the end of every frame until either
+    /// `StopRecording` is issued or `max_duration_seconds` have passed. The recorded frames are
+    /// then saved in the given format
+    StartRecording {
+        framebuffer_target: FramebufferTarget,
+        format: RecordingFormat,
+        max_duration_seconds: f32,
+    },
+    StopRecording,
+    /// Saves the draw commands of the given number of frames together with all resources they
+    /// need into a timestamped capture file that can be replayed by itself
+    CaptureDrawCommands {
+        num_frames: usize,
+    },
     ShutdownGame,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Numbered png images with an additional file containing the duration of each frame
+    PngSequence,
+    /// Looping animated gif
+    Gif,
+}
+
 //==================================================================================================
 // External calls
 //==================================================================================================
@@ -75,6 +122,12 @@ const UNIT_SIZE: f32 = 16.0;
 const CANVAS_WIDTH: f32 = 480.0;
 const CANVAS_HEIGHT: f32 = 270.0;
 
+const DEBUG_RECORDING_MAX_DURATION_SECONDS: f32 = 10.0;
+const DEBUG_DRAW_CAPTURE_NUM_FRAMES: usize = 1;
+
+const PARTICLE_EFFECTS_FILEPATH: &str = "data/particle_effects.txt";
+const PALETTE_FILEPATH: &str = "data/palette.gpl";
+
 const LOG_LEVEL_GENERAL: log::LevelFilter = log::LevelFilter::Trace;
 const LOG_LEVEL_GAME_LIB: log::LevelFilter = log::LevelFilter::Trace;
 const LOG_LEVEL_MATH: log::LevelFilter = log::LevelFilter::Trace;
@@ -93,6 +146,9 @@ pub struct GameContext<'game_context> {
 
     drawcontext: DrawContext<'game_context>,
     system_commands: Vec<SystemCommand>,
+
+    profiler: Profiler,
+    draw_inspector: DrawInspector,
 }
 
 impl<'game_context> GameContext<'game_context> {
@@ -104,6 +160,10 @@ impl<'game_context> GameContext<'game_context> {
         std::mem::replace(&mut self.system_commands, Vec::new())
     }
 
+    pub fn profiler(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
     pub fn new(
         num_audio_channels: usize,
         audio_sample_rate_hz: usize,
@@ -132,6 +192,10 @@ pub struct GameInput {
 
     pub screen_dim: Vec2,
 
+    /// Is set by the platform layer while it records a framebuffer. The platform layer can stop a
+    /// recording by itself, i.e. after its maximum duration or when writing a frame failed.
+    pub is_recording: bool,
+
     pub current_audio_sample_index: usize,
 
     /// Regular buttons
@@ -313,15 +377,37 @@ pub fn update_and_draw_internal<'game_context>(
             .expect("Could not initialize logger");
     }
 
+    // ---------------------------------------------------------------------------------------------
+    // Canvas scaling
+    //
+    if input.had_press_event("debug_canvas_scaling_mode_oneshot") {
+        gc.globals.canvas_scaling_mode = gc.globals.canvas_scaling_mode.next();
+        info!(
+            "Switched canvas scaling mode to {:?}",
+            gc.globals.canvas_scaling_mode
+        );
+    }
+    let screen_rect = Rect::from_dimension(input.screen_dim);
+    let canvas_rect = canvas_rect_for_screen(
+        gc.globals.canvas_scaling_mode,
+        screen_rect,
+        Rect::from_width_height(CANVAS_WIDTH, CANVAS_HEIGHT),
+    );
+    let canvas_blit_rect =
+        canvas_blit_rect(gc.globals.canvas_scaling_mode, screen_rect, canvas_rect);
+
     if !gc.is_initialized || input.had_press_event("debug_reset_gamestate_oneshot") {
         gc.globals.cam = Camera::new(
             WorldPoint::zero(),
-            CANVAS_WIDTH,
-            CANVAS_HEIGHT,
+            canvas_rect.width(),
+            canvas_rect.height(),
             DEFAULT_WORLD_ZNEAR,
             DEFAULT_WORLD_ZFAR,
         );
+        gc.globals.cam_controller = CameraController::new();
+        gc.globals.cam_controller.dead_zone = WorldVec::ones() * 2.0 * UNIT_SIZE;
         gc.globals.error_happened = None;
+        gc.globals.particles.clear();
         gc.gameplay_scene.reinitialize(&mut gc.system_commands);
         gc.debug_scene.reinitialize(&mut gc.system_commands);
         gc.menu_scene.reinitialize(&mut gc.system_commands);
@@ -331,13 +417,37 @@ pub fn update_and_draw_internal<'game_context>(
         || input.had_press_event("debug_hotreload_assets_oneshot")
         || input.had_transition_event("debug_highres_drawing_toggle")
     {
-        let canvas_dim = if input.is_pressed("debug_highres_drawing_toggle") {
-            (input.screen_dim.x as u16, input.screen_dim.y as u16)
-        } else {
-            (CANVAS_WIDTH as u16, CANVAS_HEIGHT as u16)
-        };
+        let canvas_dim = canvas_framebuffer_dim(input, canvas_rect);
         gc.drawcontext.reinitialize(canvas_dim.0, canvas_dim.1);
         gc.audio_context.reinitialize();
+        gc.globals.particles.load_effects(PARTICLE_EFFECTS_FILEPATH);
+        match Palette::load_from_file(PALETTE_FILEPATH) {
+            Ok(palette) => gc.globals.palette = palette,
+            Err(error) => error!("{} - keeping the previous palette", error),
+        }
+    }
+
+    // NOTE: The canvas can change its size when the screen is resized
+    let canvas_dim = canvas_framebuffer_dim(input, canvas_rect);
+    gc.drawcontext.resize_canvas(canvas_dim.0, canvas_dim.1);
+    if canvas_rect.dim() != gc.globals.canvas_rect.dim() {
+        gc.globals
+            .cam
+            .resize_frustum(canvas_rect.width(), canvas_rect.height());
+    }
+    gc.globals.canvas_rect = canvas_rect;
+    gc.globals.canvas_blit_rect = canvas_blit_rect;
+
+    if input.had_press_event("debug_postprocess_effects_oneshot") {
+        gc.globals.postprocess.enabled = !gc.globals.postprocess.enabled;
+        info!(
+            "Post-processing effects {}",
+            if gc.globals.postprocess.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
     }
 
     if !gc.is_initialized {
@@ -351,24 +461,73 @@ pub fn update_and_draw_internal<'game_context>(
     if input.had_press_event("debug_time_slowdown") {
         gc.globals.debug_time_factor_increment -= 1;
     }
-    gc.globals.debug_game_paused = input.is_pressed("debug_pause_game_toggle");
+    for &overlay in DebugOverlay::ALL.iter() {
+        gc.globals
+            .debug_draw
+            .set_overlay_enabled(overlay, input.is_pressed(overlay.input_action()));
+    }
+    gc.draw_inspector.update(input, &mut gc.drawcontext);
+    // NOTE: A frozen frame can only be inspected if the game does not change in the meantime
+    gc.globals.debug_game_paused =
+        input.is_pressed("debug_pause_game_toggle") || gc.draw_inspector.is_frozen();
+    if input.had_press_event("debug_take_screenshot_canvas") {
+        if let Some(canvas_framebuffer_target) = gc.drawcontext.canvas_framebuffer_target() {
+            gc.system_commands
+                .push(SystemCommand::TakeScreenshot(canvas_framebuffer_target));
+        }
+    }
+    if input.had_press_event("debug_take_screenshot_screen") {
+        gc.system_commands
+            .push(SystemCommand::TakeScreenshot(FramebufferTarget::Screen));
+    }
+    if input.had_press_event("debug_capture_draw_commands") {
+        gc.system_commands.push(SystemCommand::CaptureDrawCommands {
+            num_frames: DEBUG_DRAW_CAPTURE_NUM_FRAMES,
+        });
+    }
+    if input.had_press_event("debug_record_canvas_gif")
+        || input.had_press_event("debug_record_canvas_png_sequence")
+    {
+        if input.is_recording {
+            gc.system_commands.push(SystemCommand::StopRecording);
+        } else if let Some(canvas_framebuffer_target) = gc.drawcontext.canvas_framebuffer_target() {
+            let format = if input.had_press_event("debug_record_canvas_gif") {
+                RecordingFormat::Gif
+            } else {
+                RecordingFormat::PngSequence
+            };
+            gc.system_commands.push(SystemCommand::StartRecording {
+                framebuffer_target: canvas_framebuffer_target,
+                format,
+                max_duration_seconds: DEBUG_RECORDING_MAX_DURATION_SECONDS,
+            });
+        }
+    }
+
+    if input.had_press_event("debug_profiler_export_trace") {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        gc.profiler
+            .export_chrome_trace(&format!("profiler_trace_{}.json", timestamp));
+    }
 
     // ---------------------------------------------------------------------------------------------
     // Mouse input and camera
     //
-    let screen_rect = Rect::from_dimension(input.screen_dim);
-    let canvas_rect = Rect::from_width_height(CANVAS_WIDTH, CANVAS_HEIGHT);
-    let canvas_blit_rect = canvas_blit_rect(screen_rect, canvas_rect);
-
     // Canvas mouse position
     // TODO(JaSc): new_mouse_pos_canvas and accumulations of new_mouse_delta_canvas will go
     //             out of sync due to rounding errors. Maybe only allow just one or the other
     //             when we get to implement event based input?
     let new_mouse_pos_canvas =
-        screen_pos_to_canvas_pos(input.mouse_pos_screen, screen_rect, canvas_rect);
+        screen_pos_to_canvas_pos(input.mouse_pos_screen, canvas_blit_rect, canvas_rect);
     let new_mouse_delta_canvas =
-        screen_vec_to_canvas_vec(input.mouse_delta_screen, screen_rect, canvas_rect);
+        screen_vec_to_canvas_vec(input.mouse_delta_screen, canvas_blit_rect, canvas_rect);
 
+    // NOTE: The camera controller is updated by the gameplay scene after it moved its objects.
+    //       The mouse world position therefore matches the camera of the last drawn frame which
+    //       is what the user pointed at.
     // World mouse position
     let new_mouse_pos_world = gc
         .globals
@@ -415,32 +574,72 @@ pub fn update_and_draw_internal<'game_context>(
     dc.start_drawing();
     {
         //_do_collision_tests(dc, new_mouse_pos_world);
-        gc.gameplay_scene.update_and_draw(
-            input,
-            &mut gc.globals,
-            &mut dc,
-            &mut ac,
-            &mut gc.system_commands,
-        );
-
-        gc.menu_scene.update_and_draw(
-            input,
-            &mut gc.globals,
-            &mut dc,
-            &mut ac,
-            &mut gc.system_commands,
+        let mut scenes_scope = gc.profiler.scope("scenes");
+        {
+            let _scope = scenes_scope.scope("gameplay_scene");
+            gc.gameplay_scene.update_and_draw(
+                input,
+                &mut gc.globals,
+                &mut dc,
+                &mut ac,
+                &mut gc.system_commands,
+            );
+        }
+        {
+            let _scope = scenes_scope.scope("menu_scene");
+            gc.menu_scene.update_and_draw(
+                input,
+                &mut gc.globals,
+                &mut dc,
+                &mut ac,
+                &mut gc.system_commands,
+            );
+        }
+        {
+            let _scope = scenes_scope.scope("debug_scene");
+            gc.debug_scene.update_and_draw(
+                input,
+                &mut gc.globals,
+                &mut dc,
+                &mut ac,
+                &mut gc.system_commands,
+            );
+        }
+    }
+    gc.globals.debug_draw.draw(
+        dc,
+        &gc.globals.cam,
+        canvas_rect,
+        gc.globals.mouse_pos_world,
+        input.time_delta,
+    );
+    if input.is_pressed("debug_profiler_overlay_toggle") {
+        let overlay_rect = Rect::from_bounds(
+            8.0,
+            canvas_rect.right - 8.0,
+            canvas_rect.bottom - 8.0,
+            (canvas_rect.bottom - 0.4 * canvas_rect.height()).round(),
         );
-
-        gc.debug_scene.update_and_draw(
-            input,
-            &mut gc.globals,
-            &mut dc,
-            &mut ac,
-            &mut gc.system_commands,
+        gc.profiler.debug_draw_overlay(dc, overlay_rect);
+    }
+    if gc.draw_inspector.is_visible() {
+        let overlay_rect = Rect::from_bounds(
+            8.0,
+            (canvas_rect.right - 0.5 * canvas_rect.width()).round(),
+            (canvas_rect.bottom - 0.4 * canvas_rect.height()).round() - 8.0,
+            8.0,
         );
+        gc.draw_inspector.debug_draw_overlay(dc, overlay_rect);
     }
     let transform = gc.globals.cam.proj_view_matrix();
-    dc.finish_drawing(transform, canvas_rect, canvas_blit_rect);
+    let _scope = gc.profiler.scope("finish_drawing");
+    dc.finish_drawing(
+        transform,
+        screen_rect,
+        canvas_rect,
+        canvas_blit_rect,
+        &gc.globals.postprocess,
+    );
 }
 
 //==================================================================================================
@@ -527,17 +726,91 @@ fn lalaa() {
 // TODO(JaSc): Find a better place for the following functions
 // =================================================================================================
 
+/// Determines how the canvas is scaled onto the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasScalingMode {
+    /// The canvas keeps its size and is scaled by the biggest integer factor that fits onto the
+    /// screen. The remaining area of the screen is letterboxed. Falls back to `Stretch` if the
+    /// screen is smaller than the canvas.
+    IntegerScale,
+    /// The canvas keeps its size and is proportionally stretched to fill the screen. This can
+    /// result in canvas pixels of uneven size.
+    Stretch,
+    /// The canvas grows and shrinks with the screen such that every canvas pixel is drawn as a
+    /// square of `pixel_scale`x`pixel_scale` screen pixels
+    FixedPixelScale { pixel_scale: u16 },
+}
+
+impl Default for CanvasScalingMode {
+    fn default() -> CanvasScalingMode {
+        CanvasScalingMode::IntegerScale
+    }
+}
+
+impl CanvasScalingMode {
+    /// Cycles through the scaling modes
+    pub fn next(self) -> CanvasScalingMode {
+        match self {
+            CanvasScalingMode::IntegerScale => CanvasScalingMode::Stretch,
+            CanvasScalingMode::Stretch => CanvasScalingMode::FixedPixelScale { pixel_scale: 2 },
+            CanvasScalingMode::FixedPixelScale { .. } => CanvasScalingMode::IntegerScale,
+        }
+    }
+}
+
+/// Returns the canvas rectangle for a given screen. Only the `FixedPixelScale` mode changes the
+/// size of the `default_canvas_rect`.
+///
+/// # Examples
+/// ```
+/// # use game_lib::*;
+/// let screen_rect = Rect::from_width_height(1000.0, 600.0);
+/// let default_canvas_rect = Rect::from_width_height(480.0, 270.0);
+///
+/// let canvas_rect = canvas_rect_for_screen(
+///     CanvasScalingMode::IntegerScale,
+///     screen_rect,
+///     default_canvas_rect,
+/// );
+/// assert_eq!(canvas_rect.dim(), Vec2::new(480.0, 270.0));
+///
+/// let canvas_rect = canvas_rect_for_screen(
+///     CanvasScalingMode::FixedPixelScale { pixel_scale: 3 },
+///     screen_rect,
+///     default_canvas_rect,
+/// );
+/// assert_eq!(canvas_rect.dim(), Vec2::new(333.0, 200.0));
+/// ```
+pub fn canvas_rect_for_screen(
+    scaling_mode: CanvasScalingMode,
+    screen_rect: Rect,
+    default_canvas_rect: Rect,
+) -> Rect {
+    match scaling_mode {
+        CanvasScalingMode::IntegerScale | CanvasScalingMode::Stretch => default_canvas_rect,
+        CanvasScalingMode::FixedPixelScale { pixel_scale } => {
+            let pixel_scale = f32::from(u16::max(pixel_scale, 1));
+            Rect::from_width_height(
+                f32::max(1.0, (screen_rect.width() / pixel_scale).floor()),
+                f32::max(1.0, (screen_rect.height() / pixel_scale).floor()),
+            )
+        }
+    }
+}
+
 /// Returns the `blit_rectangle` of for given canvas and screen rectangles.
 /// The `blit-rectange` is the area of the screen where the content of the canvas is drawn onto.
-/// It is as big as the canvas proportionally stretched and centered to fill the whole
-/// screen.
+/// It is always centered on the screen. Its size depends on the scaling mode:
 ///
-/// It may or may not be smaller than the full screen size depending on the aspect
-/// ratio of both the screen and the canvas. The `blit_rectange` is guaranteed to either have
-/// the same width a as the screen (with letterboxing if needed) or the same height as the
-/// screen (with columnboxing if needed) or completely fill the screen.
+/// * `IntegerScale`: The canvas multiplied by the biggest integer that fits onto the screen
+/// * `Stretch`: The canvas proportionally stretched to fill the whole screen
+/// * `FixedPixelScale`: The canvas multiplied by the pixel scale
 ///
-/// # Examples
+/// In the `Stretch` mode the `blit_rectange` is guaranteed to either have the same width a as
+/// the screen (with letterboxing if needed) or the same height as the screen (with
+/// columnboxing if needed) or completely fill the screen.
+///
+/// # Examples: Stretch
 /// ```
 /// // +------+  +--------------+  +---------------+
 /// // |canvas|  |   screen     |  |               | <- screen
@@ -572,10 +845,37 @@ fn lalaa() {
 /// //           +----------------+  +-----------------+
 /// //                                                ^---- blit-rect == screen
 /// ```
-pub fn canvas_blit_rect(screen_rect: Rect, canvas_rect: Rect) -> Rect {
-    canvas_rect
-        .stretched_to_fit(screen_rect)
+pub fn canvas_blit_rect(
+    scaling_mode: CanvasScalingMode,
+    screen_rect: Rect,
+    canvas_rect: Rect,
+) -> Rect {
+    let integer_scale = f32::min(
+        (screen_rect.width() / canvas_rect.width()).floor(),
+        (screen_rect.height() / canvas_rect.height()).floor(),
+    );
+    let scale = match scaling_mode {
+        CanvasScalingMode::IntegerScale if integer_scale >= 1.0 => integer_scale,
+        CanvasScalingMode::FixedPixelScale { pixel_scale } => f32::from(u16::max(pixel_scale, 1)),
+        _ => {
+            return canvas_rect
+                .stretched_to_fit(screen_rect)
+                .centered_in_rect(screen_rect);
+        }
+    };
+    Rect::from_dimension(canvas_rect.dim() * scale)
         .centered_in_rect(screen_rect)
+        .with_pixel_snapped_position()
+}
+
+/// Returns the size of the canvas framebuffer. It is as big as the canvas unless we are in high
+/// resolution debug drawing mode.
+fn canvas_framebuffer_dim(input: &GameInput, canvas_rect: Rect) -> (u16, u16) {
+    if input.is_pressed("debug_highres_drawing_toggle") {
+        (input.screen_dim.x as u16, input.screen_dim.y as u16)
+    } else {
+        (canvas_rect.width() as u16, canvas_rect.height() as u16)
+    }
 }
 
 // TODO(JaSc): Proofread and refactor this
@@ -583,13 +883,13 @@ pub fn canvas_blit_rect(screen_rect: Rect, canvas_rect: Rect) -> Rect {
 /// into a canvas-position in the following interval:
 /// `[0..canvas_rect.width-1]x[0..canvas_rect.height-1]`
 /// where `(0,0)` is the top left of the canvas.
-fn screen_pos_to_canvas_pos(screen_point: Point, screen_rect: Rect, canvas_rect: Rect) -> Point {
+fn screen_pos_to_canvas_pos(screen_point: Point, blit_rect: Rect, canvas_rect: Rect) -> Point {
     // NOTE: Clamping the point needs to use integer arithmetic such that
     //          x != canvas.rect.width and y != canvas.rect.height
     //       holds. We therefore need to subtract one from the blit_rect's dimension and then
     //       add one again after clamping to achieve the desired effect.
     // TODO(JaSc): Maybe make this more self documenting via integer rectangles
-    let mut blit_rect = canvas_blit_rect(screen_rect, canvas_rect);
+    let mut blit_rect = blit_rect;
     blit_rect.right -= 1.0;
     blit_rect.bottom -= 1.0;
     let clamped_point = screen_point.clamped_in_rect(blit_rect);
@@ -599,8 +899,8 @@ fn screen_pos_to_canvas_pos(screen_point: Point, screen_rect: Rect, canvas_rect:
     (canvas_rect.dim() * ((clamped_point - blit_rect.pos()) / blit_rect.dim())).pixel_snapped()
 }
 
-fn screen_vec_to_canvas_vec(screen_vec: Vec2, screen_rect: Rect, canvas_rect: Rect) -> CanvasVec {
-    (canvas_rect.dim() * (screen_vec / screen_rect.dim())).pixel_snapped()
+fn screen_vec_to_canvas_vec(screen_vec: Vec2, blit_rect: Rect, canvas_rect: Rect) -> CanvasVec {
+    (canvas_rect.dim() * (screen_vec / blit_rect.dim())).pixel_snapped()
 }
 
 fn pretty_format_duration_ms(duration: f64) -> String {
@@ -128,7 +128,7 @@ use std::ops::Neg;
 use std::ops::Sub;
 use std::ops::SubAssign;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -1099,6 +1099,15 @@ impl Camera {
         self.frustum.center()
     }
 
+    pub fn set_pos(&mut self, pos: WorldPoint) {
+        self.frustum = self.frustum.centered_in_position(pos);
+    }
+
+    /// Changes the size of the unzoomed frustum while keeping the camera position
+    pub fn resize_frustum(&mut self, frustum_width: f32, frustum_height: f32) {
+        self.frustum = Rect::from_point(self.pos(), frustum_width, frustum_height).centered();
+    }
+
     pub fn dim_zoomed(&self) -> WorldVec {
         self.frustum.dim() / self.zoom_level
     }
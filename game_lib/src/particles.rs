@@ -0,0 +1,377 @@
+use crate::animation::AnimationPlaybackMode;
+use crate::draw::{DrawContext, DrawSpace, QuadColors, SpriteTransform};
+use crate::math::{clamp, Color, Rect, Vec2, WorldPoint};
+use crate::utility;
+use crate::ResourcePath;
+
+use std::collections::HashMap;
+
+//==================================================================================================
+// ParticleEffect
+//==================================================================================================
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParticleEmission {
+    /// Emits the given number of particles at once
+    Burst { count: usize },
+    /// Emits particles until the emitter is stopped
+    Continuous { particles_per_second: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParticleVisual {
+    /// A filled square with a side length of the particle size in pixels
+    Quad,
+    /// A sprite that is scaled by the particle size
+    Sprite(ResourcePath),
+    /// A looping animation that is scaled by the particle size and starts when the particle is
+    /// emitted
+    Animation(ResourcePath),
+}
+
+/// Describes how the particles of an effect are emitted, move and look. Ranges are given as
+/// `(min, max)` and a random value inside of the range is chosen for every particle.
+///
+/// Effects are usually loaded by name from a RON file (see [`ParticleSystem::load_effects`])
+/// so that they can be tuned without recompiling. Fields that are missing in the file are taken
+/// from the default effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleEffect {
+    pub emission: ParticleEmission,
+    pub visual: ParticleVisual,
+    /// Lifetime of a particle in seconds
+    pub lifetime: (f32, f32),
+    /// Initial speed of a particle in pixels per second
+    pub speed: (f32, f32),
+    /// Center of the velocity cone in degrees where 0 points to the right and 90 points down
+    pub direction: f32,
+    /// Opening angle of the velocity cone in degrees. A spread of 360 emits in all directions.
+    pub spread: f32,
+    /// Constant acceleration in pixels per second squared
+    pub gravity: Vec2,
+    /// Fraction of its velocity a particle loses per second
+    pub drag: f32,
+    /// Offsets the emission position randomly inside of a circle with the given radius
+    pub emission_radius: f32,
+    pub color_start: [f32; 4],
+    pub color_end: [f32; 4],
+    pub size_start: f32,
+    pub size_end: f32,
+    /// Particles are drawn with full additivity by default which makes overlapping particles glow
+    pub additivity: f32,
+    pub depth: f32,
+}
+
+impl Default for ParticleEffect {
+    fn default() -> ParticleEffect {
+        ParticleEffect {
+            emission: ParticleEmission::Burst { count: 16 },
+            visual: ParticleVisual::Quad,
+            lifetime: (0.5, 1.0),
+            speed: (20.0, 40.0),
+            direction: 0.0,
+            spread: 360.0,
+            gravity: Vec2::zero(),
+            drag: 0.0,
+            emission_radius: 0.0,
+            color_start: [1.0, 1.0, 1.0, 1.0],
+            color_end: [1.0, 1.0, 1.0, 0.0],
+            size_start: 2.0,
+            size_end: 0.0,
+            additivity: 1.0,
+            depth: 0.0,
+        }
+    }
+}
+
+/// The layout of a particle effects file
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ParticleEffectMap {
+    pub effects: HashMap<String, ParticleEffect>,
+}
+
+//==================================================================================================
+// ParticleEmitter
+//==================================================================================================
+//
+
+/// Emits the particles of an effect at a position that can change over time. Emitters are owned
+/// by game code and passed to [`ParticleSystem::update_emitter`] every frame.
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub effect_name: String,
+    pub pos: WorldPoint,
+    /// Continuous emitters only emit while this is set. Burst emitters emit once when this is set
+    /// and reset it afterwards.
+    pub is_emitting: bool,
+    /// Fractional particles that are carried over to the next frame
+    num_particles_pending: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect_name: &str, pos: WorldPoint) -> ParticleEmitter {
+        ParticleEmitter {
+            effect_name: String::from(effect_name),
+            pos,
+            is_emitting: true,
+            num_particles_pending: 0.0,
+        }
+    }
+}
+
+//==================================================================================================
+// ParticleSystem
+//==================================================================================================
+//
+
+#[derive(Debug, Clone)]
+struct Particle {
+    effect_index: usize,
+    pos: WorldPoint,
+    vel: Vec2,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    fn percentage_of_lifetime(&self) -> f32 {
+        clamp(self.age / self.lifetime, 0.0, 1.0)
+    }
+}
+
+/// Simulates and draws all particles of the game on the CPU.
+///
+/// # Example
+/// ```
+/// # use game_lib::math::*;
+/// # use game_lib::particles::*;
+/// let mut particles = ParticleSystem::new();
+/// particles.add_effect("sparks", ParticleEffect::default());
+///
+/// particles.emit_burst("sparks", WorldPoint::zero());
+/// assert_eq!(particles.num_particles(), 16);
+///
+/// // The default effect lives for at most one second
+/// particles.update(1.5);
+/// assert_eq!(particles.num_particles(), 0);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ParticleSystem {
+    effect_names: Vec<String>,
+    effects: Vec<ParticleEffect>,
+    particles: Vec<Particle>,
+    random: Random,
+}
+
+impl ParticleSystem {
+    pub fn new() -> ParticleSystem {
+        Default::default()
+    }
+
+    /// Adds the effects of the given RON file. Effects with an already existing name are replaced
+    /// which allows reloading the file while the game is running.
+    pub fn load_effects(&mut self, filename: &str) {
+        let effect_map: ParticleEffectMap = utility::deserialize_from_ron_file(filename);
+        for (name, effect) in effect_map.effects {
+            self.add_effect(&name, effect);
+        }
+        info!(
+            "Loaded particle effects from '{}', {} effects available",
+            filename,
+            self.effects.len()
+        );
+    }
+
+    pub fn add_effect(&mut self, name: &str, effect: ParticleEffect) {
+        match self.effect_index(name) {
+            Some(index) => self.effects[index] = effect,
+            None => {
+                self.effect_names.push(String::from(name));
+                self.effects.push(effect);
+            }
+        }
+    }
+
+    pub fn effect(&self, name: &str) -> Option<&ParticleEffect> {
+        self.effect_index(name).map(|index| &self.effects[index])
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Emits the particles of a burst effect once without the need for an emitter
+    pub fn emit_burst(&mut self, effect_name: &str, pos: WorldPoint) {
+        let effect_index = match self.effect_index_or_warn(effect_name) {
+            Some(effect_index) => effect_index,
+            None => return,
+        };
+        match self.effects[effect_index].emission {
+            ParticleEmission::Burst { count } => self.emit(effect_index, pos, count),
+            ParticleEmission::Continuous { .. } => warn!(
+                "Cannot emit a burst of continuous particle effect '{}'",
+                effect_name
+            ),
+        }
+    }
+
+    pub fn update_emitter(&mut self, emitter: &mut ParticleEmitter, delta_time: f32) {
+        if !emitter.is_emitting {
+            emitter.num_particles_pending = 0.0;
+            return;
+        }
+
+        let effect_index = match self.effect_index_or_warn(&emitter.effect_name) {
+            Some(effect_index) => effect_index,
+            None => {
+                emitter.is_emitting = false;
+                return;
+            }
+        };
+        match self.effects[effect_index].emission {
+            ParticleEmission::Burst { count } => {
+                self.emit(effect_index, emitter.pos, count);
+                emitter.is_emitting = false;
+            }
+            ParticleEmission::Continuous {
+                particles_per_second,
+            } => {
+                emitter.num_particles_pending += particles_per_second * delta_time;
+                let count = emitter.num_particles_pending.floor();
+                emitter.num_particles_pending -= count;
+                self.emit(effect_index, emitter.pos, count as usize);
+            }
+        }
+    }
+
+    /// Moves all particles and removes the ones that have reached the end of their lifetime
+    pub fn update(&mut self, delta_time: f32) {
+        let effects = &self.effects;
+        for particle in self.particles.iter_mut() {
+            let effect = &effects[particle.effect_index];
+            particle.vel += effect.gravity * delta_time;
+            particle.vel = particle.vel * f32::max(1.0 - effect.drag * delta_time, 0.0);
+            particle.pos += particle.vel * delta_time;
+            particle.age += delta_time;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    pub fn draw(&self, dc: &mut DrawContext, draw_space: DrawSpace) {
+        for particle in self.particles.iter() {
+            let effect = &self.effects[particle.effect_index];
+            let percentage = particle.percentage_of_lifetime();
+            let color = Color::from(effect.color_start)
+                + percentage * (Color::from(effect.color_end) - Color::from(effect.color_start));
+            let size = effect.size_start + percentage * (effect.size_end - effect.size_start);
+
+            match &effect.visual {
+                ParticleVisual::Quad => dc.draw_rect_filled(
+                    Rect::from_point_dimension(particle.pos, Vec2::ones() * size).centered(),
+                    effect.depth,
+                    color,
+                    effect.additivity,
+                    draw_space,
+                ),
+                ParticleVisual::Sprite(resource_path) => {
+                    let sprite_handle = dc.sprite_handle(resource_path);
+                    let sprite = dc.sprite(sprite_handle);
+                    dc.draw_sprite_transformed(
+                        &sprite,
+                        SpriteTransform::from_pos(particle.pos).with_scale(Vec2::ones() * size),
+                        effect.depth,
+                        QuadColors::uniform(color),
+                        effect.additivity,
+                        draw_space,
+                    );
+                }
+                ParticleVisual::Animation(resource_path) => {
+                    let animation_handle = dc.animation_handle(resource_path);
+                    let animation = dc.animation(animation_handle);
+                    if animation.num_frames() == 0 {
+                        continue;
+                    }
+                    let sprite = animation.frames
+                        [animation.frame_index_at_time(particle.age, AnimationPlaybackMode::Loop)];
+                    dc.draw_sprite_transformed(
+                        &sprite,
+                        SpriteTransform::from_pos(particle.pos).with_scale(Vec2::ones() * size),
+                        effect.depth,
+                        QuadColors::uniform(color),
+                        effect.additivity,
+                        draw_space,
+                    );
+                }
+            }
+        }
+    }
+
+    fn emit(&mut self, effect_index: usize, pos: WorldPoint, count: usize) {
+        let effect = &self.effects[effect_index];
+        let random = &mut self.random;
+        for _ in 0..count {
+            let angle = effect.direction + effect.spread * (random.f32_in_range(0.0, 1.0) - 0.5);
+            let speed = random.f32_in_range(effect.speed.0, effect.speed.1);
+            // NOTE: The square root distributes the offsets uniformly over the area of the circle
+            let offset = Vec2::from_angle(random.f32_in_range(0.0, 360.0).to_radians())
+                * effect.emission_radius
+                * random.f32_in_range(0.0, 1.0).sqrt();
+            self.particles.push(Particle {
+                effect_index,
+                pos: pos + offset,
+                vel: Vec2::from_angle(angle.to_radians()) * speed,
+                age: 0.0,
+                lifetime: random.f32_in_range(effect.lifetime.0, effect.lifetime.1),
+            });
+        }
+    }
+
+    fn effect_index(&self, name: &str) -> Option<usize> {
+        self.effect_names
+            .iter()
+            .position(|effect_name| effect_name == name)
+    }
+
+    fn effect_index_or_warn(&self, name: &str) -> Option<usize> {
+        let effect_index = self.effect_index(name);
+        if effect_index.is_none() {
+            warn!("Particle effect '{}' does not exist", name);
+        }
+        effect_index
+    }
+}
+
+//==================================================================================================
+// Random
+//==================================================================================================
+//
+
+/// Xorshift random number generator which is good enough for visual effects
+#[derive(Debug, Clone)]
+struct Random {
+    state: u32,
+}
+
+impl Default for Random {
+    fn default() -> Random {
+        Random { state: 0x2545_f491 }
+    }
+}
+
+impl Random {
+    fn f32_in_range(&mut self, min: f32, max: f32) -> f32 {
+        // NOTE: Xorshift32 from https://en.wikipedia.org/wiki/Xorshift
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        let percentage = self.state as f32 / std::u32::MAX as f32;
+        min + percentage * (max - min)
+    }
+}
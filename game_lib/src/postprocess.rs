@@ -0,0 +1,302 @@
+use crate::draw::Pixel;
+use crate::math::{clamp, Color, Vec2};
+
+/// Number of color levels per channel stored in a color grading LUT
+pub const COLOR_GRADING_LUT_SIZE: usize = 16;
+
+//==================================================================================================
+// PostProcessEffect
+//==================================================================================================
+//
+
+/// A single full-screen pass that reads from a source framebuffer and writes to a target.
+/// The [`PostProcessSettings`] are translated into a chain of these passes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum PostProcessEffect {
+    /// Keeps only the parts of the image that are brighter than `threshold`
+    BrightPass { threshold: f32 },
+    /// One dimensional gaussian blur along `direction` with the given radius in source pixels
+    Blur { direction: Vec2, radius: f32 },
+    /// Adds the secondary source on top of the source
+    BloomComposite { intensity: f32 },
+    /// Darkens the borders of every source pixel row
+    Scanlines { intensity: f32, line_thickness: f32 },
+    /// Darkens the image towards its corners
+    Vignette {
+        intensity: f32,
+        radius: f32,
+        softness: f32,
+    },
+    /// Maps all colors through the color grading LUT
+    ColorGrading { intensity: f32 },
+}
+
+//==================================================================================================
+// PostProcessSettings
+//==================================================================================================
+//
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Luminance above which pixels start to glow
+    pub threshold: f32,
+    pub intensity: f32,
+    /// Blur radius in canvas pixels
+    pub blur_radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> BloomSettings {
+        BloomSettings {
+            enabled: true,
+            threshold: 0.6,
+            intensity: 0.8,
+            blur_radius: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanlineSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    /// Fraction of a canvas pixel row that is darkened in the range [0, 1]
+    pub line_thickness: f32,
+}
+
+impl Default for ScanlineSettings {
+    fn default() -> ScanlineSettings {
+        ScanlineSettings {
+            enabled: true,
+            intensity: 0.25,
+            line_thickness: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    /// Distance from the center at which the vignette reaches full intensity where 1.0 is the
+    /// distance to the corners
+    pub radius: f32,
+    /// Width of the transition from no darkening to full darkening
+    pub softness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> VignetteSettings {
+        VignetteSettings {
+            enabled: true,
+            intensity: 0.4,
+            radius: 1.0,
+            softness: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGradingSettings {
+    pub enabled: bool,
+    /// Blends between the original (0.0) and the graded (1.0) colors
+    pub intensity: f32,
+    pub lut: ColorGradingLut,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> ColorGradingSettings {
+        ColorGradingSettings {
+            enabled: true,
+            intensity: 1.0,
+            lut: ColorGradingLut {
+                contrast: 1.1,
+                saturation: 1.15,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Describes the post-processing chain that is applied to the canvas before it is drawn onto the
+/// screen. The effects are applied in the order bloom, color grading, vignette, scanlines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcessSettings {
+    /// Disables the whole chain regardless of the individual effect settings. The chain is
+    /// disabled by default so that the pixel-art stays untouched unless the effects are toggled on
+    /// for debugging.
+    pub enabled: bool,
+    pub bloom: BloomSettings,
+    pub color_grading: ColorGradingSettings,
+    pub vignette: VignetteSettings,
+    pub scanlines: ScanlineSettings,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> PostProcessSettings {
+        PostProcessSettings {
+            enabled: false,
+            bloom: BloomSettings::default(),
+            color_grading: ColorGradingSettings::default(),
+            vignette: VignetteSettings::default(),
+            scanlines: ScanlineSettings::default(),
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// Returns the passes that make up the chain. Bloom results in multiple passes where the
+    /// bloom composite pass uses the result of the blur passes as its secondary source.
+    pub fn effects(&self) -> Vec<PostProcessEffect> {
+        let mut effects = Vec::new();
+        if !self.enabled {
+            return effects;
+        }
+
+        if self.bloom.enabled {
+            effects.push(PostProcessEffect::BrightPass {
+                threshold: self.bloom.threshold,
+            });
+            effects.push(PostProcessEffect::Blur {
+                direction: Vec2::unit_x(),
+                radius: self.bloom.blur_radius,
+            });
+            effects.push(PostProcessEffect::Blur {
+                direction: Vec2::unit_y(),
+                radius: self.bloom.blur_radius,
+            });
+            effects.push(PostProcessEffect::BloomComposite {
+                intensity: self.bloom.intensity,
+            });
+        }
+        if self.color_grading.enabled {
+            effects.push(PostProcessEffect::ColorGrading {
+                intensity: self.color_grading.intensity,
+            });
+        }
+        if self.vignette.enabled {
+            effects.push(PostProcessEffect::Vignette {
+                intensity: self.vignette.intensity,
+                radius: self.vignette.radius,
+                softness: self.vignette.softness,
+            });
+        }
+        if self.scanlines.enabled {
+            effects.push(PostProcessEffect::Scanlines {
+                intensity: self.scanlines.intensity,
+                line_thickness: self.scanlines.line_thickness,
+            });
+        }
+        effects
+    }
+}
+
+//==================================================================================================
+// ColorGradingLut
+//==================================================================================================
+//
+
+/// Color adjustments that are baked into a color grading lookup texture.
+///
+/// The LUT is a horizontal strip of `COLOR_GRADING_LUT_SIZE` square tiles. Inside a tile the
+/// red channel grows from left to right and the green channel from the first to the last row.
+/// The blue channel grows from tile to tile.
+///
+/// # Example
+/// ```
+/// # use game_lib::math::*;
+/// # use game_lib::postprocess::*;
+/// let identity = ColorGradingLut::default();
+/// let color = Color::new(0.2, 0.4, 0.6, 1.0);
+/// let graded = identity.grade(color);
+/// assert!((graded - color).magnitude() < 0.0001);
+///
+/// let grayscale = ColorGradingLut { saturation: 0.0, ..Default::default() };
+/// let graded = grayscale.grade(color);
+/// assert!(graded.x == graded.y && graded.y == graded.z);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGradingLut {
+    pub contrast: f32,
+    pub saturation: f32,
+    /// Added to all color channels before contrast is applied
+    pub brightness: f32,
+    /// Multiplied with all colors
+    pub tint: Color,
+}
+
+impl Default for ColorGradingLut {
+    fn default() -> ColorGradingLut {
+        ColorGradingLut {
+            contrast: 1.0,
+            saturation: 1.0,
+            brightness: 0.0,
+            tint: Color::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl ColorGradingLut {
+    /// Applies the adjustments to the given color. The alpha channel is left untouched.
+    pub fn grade(&self, color: Color) -> Color {
+        let tinted = Color::new(
+            color.x * self.tint.x,
+            color.y * self.tint.y,
+            color.z * self.tint.z,
+            color.w,
+        );
+        let adjust_contrast =
+            |channel: f32| (channel + self.brightness - 0.5) * self.contrast + 0.5;
+        let (r, g, b) = (
+            adjust_contrast(tinted.x),
+            adjust_contrast(tinted.y),
+            adjust_contrast(tinted.z),
+        );
+
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let adjust_saturation = |channel: f32| {
+            clamp(
+                luminance + (channel - luminance) * self.saturation,
+                0.0,
+                1.0,
+            )
+        };
+        Color::new(
+            adjust_saturation(r),
+            adjust_saturation(g),
+            adjust_saturation(b),
+            color.w,
+        )
+    }
+
+    /// Returns the width, height and pixels of the lookup texture
+    pub fn to_pixels(&self) -> (usize, usize, Vec<Pixel>) {
+        let size = COLOR_GRADING_LUT_SIZE;
+        let width = size * size;
+        let height = size;
+        let max_level = (size - 1) as f32;
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for green in 0..size {
+            for blue in 0..size {
+                for red in 0..size {
+                    let color = Color::new(
+                        red as f32 / max_level,
+                        green as f32 / max_level,
+                        blue as f32 / max_level,
+                        1.0,
+                    );
+                    let graded = self.grade(color);
+                    pixels.push(Pixel::new(
+                        (255.0 * graded.x).round() as u8,
+                        (255.0 * graded.y).round() as u8,
+                        (255.0 * graded.z).round() as u8,
+                        255,
+                    ));
+                }
+            }
+        }
+        (width, height, pixels)
+    }
+}
@@ -0,0 +1,459 @@
+use crate::draw::{self, DrawContext, DrawSpace};
+use crate::math::{Color, Line, Point, Rect};
+use crate::utility;
+
+use std;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+const FRAME_HISTORY_LENGTH: usize = 300;
+const TARGET_FRAME_DURATION: f64 = 1.0 / 60.0;
+
+const OVERLAY_DEPTH_BACKGROUND: f32 = -0.3;
+const OVERLAY_DEPTH_BARS: f32 = -0.2;
+const OVERLAY_DEPTH_LINES: f32 = -0.1;
+const OVERLAY_DEPTH_TEXT: f32 = 0.0;
+const OVERLAY_ZONE_ROW_HEIGHT: f32 = 10.0;
+const OVERLAY_GRAPH_HEIGHT_RATIO: f32 = 0.4;
+
+const OVERLAY_COLOR_BACKGROUND: Color = Color {
+    x: 0.0,
+    y: 0.0,
+    z: 0.0,
+    w: 0.7,
+};
+const OVERLAY_COLORS_ZONES: [Color; 4] = [
+    Color {
+        x: 0.2,
+        y: 0.4,
+        z: 0.8,
+        w: 1.0,
+    },
+    Color {
+        x: 0.6,
+        y: 0.3,
+        z: 0.7,
+        w: 1.0,
+    },
+    Color {
+        x: 0.2,
+        y: 0.6,
+        z: 0.5,
+        w: 1.0,
+    },
+    Color {
+        x: 0.7,
+        y: 0.5,
+        z: 0.2,
+        w: 1.0,
+    },
+];
+
+//==================================================================================================
+// Profiler
+//==================================================================================================
+//
+
+/// A named timespan within a profiled frame. All times are given in seconds since the creation of
+/// the profiler.
+#[derive(Debug, Clone)]
+pub struct ProfilerZone {
+    pub name: String,
+    /// Number of zones that enclose this zone
+    pub depth: usize,
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// All zones that were recorded between [`Profiler::begin_frame`] and [`Profiler::end_frame`]
+#[derive(Debug, Clone, Default)]
+pub struct ProfilerFrame {
+    pub index: u64,
+    pub start: f64,
+    pub duration: f64,
+    /// Zones in the order they were started
+    pub zones: Vec<ProfilerZone>,
+}
+
+/// Records nested named zones for each frame and keeps the last [`FRAME_HISTORY_LENGTH`] frames
+/// in a ring buffer. It lives in the [`GameContext`] so that it can be used by the platform layer
+/// as well as by the game lib and survives hot reloading.
+///
+/// # Examples
+/// ```
+/// # use game_lib::profiler::Profiler;
+/// let mut profiler = Profiler::new();
+///
+/// profiler.begin_frame();
+/// {
+///     let mut update_scope = profiler.scope("update");
+///     {
+///         let _physics_scope = update_scope.scope("physics");
+///         // do_physics();
+///     }
+/// }
+/// profiler.end_frame();
+///
+/// let frame = profiler.last_frame().unwrap();
+/// assert_eq!(frame.zones[0].name, "update");
+/// assert_eq!(frame.zones[1].depth, 1);
+/// ```
+///
+/// [`GameContext`]: ../struct.GameContext.html
+pub struct Profiler {
+    creation_time: Instant,
+    frame_counter: u64,
+    frames: VecDeque<ProfilerFrame>,
+    current_frame: Option<ProfilerFrame>,
+    /// Indices into the zones of the current frame which were started but not yet ended
+    open_zones: Vec<usize>,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            creation_time: Instant::now(),
+            frame_counter: 0,
+            frames: VecDeque::with_capacity(FRAME_HISTORY_LENGTH),
+            current_frame: None,
+            open_zones: Vec::new(),
+        }
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Recording
+    //
+    pub fn begin_frame(&mut self) {
+        if self.current_frame.is_some() {
+            warn!("Profiler frame was not ended before a new frame began");
+            self.end_frame();
+        }
+
+        // NOTE: We reuse the zone allocations of the oldest frame if our history is full
+        let mut frame = if self.frames.len() >= FRAME_HISTORY_LENGTH {
+            self.frames.pop_front().unwrap_or_default()
+        } else {
+            ProfilerFrame::default()
+        };
+        frame.index = self.frame_counter;
+        frame.start = self.time_since_creation();
+        frame.duration = 0.0;
+        frame.zones.clear();
+
+        self.frame_counter += 1;
+        self.current_frame = Some(frame);
+    }
+
+    pub fn end_frame(&mut self) {
+        while let Some(&zone_index) = self.open_zones.last() {
+            if let Some(ref frame) = self.current_frame {
+                warn!(
+                    "Profiler zone '{}' was not ended before the end of its frame",
+                    frame.zones[zone_index].name
+                );
+            }
+            self.end_zone();
+        }
+
+        let now = self.time_since_creation();
+        if let Some(mut frame) = self.current_frame.take() {
+            frame.duration = now - frame.start;
+            self.frames.push_back(frame);
+        }
+    }
+
+    /// Starts a zone that is nested within all currently open zones. Zones that are started
+    /// outside of a frame are ignored.
+    pub fn begin_zone(&mut self, name: &str) {
+        let now = self.time_since_creation();
+        let depth = self.open_zones.len();
+        if let Some(ref mut frame) = self.current_frame {
+            // NOTE: We need to copy the name because string literals of the game lib become
+            //       invalid after hot reloading it
+            frame.zones.push(ProfilerZone {
+                name: String::from(name),
+                depth,
+                start: now,
+                duration: 0.0,
+            });
+            self.open_zones.push(frame.zones.len() - 1);
+        }
+    }
+
+    /// Ends the most recently started zone
+    pub fn end_zone(&mut self) {
+        let now = self.time_since_creation();
+        if let Some(zone_index) = self.open_zones.pop() {
+            if let Some(ref mut frame) = self.current_frame {
+                let zone = &mut frame.zones[zone_index];
+                zone.duration = now - zone.start;
+            }
+        }
+    }
+
+    /// Starts a zone that is ended automatically when the returned scope goes out of scope
+    pub fn scope(&mut self, name: &str) -> ProfilerScope<'_> {
+        self.begin_zone(name);
+        ProfilerScope { profiler: self }
+    }
+
+    fn time_since_creation(&self) -> f64 {
+        let duration = self.creation_time.elapsed();
+        duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Access
+    //
+
+    /// Returns all recorded frames from oldest to newest
+    pub fn frames(&self) -> impl Iterator<Item = &ProfilerFrame> {
+        self.frames.iter()
+    }
+
+    /// Returns the most recently finished frame
+    pub fn last_frame(&self) -> Option<&ProfilerFrame> {
+        self.frames.back()
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Export
+    //
+
+    /// Returns all recorded frames as JSON in the Chrome trace event format which can be opened
+    /// with `chrome://tracing`
+    pub fn to_chrome_trace_json(&self) -> String {
+        let mut events = Vec::new();
+        for frame in &self.frames {
+            events.push(chrome_trace_event(
+                &format!("frame {}", frame.index),
+                "frame",
+                frame.start,
+                frame.duration,
+            ));
+            for zone in &frame.zones {
+                events.push(chrome_trace_event(
+                    &zone.name,
+                    "zone",
+                    zone.start,
+                    zone.duration,
+                ));
+            }
+        }
+        format!(
+            "{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n",
+            events.join(",\n")
+        )
+    }
+
+    pub fn export_chrome_trace(&self, filename: &str) {
+        utility::write_string_to_file(filename, &self.to_chrome_trace_json());
+        info!(
+            "Exported {} profiled frames to '{}'",
+            self.frames.len(),
+            filename
+        );
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Debug drawing
+    //
+
+    /// Draws a graph of the durations of all recorded frames into the lower part of the given
+    /// rect and a flamegraph of the zones of the last frame into the upper part
+    pub fn debug_draw_overlay(&self, dc: &mut DrawContext, rect: Rect) {
+        dc.draw_rect_filled(
+            rect,
+            OVERLAY_DEPTH_BACKGROUND,
+            OVERLAY_COLOR_BACKGROUND,
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        );
+
+        let graph_top = (rect.bottom - OVERLAY_GRAPH_HEIGHT_RATIO * rect.height()).round();
+        let graph_rect = Rect::from_bounds(rect.left, rect.right, rect.bottom, graph_top);
+        let flamegraph_rect = Rect::from_bounds(rect.left, rect.right, graph_top, rect.top);
+
+        self.debug_draw_frame_graph(dc, graph_rect);
+        if let Some(frame) = self.last_frame() {
+            debug_draw_flamegraph(dc, frame, flamegraph_rect);
+        }
+    }
+
+    fn debug_draw_frame_graph(&self, dc: &mut DrawContext, rect: Rect) {
+        // NOTE: The graph is scaled so that frames taking twice our target duration fill it
+        let max_duration = 2.0 * TARGET_FRAME_DURATION;
+        let bar_width = rect.width() / FRAME_HISTORY_LENGTH as f32;
+
+        // The newest frame is always drawn at the right border
+        let num_frames = self.frames.len();
+        for (frame_index, frame) in self.frames.iter().enumerate() {
+            let bar_height = (f64::min(frame.duration / max_duration, 1.0) as f32) * rect.height();
+            let bar_left = rect.right - (num_frames - frame_index) as f32 * bar_width;
+            let bar_color = if frame.duration <= 1.05 * TARGET_FRAME_DURATION {
+                draw::COLOR_GREEN
+            } else if frame.duration <= max_duration {
+                draw::COLOR_YELLOW
+            } else {
+                draw::COLOR_RED
+            };
+            dc.draw_rect_filled(
+                Rect::from_bounds(
+                    bar_left,
+                    bar_left + bar_width,
+                    rect.bottom,
+                    rect.bottom - bar_height,
+                ),
+                OVERLAY_DEPTH_BARS,
+                bar_color,
+                draw::ADDITIVITY_NONE,
+                DrawSpace::Debug,
+            );
+        }
+
+        let target_line_y = (rect.bottom - 0.5 * rect.height()).round();
+        dc.draw_line(
+            Line::new(
+                Point::new(rect.left, target_line_y),
+                Point::new(rect.right, target_line_y),
+            ),
+            OVERLAY_DEPTH_LINES,
+            draw::COLOR_WHITE,
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        );
+
+        if let Some(frame) = self.last_frame() {
+            let font = dc.font_handle(draw::DEBUG_FONT_RESOURCE_PATH);
+            let line_height = dc.font(font).vertical_advance;
+            let max_frame_duration = self
+                .frames
+                .iter()
+                .map(|frame| frame.duration)
+                .fold(0.0, f64::max);
+            dc.draw_text(
+                Point::new(rect.left + 1.0, target_line_y - line_height),
+                &format!(
+                    "frame: {:.3}ms max: {:.3}ms",
+                    1000.0 * frame.duration,
+                    1000.0 * max_frame_duration
+                ),
+                font,
+                OVERLAY_DEPTH_TEXT,
+                draw::COLOR_WHITE,
+                draw::ADDITIVITY_NONE,
+                DrawSpace::Debug,
+            );
+        }
+    }
+}
+
+/// Ends its zone when dropped. Nested scopes can be created directly from a scope.
+pub struct ProfilerScope<'profiler> {
+    profiler: &'profiler mut Profiler,
+}
+
+impl<'profiler> std::ops::Deref for ProfilerScope<'profiler> {
+    type Target = Profiler;
+
+    fn deref(&self) -> &Profiler {
+        self.profiler
+    }
+}
+
+impl<'profiler> std::ops::DerefMut for ProfilerScope<'profiler> {
+    fn deref_mut(&mut self) -> &mut Profiler {
+        self.profiler
+    }
+}
+
+impl<'profiler> Drop for ProfilerScope<'profiler> {
+    fn drop(&mut self) {
+        self.profiler.end_zone();
+    }
+}
+
+//==================================================================================================
+// Helper functions
+//==================================================================================================
+//
+
+fn debug_draw_flamegraph(dc: &mut DrawContext, frame: &ProfilerFrame, rect: Rect) {
+    if frame.duration <= 0.0 {
+        return;
+    }
+
+    let font = dc.font_handle(draw::DEBUG_FONT_RESOURCE_PATH);
+    for zone in &frame.zones {
+        let relative_start = (zone.start - frame.start) / frame.duration;
+        let relative_duration = zone.duration / frame.duration;
+        let zone_left = (rect.left + relative_start as f32 * rect.width()).round();
+        let zone_right = (zone_left + relative_duration as f32 * rect.width()).round();
+        let zone_top = rect.top + zone.depth as f32 * OVERLAY_ZONE_ROW_HEIGHT;
+        let zone_bottom = zone_top + OVERLAY_ZONE_ROW_HEIGHT - 1.0;
+        if zone_bottom > rect.bottom {
+            continue;
+        }
+
+        let zone_rect = Rect::from_bounds(
+            zone_left,
+            f32::max(zone_right, zone_left + 1.0),
+            zone_bottom,
+            zone_top,
+        );
+        dc.draw_rect_filled(
+            zone_rect,
+            OVERLAY_DEPTH_BARS,
+            OVERLAY_COLORS_ZONES[zone.depth % OVERLAY_COLORS_ZONES.len()],
+            draw::ADDITIVITY_NONE,
+            DrawSpace::Debug,
+        );
+
+        // NOTE: We only build the full label if at least the zone name fits into the zone
+        if dc.get_text_dimensions(&zone.name, font).x + 2.0 > zone_rect.width() {
+            continue;
+        }
+        let label = format!("{} {:.2}ms", zone.name, 1000.0 * zone.duration);
+        if dc.get_text_dimensions(&label, font).x + 2.0 <= zone_rect.width() {
+            dc.draw_text(
+                Point::new(zone_rect.left + 1.0, zone_rect.top + 1.0),
+                &label,
+                font,
+                OVERLAY_DEPTH_TEXT,
+                draw::COLOR_WHITE,
+                draw::ADDITIVITY_NONE,
+                DrawSpace::Debug,
+            );
+        }
+    }
+}
+
+/// Creates a 'complete' event of the Chrome trace event format. Times are given in seconds.
+fn chrome_trace_event(name: &str, category: &str, start: f64, duration: f64) -> String {
+    format!(
+        "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":0}}",
+        escape_json_string(name),
+        category,
+        1_000_000.0 * start,
+        1_000_000.0 * duration
+    )
+}
+
+fn escape_json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -7,6 +7,22 @@ const PONGI_BASE_SPEED: f32 = 15.0 * UNIT_SIZE;
 const WALL_THICKNESS: f32 = 0.5 * UNIT_SIZE;
 const PADDLE_SIZE: f32 = 3.0 * UNIT_SIZE;
 
+const CAMERA_TRANSITION_TIME: f32 = 0.5;
+const CAMERA_TRAUMA_GOAL: f32 = 0.5;
+
+/// How long the position and new direction of pongi stay visible after a bounce in seconds
+const DEBUG_DRAW_BOUNCE_DURATION: f32 = 2.0;
+
+const PARTICLE_EFFECT_PONGI_TRAIL: &str = "pongi_trail";
+const PARTICLE_EFFECT_HIT_SPARKS: &str = "hit_sparks";
+const PARTICLE_EFFECT_SCORE_EXPLOSION: &str = "score_explosion";
+
+const PALETTE_COLOR_PONGI_BEAT: &str = "pongi_beat";
+const PALETTE_COLOR_PADDLE_LEFT: &str = "paddle_left";
+const PALETTE_COLOR_PADDLE_RIGHT: &str = "paddle_right";
+
+const TILEMAP_BACKGROUND: &str = "tilemaps/playfield";
+
 const FIELD_BOUNDS: Rect = Rect {
     left: -10.0 * UNIT_SIZE,
     right: 10.0 * UNIT_SIZE,
@@ -46,7 +62,20 @@ pub struct Globals {
     pub mouse_delta_world: WorldVec,
     pub mouse_delta_canvas: CanvasVec,
 
+    pub canvas_scaling_mode: CanvasScalingMode,
+    /// The area of the canvas that the scenes draw to in canvas space
+    pub canvas_rect: Rect,
+    /// The area of the screen that the canvas is drawn onto
+    pub canvas_blit_rect: Rect,
+    pub postprocess: PostProcessSettings,
+    pub particles: ParticleSystem,
+    /// The named colors shared with the art
+    pub palette: Palette,
+    /// Debug shapes that are drawn on top of everything when their overlay is enabled
+    pub debug_draw: DebugDraw,
+
     pub cam: Camera,
+    pub cam_controller: CameraController,
     pub error_happened: Option<String>,
 }
 
@@ -113,15 +142,22 @@ impl Scene for DebugScene {
         );
 
         // Frametimes etc.
+        let debug_font = dc.default_font();
         let delta = pretty_format_duration_ms(f64::from(input.time_delta));
         let draw = pretty_format_duration_ms(f64::from(input.time_draw));
         let update = pretty_format_duration_ms(f64::from(input.time_update));
         let audio = pretty_format_duration_ms(f64::from(input.time_audio));
+        let mesh_splits = dc.num_mesh_splits();
         dc.debug_draw_text(
             &format!(
-                "delta: {}\ndraw: {}\nupdate: {}\naudio: {}\n",
-                delta, draw, update, audio
+                "delta: {}\ndraw: {}\nupdate: {}\naudio: {}\nmesh splits: {}\n",
+                delta,
+                draw,
+                update,
+                audio,
+                mesh_splits
             ),
+            debug_font,
             draw::COLOR_WHITE,
         );
         dc.debug_draw_text(
@@ -129,6 +165,7 @@ impl Scene for DebugScene {
                 "mouse_screen: {}x{}",
                 input.mouse_pos_screen.x, input.mouse_pos_screen.y
             ),
+            debug_font,
             draw::COLOR_WHITE,
         );
         dc.debug_draw_text(
@@ -136,6 +173,7 @@ impl Scene for DebugScene {
                 "mouse_delta_screen: {}x{}",
                 input.mouse_delta_screen.x, input.mouse_delta_screen.y
             ),
+            debug_font,
             draw::COLOR_WHITE,
         );
         dc.debug_draw_text(
@@ -143,6 +181,7 @@ impl Scene for DebugScene {
                 "mouse_world: {}x{}",
                 globals.mouse_pos_world.x, globals.mouse_pos_world.y
             ),
+            debug_font,
             draw::COLOR_WHITE,
         );
         dc.debug_draw_text(
@@ -150,6 +189,7 @@ impl Scene for DebugScene {
                 "mouse_canvas: {}x{}\n",
                 globals.mouse_pos_canvas.x, globals.mouse_pos_canvas.y
             ),
+            debug_font,
             draw::COLOR_WHITE,
         );
 
@@ -157,6 +197,7 @@ impl Scene for DebugScene {
             if globals.debug_time_factor_increment > 0 {
                 dc.debug_draw_text(
                     &format!("Time speedup {}x", globals.debug_time_factor_increment + 1),
+                    debug_font,
                     draw::COLOR_GREEN,
                 );
             } else if globals.debug_time_factor_increment < 0 {
@@ -165,13 +206,14 @@ impl Scene for DebugScene {
                         "Time slowdown {}x",
                         i32::abs(globals.debug_time_factor_increment) + 1
                     ),
+                    debug_font,
                     draw::COLOR_YELLOW,
                 );
             } else {
             };
         }
         if globals.game_paused {
-            dc.debug_draw_text("The game is paused", draw::COLOR_CYAN);
+            dc.debug_draw_text("The game is paused", debug_font, draw::COLOR_CYAN);
         }
         // Debug crash message
         if globals.error_happened.is_some() {
@@ -180,6 +222,7 @@ impl Scene for DebugScene {
                     "The game has crashed: {}",
                     globals.error_happened.clone().unwrap()
                 ),
+                debug_font,
                 draw::COLOR_RED,
             );
         }
@@ -202,8 +245,13 @@ pub struct GameplayScene {
 
     pongi_pos: WorldPoint,
     pongi_vel: Vec2,
+    pongi_animation: Option<AnimationPlayer>,
+    pongi_rotation: f32,
+    pongi_trail: Option<ParticleEmitter>,
 
     time_till_next_beat: f32,
+    /// Drives the animated tiles of the background
+    background_time: f32,
 
     game_difficulty: GameDifficulty,
     right_player_is_human: bool,
@@ -219,6 +267,9 @@ impl Scene for GameplayScene {
         let angle: f32 = 40.0;
         self.pongi_pos = Point::new(8.0, -4.0) * UNIT_SIZE;
         self.pongi_vel = Vec2::from_angle(angle.to_radians()) * PONGI_BASE_SPEED;
+        self.pongi_animation = None;
+        self.pongi_rotation = 0.0;
+        self.pongi_trail = None;
 
         // gc.pongi_pos = Point::new(-151.48575, -88.0);
         // gc.pongi_vel = Vec2::new(-4644.807, 6393.034);
@@ -238,6 +289,14 @@ impl Scene for GameplayScene {
             self.right_player_is_human = globals.right_player_is_human;
             self.game_difficulty = globals.game_difficulty;
             self.reinitialize(system_commands);
+            globals.particles.clear();
+
+            let field_overview = CameraPreset::new(FIELD_BOUNDS.center(), 1.0);
+            globals.cam_controller.transition_to(
+                &globals.cam,
+                field_overview,
+                CAMERA_TRANSITION_TIME,
+            );
         }
 
         let delta_time =
@@ -258,36 +317,7 @@ impl Scene for GameplayScene {
         // Playfield
         //
 
-        let screen_rect = Rect::from_dimension(input.screen_dim);
-        let canvas_rect = Rect::from_width_height(CANVAS_WIDTH, CANVAS_HEIGHT);
-
-        // Draw grid
-        let grid_light = Color::new(0.9, 0.7, 0.2, 1.0);
-        for x in -30..30 {
-            for diagonal in -20..20 {
-                let pos = Point::new((x + diagonal) as f32, diagonal as f32) * UNIT_SIZE;
-                if x % 2 == 0 {
-                    dc.draw_rect_filled(
-                        Rect::from_point_dimension(pos, Vec2::ones() * UNIT_SIZE),
-                        -1.0,
-                        grid_light,
-                        ADDITIVITY_NONE,
-                        DrawSpace::World,
-                    );
-                } else {
-                    let r = (x + 30) as f32 / 60.0;
-                    let g = (diagonal + 20) as f32 / 40.0;
-                    let b = (r + g) / 2.0;
-                    dc.draw_rect_filled(
-                        Rect::from_point_dimension(pos, Vec2::ones() * UNIT_SIZE),
-                        -1.0,
-                        Color::new(r, g, b, 1.0),
-                        ADDITIVITY_NONE,
-                        DrawSpace::World,
-                    );
-                }
-            }
-        }
+        let canvas_rect = globals.canvas_rect;
 
         // Draw playing field
         let field_depth = -0.4;
@@ -381,25 +411,86 @@ impl Scene for GameplayScene {
         // Write back to game_context
         globals.error_happened = error_happened;
         if globals.error_happened.is_none() {
+            let pongi_bounced = new_pongi_vel.x.signum() != pongi_vel.x.signum()
+                || new_pongi_vel.y.signum() != pongi_vel.y.signum();
+            if pongi_bounced {
+                // NOTE: Bouncing off the left or right wall is where a player would score
+                let hit_goal = new_pongi_vel.x.signum() != pongi_vel.x.signum();
+                let effect_name = if hit_goal {
+                    PARTICLE_EFFECT_SCORE_EXPLOSION
+                } else {
+                    PARTICLE_EFFECT_HIT_SPARKS
+                };
+                globals.particles.emit_burst(effect_name, new_pongi_pos);
+                if hit_goal {
+                    globals.cam_controller.add_trauma(CAMERA_TRAUMA_GOAL);
+                }
+                globals.debug_draw.point(
+                    new_pongi_pos,
+                    effect_name,
+                    draw::COLOR_RED,
+                    DEBUG_DRAW_BOUNCE_DURATION,
+                );
+                globals.debug_draw.arrow(
+                    new_pongi_pos,
+                    new_pongi_pos + 0.1 * new_pongi_vel,
+                    draw::COLOR_RED,
+                    DEBUG_DRAW_BOUNCE_DURATION,
+                );
+            }
+
             self.pongi_vel = new_pongi_vel;
             self.pongi_pos = new_pongi_pos;
             self.time_till_next_beat = time_till_next_beat;
             self.paddle_left_pos = clamp(
                 self.paddle_left_pos
-                    + input.mouse_delta_screen.y / screen_rect.height() * canvas_rect.height(),
+                    + input.mouse_delta_screen.y / globals.canvas_blit_rect.height()
+                        * canvas_rect.height(),
                 FIELD_BOUNDS.top,
                 FIELD_BOUNDS.bottom - PADDLE_SIZE,
             );
         }
 
+        // Update camera
+        //
+        // NOTE: At a zoom level of 1 the canvas already shows the whole field so following pongi
+        //       only has a visible effect after zooming in with the mouse wheel. We only keep the
+        //       camera inside the field while following as the bounds would otherwise undo
+        //       panning with the mouse.
+        //       The camera is updated after pongi has moved so that it does not lag behind.
+        if input.is_pressed("debug_camera_follow_toggle") {
+            globals.cam_controller.bounds =
+                Some(FIELD_BOUNDS.extended_uniformly_by(WALL_THICKNESS));
+            globals.cam_controller.follow(self.pongi_pos);
+        } else {
+            globals.cam_controller.bounds = None;
+            globals.cam_controller.stop_following();
+        }
+        globals
+            .cam_controller
+            .update(&mut globals.cam, input.time_delta);
+
+        // Draw background
+        self.background_time += delta_time;
+        let background = dc.tilemap_handle(TILEMAP_BACKGROUND);
+        let background_pos = dc.tilemap(background).bounds(WorldPoint::zero()).dim() * -0.5;
+        dc.draw_tilemap(
+            background,
+            background_pos,
+            globals.cam.frustum(),
+            self.background_time,
+            DrawSpace::World,
+        );
+
         // Debug draw sphere sweeping
         collision_mesh
             .shapes
             .iter()
             .map(|rect| RectSphereSum::new(rect, PONGI_RADIUS))
             .for_each(|sum| {
-                dc.draw_lines(
+                dc.draw_lines_thick(
                     &sum.to_lines(),
+                    LineStyle::new(2.0).with_cap(LineCap::Round),
                     0.0,
                     COLOR_YELLOW,
                     ADDITIVITY_NONE,
@@ -426,26 +517,67 @@ impl Scene for GameplayScene {
         );
 
         // Draw pongi
-        dc.debug_draw_text(&dformat!(self.pongi_vel), draw::COLOR_WHITE);
-        dc.debug_draw_text(&dformat!(self.pongi_pos), draw::COLOR_WHITE);
-        dc.draw_arrow(
+        let debug_font = dc.default_font();
+        dc.debug_draw_text(&dformat!(self.pongi_vel), debug_font, draw::COLOR_WHITE);
+        dc.debug_draw_text(&dformat!(self.pongi_pos), debug_font, draw::COLOR_WHITE);
+        dc.draw_arrow_thick(
             self.pongi_pos.pixel_snapped(),
             self.pongi_vel.normalized(),
             0.3 * self.pongi_vel.magnitude(),
+            LineStyle::new(2.0),
+            -0.1,
+            draw::COLOR_GREEN,
+            ADDITIVITY_NONE,
+            DrawSpace::World,
+        );
+        dc.draw_circle(
+            Circle::new(self.pongi_pos.pixel_snapped(), PONGI_RADIUS),
+            1.0,
+            CurveDetail::Pixelated,
             -0.1,
             draw::COLOR_GREEN,
             ADDITIVITY_NONE,
             DrawSpace::World,
         );
 
-        dc.debug_draw_circle_textured(
-            self.pongi_pos.pixel_snapped(),
+        let pongi_animation = self.pongi_animation.get_or_insert_with(|| {
+            AnimationPlayer::new(
+                dc.animation_handle("images/test"),
+                AnimationPlaybackMode::Loop,
+            )
+        });
+        pongi_animation.update(delta_time, dc.animation(pongi_animation.animation));
+        // NOTE: We let pongi spin as if it would roll along its path
+        self.pongi_rotation = (self.pongi_rotation
+            + delta_time * self.pongi_vel.magnitude() / PONGI_RADIUS)
+            % (2.0 * PI);
+        dc.draw_animation_transformed(
+            pongi_animation,
+            SpriteTransform::from_pos(self.pongi_pos.pixel_snapped())
+                .with_rotation(self.pongi_rotation),
             -0.3,
-            Color::new(1.0 - beat_value, 1.0 - beat_value, 1.0, 1.0),
+            QuadColors::vertical_gradient(
+                lerp_color(
+                    draw::COLOR_WHITE,
+                    globals.palette.color_by_name(PALETTE_COLOR_PONGI_BEAT),
+                    beat_value,
+                ),
+                draw::COLOR_WHITE,
+            ),
             ADDITIVITY_NONE,
             DrawSpace::World,
         );
 
+        // Update and draw particles
+        let pongi_pos = self.pongi_pos;
+        let pongi_trail = self
+            .pongi_trail
+            .get_or_insert_with(|| ParticleEmitter::new(PARTICLE_EFFECT_PONGI_TRAIL, pongi_pos));
+        pongi_trail.pos = pongi_pos;
+        globals.particles.update_emitter(pongi_trail, delta_time);
+        globals.particles.update(delta_time);
+        globals.particles.draw(dc, DrawSpace::World);
+
         // Draw paddles
         dc.draw_rect_filled(
             Rect::from_point(
@@ -454,7 +586,7 @@ impl Scene for GameplayScene {
                 PADDLE_SIZE,
             ),
             -0.2,
-            COLOR_WHITE,
+            globals.palette.color_by_name(PALETTE_COLOR_PADDLE_LEFT),
             ADDITIVITY_NONE,
             DrawSpace::World,
         );
@@ -465,7 +597,7 @@ impl Scene for GameplayScene {
                 PADDLE_SIZE,
             ),
             -0.2,
-            COLOR_WHITE,
+            globals.palette.color_by_name(PALETTE_COLOR_PADDLE_RIGHT),
             ADDITIVITY_NONE,
             DrawSpace::World,
         );
@@ -612,7 +744,7 @@ impl Scene for MenuScene {
         _ac: &mut AudioContext,
         system_commands: &mut Vec<SystemCommand>,
     ) {
-        let canvas_rect = Rect::from_width_height(CANVAS_WIDTH, CANVAS_HEIGHT);
+        let canvas_rect = globals.canvas_rect;
 
         // Update screen fader
         self.screen_fader.increment(input.time_delta);
@@ -636,16 +768,15 @@ impl Scene for MenuScene {
 
         // Fade overlay
         if self.screen_fader.fading_overlay_opacity() > 0.0 {
+            dc.set_render_layer(RenderLayer::Overlay);
             dc.draw_rect_filled(
                 canvas_rect,
                 0.0,
                 Color::new(1.0, 1.0, 1.0, self.screen_fader.fading_overlay_opacity()),
                 ADDITIVITY_NONE,
-                // TODO(JaSc): For now we just use the debug-drawspace as a hack until we implement
-                //             transparency sorting. If we would draw this in canvas-drawspace
-                //             it would interfere with menu overlay.
-                DrawSpace::Debug,
+                DrawSpace::Canvas,
             );
+            dc.set_render_layer(RenderLayer::Default);
         }
 
         // Enable or disable relative mouse movement capture
@@ -769,18 +900,19 @@ fn create_button_menu(
     }
 
     // Create button sizes
+    let font = dc.default_font();
     let button_margin = 1.0;
     let button_padding = 4.0;
     let button_dim = menu_items
         .iter()
-        .map(|item| dc.get_text_dimensions(item) + 2.0 * button_padding)
+        .map(|item| dc.get_text_dimensions(item, font) + 2.0 * button_padding)
         .fold(Rect::zero(), |acc, dim| {
             Rect::smallest_rect_that_contains_both_rects(acc, Rect::from_dimension(dim))
         })
         .dim();
 
     // Create and draw menu box
-    let menu_padding = 2.0;
+    let menu_padding = 3.0;
     let menu_height = 2.0 * menu_padding
         + menu_items.len() as f32 * button_dim.y
         + ((menu_items.len() - 1) as f32) * button_margin;
@@ -788,11 +920,13 @@ fn create_button_menu(
     let menu_box = Rect::from_width_height(menu_width, menu_height)
         .centered_in_rect(canvas_rect)
         .with_pixel_snapped_position();
-    dc.draw_rect_filled(menu_box, depth, COLOR_CYAN, 0.0, DrawSpace::Canvas);
-    dc.draw_rect(
+    let menu_panel = dc.nine_slice_handle("images/ui_panel");
+    dc.draw_nine_slice(
         menu_box,
+        menu_panel,
+        NineSliceMode::Stretch,
         depth,
-        Color::new(0.4, 0.4, 0.4, 0.4),
+        COLOR_WHITE,
         ADDITIVITY_NONE,
         DrawSpace::Canvas,
     );
@@ -811,7 +945,7 @@ fn create_button_menu(
             .with_pixel_snapped_position();
         vertical_offset += button_rect.height() + button_margin;
 
-        if gui.button(index, label, button_rect, depth, dc) {
+        if gui.button(index, label, font, button_rect, depth, dc) {
             clicked_button_index = Some(index);
         }
     }
@@ -0,0 +1,446 @@
+use crate::color::parse_hex_color;
+use crate::draw::{Font, FontHandle, Glyph, Sprite};
+use crate::math::{Color, Point, Rect, Vec2};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+impl Default for TextAlignment {
+    fn default() -> TextAlignment {
+        TextAlignment::Left
+    }
+}
+
+/// A piece of text that is drawn with the same color and font
+#[derive(Debug, Clone, Copy)]
+pub struct TextRun<'text> {
+    pub text: &'text str,
+    pub color: Color,
+    pub font: FontHandle,
+}
+
+impl<'text> TextRun<'text> {
+    pub fn new(text: &'text str, color: Color, font: FontHandle) -> TextRun<'text> {
+        TextRun { text, color, font }
+    }
+}
+
+//==================================================================================================
+// Font
+//==================================================================================================
+//
+
+impl Font {
+    /// Returns the glyph of the given character or the fallback glyph if the font does not
+    /// contain it. Control characters are treated as whitespace.
+    pub fn glyph(&self, c: char) -> Glyph {
+        let c = if c.is_control() { ' ' } else { c };
+        self.glyphs.get(&c).cloned().unwrap_or(self.fallback_glyph)
+    }
+}
+
+//==================================================================================================
+// Markup
+//==================================================================================================
+//
+
+/// Splits a string with inline style tags into text runs. Supported tags are
+/// `[color=RRGGBB]`, `[color=RRGGBBAA]`, `[/color]`, `[font=<resource path>]` and `[/font]`.
+/// Tags can be nested and `[[` is used to write a literal `[`. Unknown tags are kept as text.
+///
+/// # Examples
+/// ```
+/// # use game_lib::{Color, FontHandle};
+/// # use game_lib::text::parse_text_markup;
+/// let runs = parse_text_markup(
+///     "Press [color=ff0000]Escape[/color] to [[quit]",
+///     Color::new(1.0, 1.0, 1.0, 1.0),
+///     FontHandle::default(),
+///     |_font_resource_path| FontHandle::default(),
+/// );
+///
+/// let texts: Vec<&str> = runs.iter().map(|run| run.text).collect();
+/// assert_eq!(texts, vec!["Press ", "Escape", " to [", "quit]"]);
+/// assert_eq!(runs[1].color, Color::new(1.0, 0.0, 0.0, 1.0));
+/// ```
+pub fn parse_text_markup<'text, F>(
+    markup: &'text str,
+    color: Color,
+    font: FontHandle,
+    mut font_handle: F,
+) -> Vec<TextRun<'text>>
+where
+    F: FnMut(&str) -> FontHandle,
+{
+    let mut runs = Vec::new();
+    let mut color_stack = vec![color];
+    let mut font_stack = vec![font];
+    let push_run = |runs: &mut Vec<TextRun<'text>>, text: &'text str, color, font| {
+        if !text.is_empty() {
+            runs.push(TextRun::new(text, color, font));
+        }
+    };
+
+    let mut run_start = 0;
+    let mut search_start = 0;
+    while let Some(tag_start) = markup[search_start..]
+        .find('[')
+        .map(|pos| search_start + pos)
+    {
+        let current_color = *color_stack.last().unwrap();
+        let current_font = *font_stack.last().unwrap();
+
+        if markup[tag_start + 1..].starts_with('[') {
+            push_run(
+                &mut runs,
+                &markup[run_start..=tag_start],
+                current_color,
+                current_font,
+            );
+            run_start = tag_start + 2;
+            search_start = run_start;
+            continue;
+        }
+
+        let tag_end = match markup[tag_start..].find(']') {
+            Some(pos) => tag_start + pos,
+            None => break,
+        };
+        let tag = &markup[tag_start + 1..tag_end];
+
+        let is_known_tag = match tag {
+            "/color" => color_stack.len() > 1,
+            "/font" => font_stack.len() > 1,
+            _ => match (tag_value(tag, "color"), tag_value(tag, "font")) {
+                (Some(hex), _) => parse_hex_color(hex).is_some(),
+                (None, font_resource_path) => font_resource_path.is_some(),
+            },
+        };
+        if !is_known_tag {
+            warn!("Unknown text markup tag '[{}]' in '{}'", tag, markup);
+            search_start = tag_start + 1;
+            continue;
+        }
+
+        push_run(
+            &mut runs,
+            &markup[run_start..tag_start],
+            current_color,
+            current_font,
+        );
+        match tag {
+            "/color" => {
+                color_stack.pop();
+            }
+            "/font" => {
+                font_stack.pop();
+            }
+            _ => {
+                if let Some(color) = tag_value(tag, "color").and_then(parse_hex_color) {
+                    color_stack.push(color);
+                } else if let Some(font_resource_path) = tag_value(tag, "font") {
+                    font_stack.push(font_handle(font_resource_path));
+                }
+            }
+        }
+        run_start = tag_end + 1;
+        search_start = run_start;
+    }
+
+    push_run(
+        &mut runs,
+        &markup[run_start..],
+        *color_stack.last().unwrap(),
+        *font_stack.last().unwrap(),
+    );
+    runs
+}
+
+/// Returns the value of a markup tag of the form `name=value`
+fn tag_value<'tag>(tag: &'tag str, name: &str) -> Option<&'tag str> {
+    if tag.starts_with(name) && tag[name.len()..].starts_with('=') {
+        Some(&tag[name.len() + 1..])
+    } else {
+        None
+    }
+}
+
+//==================================================================================================
+// TextLayout
+//==================================================================================================
+//
+
+#[derive(Debug, Clone, Copy)]
+pub struct LaidOutGlyph {
+    pub sprite: Sprite,
+    /// Position of the glyph relative to the top-left corner of the layout
+    pub pos: Point,
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextLine {
+    /// Index of the first glyph of this line in `TextLayout::glyphs`
+    pub first_glyph: usize,
+    pub num_glyphs: usize,
+    /// Position of the line relative to the top-left corner of the layout
+    pub pos: Point,
+    pub width: f32,
+    /// The vertical advance of the largest font in this line
+    pub height: f32,
+}
+
+/// The positions of all glyphs of a text. Measuring and drawing text both use this so that they
+/// always agree with each other.
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<LaidOutGlyph>,
+    pub lines: Vec<TextLine>,
+    /// The size of the bounding box of all visible glyphs
+    pub dimensions: Vec2,
+    /// The position right after the last character of the text relative to the top-left corner
+    /// of the layout. This is where text would continue to be written.
+    pub end_pos: Point,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingGlyph {
+    glyph: Glyph,
+    color: Color,
+    x: f32,
+    is_whitespace: bool,
+    font_height: f32,
+    vertical_advance: f32,
+    baseline: f32,
+}
+
+#[derive(Debug, Default)]
+struct PendingLine {
+    glyphs: Vec<PendingGlyph>,
+    cursor: f32,
+    height: f32,
+    font_height: f32,
+    /// The largest baseline of all fonts in this line. Glyphs of all fonts are aligned on it.
+    baseline: f32,
+    /// Index of the first glyph after the last whitespace where we can break the line
+    break_index: Option<usize>,
+}
+
+impl PendingLine {
+    fn push(&mut self, mut glyph: PendingGlyph) {
+        glyph.x = self.cursor;
+        self.cursor += glyph.glyph.horizontal_advance;
+        self.baseline = f32::max(self.baseline, glyph.baseline);
+        self.height = f32::max(self.height, glyph.vertical_advance);
+        self.font_height = f32::max(self.font_height, glyph.font_height);
+        if glyph.is_whitespace {
+            self.break_index = Some(self.glyphs.len() + 1);
+        }
+        self.glyphs.push(glyph);
+    }
+
+    /// The width of the line without trailing whitespace
+    fn width(&self) -> f32 {
+        self.glyphs
+            .iter()
+            .filter(|glyph| !glyph.is_whitespace)
+            .map(|glyph| glyph.x + glyph.glyph.horizontal_advance)
+            .fold(0.0, f32::max)
+    }
+
+    fn has_visible_glyphs(&self) -> bool {
+        self.glyphs.iter().any(|glyph| !glyph.is_whitespace)
+    }
+
+    /// How far a glyph needs to be moved down to sit on the baseline of the line
+    fn baseline_offset(&self, glyph: &PendingGlyph) -> f32 {
+        self.baseline - glyph.baseline
+    }
+
+    /// The height of the line after its glyphs were aligned on the baseline
+    fn aligned_height(&self) -> f32 {
+        self.glyphs
+            .iter()
+            .map(|glyph| self.baseline_offset(glyph) + glyph.vertical_advance)
+            .fold(self.height, f32::max)
+    }
+
+    /// The height of the visible part of the line after its glyphs were aligned on the baseline
+    fn aligned_font_height(&self) -> f32 {
+        self.glyphs
+            .iter()
+            .map(|glyph| self.baseline_offset(glyph) + glyph.font_height)
+            .fold(self.font_height, f32::max)
+    }
+}
+
+impl TextLayout {
+    /// Lays out the given text runs line by line. If a maximum width is given, lines are wrapped
+    /// at whitespace or inside of words that do not fit into a line on their own. The
+    /// `font` closure is used to look up the fonts of the runs.
+    pub fn new<'font, F>(
+        runs: &[TextRun],
+        font: F,
+        alignment: TextAlignment,
+        max_width: Option<f32>,
+    ) -> TextLayout
+    where
+        F: Fn(FontHandle) -> &'font Font,
+    {
+        let mut lines = vec![PendingLine::default()];
+        for run in runs {
+            let font = font(run.font);
+            for c in run.text.chars() {
+                if c == '\n' {
+                    let line = lines.last_mut().unwrap();
+                    line.height = f32::max(line.height, font.vertical_advance);
+                    lines.push(PendingLine::default());
+                    continue;
+                }
+
+                let glyph = PendingGlyph {
+                    glyph: font.glyph(c),
+                    color: run.color,
+                    x: 0.0,
+                    is_whitespace: c.is_whitespace(),
+                    font_height: font.font_height,
+                    vertical_advance: font.vertical_advance,
+                    baseline: font.baseline,
+                };
+
+                if let Some(max_width) = max_width {
+                    let line = lines.last_mut().unwrap();
+                    let exceeds_max_width =
+                        line.cursor + glyph.glyph.horizontal_advance > max_width;
+                    if !glyph.is_whitespace && exceeds_max_width && line.has_visible_glyphs() {
+                        // Move the word we are currently in to the next line. If the line consists
+                        // of only one word we break the word itself.
+                        let break_index = line.break_index.unwrap_or(line.glyphs.len());
+                        let wrapped_glyphs: Vec<PendingGlyph> =
+                            line.glyphs.drain(break_index..).collect();
+                        line.break_index = None;
+                        line.cursor = wrapped_glyphs
+                            .first()
+                            .map(|glyph| glyph.x)
+                            .unwrap_or(line.cursor);
+
+                        let mut new_line = PendingLine::default();
+                        for wrapped_glyph in wrapped_glyphs {
+                            new_line.push(wrapped_glyph);
+                        }
+                        lines.push(new_line);
+                    }
+                }
+
+                lines.last_mut().unwrap().push(glyph);
+            }
+        }
+
+        let layout_width =
+            max_width.unwrap_or_else(|| lines.iter().map(|line| line.width()).fold(0.0, f32::max));
+
+        let mut layout = TextLayout::default();
+        let mut line_top = 0.0;
+        for line in &lines {
+            let width = line.width();
+            let alignment_offset = match alignment {
+                TextAlignment::Left => 0.0,
+                TextAlignment::Center => (0.5 * (layout_width - width)).floor(),
+                TextAlignment::Right => (layout_width - width).floor(),
+            };
+            let line_pos = Point::new(alignment_offset, line_top);
+
+            layout.lines.push(TextLine {
+                first_glyph: layout.glyphs.len(),
+                num_glyphs: line
+                    .glyphs
+                    .iter()
+                    .filter(|glyph| !glyph.is_whitespace)
+                    .count(),
+                pos: line_pos,
+                width,
+                height: line.aligned_height(),
+            });
+            layout
+                .glyphs
+                .extend(
+                    line.glyphs
+                        .iter()
+                        .filter(|glyph| !glyph.is_whitespace)
+                        .map(|glyph| LaidOutGlyph {
+                            sprite: glyph.glyph.sprite,
+                            pos: line_pos + Vec2::new(glyph.x, line.baseline_offset(glyph)),
+                            color: glyph.color,
+                        }),
+                );
+
+            if line.has_visible_glyphs() {
+                layout.dimensions.x = f32::max(layout.dimensions.x, alignment_offset + width);
+                layout.dimensions.y =
+                    f32::max(layout.dimensions.y, line_top + line.aligned_font_height());
+            }
+            layout.end_pos = Point::new(alignment_offset + line.cursor, line_top);
+            line_top += line.aligned_height();
+        }
+        layout
+    }
+
+    /// Returns the positioned quads of all glyphs together with their uvs. Glyphs that are
+    /// partially outside of the clipping rect are cut off.
+    pub fn glyph_quads(
+        &self,
+        origin: Point,
+        clip_rect: Option<Rect>,
+    ) -> impl Iterator<Item = (LaidOutGlyph, Rect, Rect)> + '_ {
+        self.glyphs.iter().filter_map(move |glyph| {
+            let rect = glyph.sprite.vertex_bounds.translated_by(origin + glyph.pos);
+            let uv = glyph.sprite.uv_bounds;
+            match clip_rect {
+                Some(clip_rect) => {
+                    clip_quad(rect, uv, clip_rect).map(|(rect, uv)| (*glyph, rect, uv))
+                }
+                None => Some((*glyph, rect, uv)),
+            }
+        })
+    }
+}
+
+/// Cuts off the parts of a quad that lie outside of the clipping rect and adjusts its uvs
+/// accordingly. Returns `None` if nothing is left of the quad. Both rects need to have their top
+/// above their bottom as it is the case in canvas space.
+fn clip_quad(rect: Rect, uv: Rect, clip_rect: Rect) -> Option<(Rect, Rect)> {
+    debug_assert!(
+        rect.top <= rect.bottom,
+        "Quad {:?} is flipped vertically",
+        rect
+    );
+    debug_assert!(
+        clip_rect.top <= clip_rect.bottom,
+        "Clipping rect {:?} is flipped vertically",
+        clip_rect
+    );
+    let clipped = Rect {
+        left: f32::max(rect.left, clip_rect.left),
+        right: f32::min(rect.right, clip_rect.right),
+        top: f32::max(rect.top, clip_rect.top),
+        bottom: f32::min(rect.bottom, clip_rect.bottom),
+    };
+    if clipped.left >= clipped.right || clipped.top >= clipped.bottom {
+        return None;
+    }
+
+    let uv_at = |pos: f32, start: f32, end: f32, uv_start: f32, uv_end: f32| {
+        uv_start + (uv_end - uv_start) * (pos - start) / (end - start)
+    };
+    let clipped_uv = Rect {
+        left: uv_at(clipped.left, rect.left, rect.right, uv.left, uv.right),
+        right: uv_at(clipped.right, rect.left, rect.right, uv.left, uv.right),
+        top: uv_at(clipped.top, rect.top, rect.bottom, uv.top, uv.bottom),
+        bottom: uv_at(clipped.bottom, rect.top, rect.bottom, uv.top, uv.bottom),
+    };
+    Some((clipped, clipped_uv))
+}
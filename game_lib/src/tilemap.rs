@@ -0,0 +1,201 @@
+use crate::animation::AnimationPlaybackMode;
+use crate::draw::{Animation, Sprite};
+use crate::math::{clamp, Point, Rect, Vec2, WorldPoint};
+
+use std::ops::Range;
+
+//==================================================================================================
+// Tile
+//==================================================================================================
+//
+
+/// A tile of the tileset of a tilemap. Animated tiles loop through the frames of their animation
+/// instead of showing their sprite.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Tile {
+    pub sprite: Sprite,
+    pub animation: Option<Animation>,
+}
+
+impl Tile {
+    /// Returns the sprite that is shown at the given time in seconds. All animated tiles of a
+    /// tilemap share the same clock so that they stay in sync.
+    pub fn sprite_at_time(&self, time: f32) -> Sprite {
+        match &self.animation {
+            Some(animation) if animation.num_frames() > 0 => {
+                animation.frames[animation.frame_index_at_time(time, AnimationPlaybackMode::Loop)]
+            }
+            _ => self.sprite,
+        }
+    }
+}
+
+//==================================================================================================
+// TileLayer
+//==================================================================================================
+//
+
+/// A grid of tiles. The cells are stored row by row starting at the top-left corner and contain
+/// indices into the tiles of their tilemap. Empty cells are `None`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TileLayer {
+    pub name: String,
+    pub num_columns: usize,
+    pub num_rows: usize,
+    pub cells: Vec<Option<u32>>,
+    /// Offset of the layer relative to the top-left corner of the tilemap in pixels
+    pub offset: Vec2,
+    pub depth: f32,
+    pub opacity: f32,
+    pub is_visible: bool,
+}
+
+impl TileLayer {
+    pub fn new(name: &str, num_columns: usize, num_rows: usize, depth: f32) -> TileLayer {
+        TileLayer {
+            name: String::from(name),
+            num_columns,
+            num_rows,
+            cells: vec![None; num_columns * num_rows],
+            offset: Vec2::zero(),
+            depth,
+            opacity: 1.0,
+            is_visible: true,
+        }
+    }
+
+    pub fn cell(&self, column: usize, row: usize) -> Option<u32> {
+        assert!(column < self.num_columns && row < self.num_rows);
+        self.cells[row * self.num_columns + column]
+    }
+
+    pub fn set_cell(&mut self, column: usize, row: usize, tile_index: Option<u32>) {
+        assert!(column < self.num_columns && row < self.num_rows);
+        self.cells[row * self.num_columns + column] = tile_index;
+    }
+}
+
+//==================================================================================================
+// Tilemap
+//==================================================================================================
+//
+
+/// Layers of tiles that share one tileset. Tilemaps are usually designed in Tiled and imported
+/// by the asset packer, but they can also be built in code.
+///
+/// # Example
+/// ```
+/// # use game_lib::math::*;
+/// # use game_lib::tilemap::*;
+/// let mut tilemap = Tilemap::new(Vec2::ones() * 16.0);
+/// tilemap.tiles.push(Tile::default());
+///
+/// let mut background = TileLayer::new("background", 4, 2, -1.0);
+/// background.set_cell(3, 1, Some(0));
+/// tilemap.layers.push(background);
+///
+/// assert_eq!(tilemap.layer("background").unwrap().cell(3, 1), Some(0));
+/// assert_eq!(tilemap.bounds(WorldPoint::zero()).dim(), Vec2::new(64.0, 32.0));
+/// ```
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Tilemap {
+    pub tile_dim: Vec2,
+    pub tiles: Vec<Tile>,
+    pub layers: Vec<TileLayer>,
+}
+
+impl Tilemap {
+    pub fn new(tile_dim: Vec2) -> Tilemap {
+        Tilemap {
+            tile_dim,
+            tiles: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    /// Returns the area that all layers cover if the top-left corner of the tilemap is placed at
+    /// the given position
+    pub fn bounds(&self, pos: WorldPoint) -> Rect {
+        self.layers
+            .iter()
+            .map(|layer| self.layer_bounds(layer, pos))
+            .fold(None, |bounds, layer_bounds| match bounds {
+                None => Some(layer_bounds),
+                Some(bounds) => Some(Rect::smallest_rect_that_contains_both_rects(
+                    bounds,
+                    layer_bounds,
+                )),
+            })
+            .unwrap_or_else(|| Rect::from_point_dimension(pos, Vec2::zero()))
+    }
+
+    pub fn layer_bounds(&self, layer: &TileLayer, pos: WorldPoint) -> Rect {
+        let dim = Vec2::new(layer.num_columns as f32, layer.num_rows as f32) * self.tile_dim;
+        Rect::from_point_dimension(pos + layer.offset, dim)
+    }
+}
+
+/// Returns the columns and rows of a grid that overlap the given visible area. This allows us to
+/// skip all tiles outside of the camera frustum without looking at them.
+///
+/// # Example
+/// ```
+/// # use game_lib::math::*;
+/// # use game_lib::tilemap::*;
+/// let cell_dim = Vec2::ones() * 10.0;
+///
+/// let visible_rect = Rect::from_bounds(15.0, 35.0, 1000.0, -50.0);
+/// let (columns, rows) = visible_cell_range(Point::zero(), cell_dim, 10, 10, visible_rect);
+/// assert_eq!(columns, 1..4);
+/// assert_eq!(rows, 0..10);
+///
+/// let visible_rect = Rect::from_bounds(200.0, 300.0, 20.0, 0.0);
+/// let (columns, _) = visible_cell_range(Point::zero(), cell_dim, 10, 10, visible_rect);
+/// assert!(columns.len() == 0);
+/// ```
+pub fn visible_cell_range(
+    grid_pos: Point,
+    cell_dim: Vec2,
+    num_columns: usize,
+    num_rows: usize,
+    visible_rect: Rect,
+) -> (Range<usize>, Range<usize>) {
+    (
+        visible_cell_range_on_axis(
+            visible_rect.left,
+            visible_rect.right,
+            grid_pos.x,
+            cell_dim.x,
+            num_columns,
+        ),
+        visible_cell_range_on_axis(
+            visible_rect.top,
+            visible_rect.bottom,
+            grid_pos.y,
+            cell_dim.y,
+            num_rows,
+        ),
+    )
+}
+
+fn visible_cell_range_on_axis(
+    visible_start: f32,
+    visible_end: f32,
+    grid_start: f32,
+    cell_size: f32,
+    num_cells: usize,
+) -> Range<usize> {
+    let first = f32::floor((visible_start - grid_start) / cell_size);
+    let last = f32::ceil((visible_end - grid_start) / cell_size);
+    let first = clamp(first, 0.0, num_cells as f32) as usize;
+    let last = clamp(last, 0.0, num_cells as f32) as usize;
+    first..usize::max(first, last)
+}
@@ -1,5 +1,7 @@
 use bincode;
+use crate::draw::Pixel;
 use crate::math;
+use lodepng;
 use ron;
 use serde;
 use std;
@@ -137,6 +139,18 @@ pub fn write_string_to_file(filename: &str, data: &str) {
     });
 }
 
+/// Encodes the given pixels as RGBA png image and writes it to a file. The pixels are expected
+/// to be in row-major order starting at the top left of the image.
+pub fn save_pixels_to_png_file(
+    filename: &str,
+    pixels: &[Pixel],
+    width: usize,
+    height: usize,
+) -> Result<(), lodepng::Error> {
+    debug_assert!(pixels.len() == width * height);
+    lodepng::encode32_file(filename, pixels, width, height)
+}
+
 //==================================================================================================
 // CountdownTimer
 //==================================================================================================
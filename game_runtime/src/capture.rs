@@ -0,0 +1,370 @@
+use game_lib::{DrawCapture, FramebufferTarget, Pixel, RecordingFormat};
+
+use crate::graphics::Readback;
+
+use failure::{self, Error, ResultExt};
+use gif;
+use log::*;
+use std;
+use std::collections::HashMap;
+
+const SCREENSHOT_DIR: &str = "screenshots";
+const RECORDING_DIR: &str = "recordings";
+const DRAW_CAPTURE_DIR: &str = "captures";
+
+/// GIF frame delays are given in hundredths of a second
+const GIF_DELAY_UNITS_PER_SECOND: f64 = 100.0;
+
+// NOTE: Most GIF viewers replace frame delays smaller than 20ms with 100ms which would make our
+//       recordings play back way too slow
+const GIF_MIN_FRAME_DELAY: u16 = 2;
+
+/// Returns a string of the form `<seconds>_<milliseconds>` since the unix epoch
+fn create_timestamp_string() -> Result<String, Error> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .context("Could not determine current time")?;
+    Ok(format!(
+        "{}_{:03}",
+        timestamp.as_secs(),
+        timestamp.subsec_millis()
+    ))
+}
+
+//==================================================================================================
+// Screenshots
+//==================================================================================================
+//
+
+pub fn save_screenshot(readback: &Readback) -> Result<(), Error> {
+    std::fs::create_dir_all(SCREENSHOT_DIR).context(format!(
+        "Could not create screenshot directory '{}'",
+        SCREENSHOT_DIR
+    ))?;
+    let filename = format!(
+        "{}/screenshot_{}_{}.png",
+        SCREENSHOT_DIR,
+        readback.framebuffer_info.name.to_lowercase(),
+        create_timestamp_string()?
+    );
+    game_lib::utility::save_pixels_to_png_file(
+        &filename,
+        &readback.pixels,
+        usize::from(readback.framebuffer_info.width),
+        usize::from(readback.framebuffer_info.height),
+    )
+    .context(format!("Could not write screenshot to '{}'", filename))?;
+    info!("Saved screenshot to '{}'", filename);
+
+    Ok(())
+}
+
+//==================================================================================================
+// Draw captures
+//==================================================================================================
+//
+
+pub fn save_draw_capture(draw_capture: &DrawCapture) -> Result<(), Error> {
+    std::fs::create_dir_all(DRAW_CAPTURE_DIR).context(format!(
+        "Could not create draw capture directory '{}'",
+        DRAW_CAPTURE_DIR
+    ))?;
+    let filename = format!(
+        "{}/drawcapture_{}.bin",
+        DRAW_CAPTURE_DIR,
+        create_timestamp_string()?
+    );
+    draw_capture
+        .save_to_file(&filename)
+        .context(format!("Could not write draw capture to '{}'", filename))?;
+    info!(
+        "Saved draw capture with {} frames to '{}'",
+        draw_capture.frames.len(),
+        filename
+    );
+
+    Ok(())
+}
+
+pub fn load_draw_capture(filepath: &str) -> Result<DrawCapture, Error> {
+    let draw_capture = DrawCapture::load_from_file(filepath)
+        .context(format!("Could not read draw capture '{}'", filepath))?;
+    info!(
+        "Loaded draw capture with {} frames from '{}'",
+        draw_capture.frames.len(),
+        filepath
+    );
+
+    Ok(draw_capture)
+}
+
+//==================================================================================================
+// FrameRecorder
+//==================================================================================================
+//
+
+struct RecordedFrame {
+    /// Time since startup in seconds at which the frame was captured
+    timestamp: f64,
+    pixels: Vec<Pixel>,
+}
+
+/// Encodes the frames of a recording while they arrive
+enum FrameWriter {
+    PngSequence {
+        dirpath: String,
+        frame_durations: String,
+    },
+    Gif {
+        filepath: String,
+        encoder: gif::Encoder<std::io::BufWriter<std::fs::File>>,
+    },
+}
+
+/// Writes the read back frames of a framebuffer to disk in the given format while the recording
+/// is running. The time each frame was shown on screen is preserved so that the recording plays
+/// back with the same pacing as the game.
+///
+/// NOTE: How long a frame was shown is only known when the next frame arrives. Therefore we only
+///       ever hold on to the pixels of the last frame and write it out one frame later.
+pub struct FrameRecorder {
+    framebuffer_target: FramebufferTarget,
+    format: RecordingFormat,
+    max_duration_seconds: f64,
+
+    width: u16,
+    height: u16,
+    start_timestamp: f64,
+    num_written_frames: usize,
+    pending_frame: Option<RecordedFrame>,
+    /// Created when the first frame arrives
+    writer: Option<FrameWriter>,
+}
+
+impl FrameRecorder {
+    pub fn new(
+        framebuffer_target: FramebufferTarget,
+        format: RecordingFormat,
+        max_duration_seconds: f32,
+    ) -> FrameRecorder {
+        info!(
+            "Started recording {:?} as {:?} for at most {} seconds",
+            framebuffer_target, format, max_duration_seconds
+        );
+        FrameRecorder {
+            framebuffer_target,
+            format,
+            max_duration_seconds: f64::from(max_duration_seconds),
+            width: 0,
+            height: 0,
+            start_timestamp: 0.0,
+            num_written_frames: 0,
+            pending_frame: None,
+            writer: None,
+        }
+    }
+
+    pub fn framebuffer_target(&self) -> FramebufferTarget {
+        self.framebuffer_target.clone()
+    }
+
+    /// Adds a read back frame that was captured at the given time since startup. Returns `false`
+    /// if the recording cannot take any more frames because its maximum duration was reached or
+    /// the framebuffer dimensions have changed. The given frame is dropped in the latter case.
+    pub fn add_frame(&mut self, readback: Readback, timestamp: f64) -> Result<bool, Error> {
+        let info = &readback.framebuffer_info;
+        if self.writer.is_none() {
+            self.width = info.width;
+            self.height = info.height;
+            self.start_timestamp = timestamp;
+            self.writer = Some(self.create_writer()?);
+        } else if self.width != info.width || self.height != info.height {
+            warn!(
+                "Stopping recording because the dimensions of {:?} changed from {}x{}",
+                info, self.width, self.height
+            );
+            return Ok(false);
+        }
+
+        let frame = RecordedFrame {
+            timestamp,
+            pixels: readback.pixels,
+        };
+        match self.pending_frame.take() {
+            // NOTE: Frames that would be shown shorter than `GIF_MIN_FRAME_DELAY` are dropped
+            //       and the previous frame is shown longer instead
+            Some(pending_frame)
+                if self.format == RecordingFormat::Gif
+                    && self.to_gif_delay_units(timestamp)
+                        < self.to_gif_delay_units(pending_frame.timestamp)
+                            + u64::from(GIF_MIN_FRAME_DELAY) =>
+            {
+                self.pending_frame = Some(pending_frame);
+            }
+            Some(pending_frame) => {
+                self.write_frame(&pending_frame, timestamp)?;
+                self.pending_frame = Some(frame);
+            }
+            None => self.pending_frame = Some(frame),
+        }
+
+        Ok(timestamp - self.start_timestamp < self.max_duration_seconds)
+    }
+
+    /// Writes the remaining frame and finalizes the recording. The given timestamp marks the
+    /// time since startup at which the last frame stopped being shown.
+    pub fn finish(mut self, end_timestamp: f64) -> Result<(), Error> {
+        let pending_frame = match self.pending_frame.take() {
+            Some(pending_frame) => pending_frame,
+            None => {
+                warn!("Recording of {:?} has no frames", self.framebuffer_target);
+                return Ok(());
+            }
+        };
+        self.write_frame(&pending_frame, end_timestamp)?;
+
+        let timer = crate::timer::Timer::new();
+        let path = match self.writer.take().expect("Recording has no frame writer") {
+            FrameWriter::PngSequence {
+                dirpath,
+                frame_durations,
+            } => {
+                let durations_filepath = format!("{}/frame_durations_ms.txt", dirpath);
+                game_lib::utility::write_string_to_file(&durations_filepath, &frame_durations);
+                dirpath
+            }
+            FrameWriter::Gif { filepath, encoder } => {
+                // NOTE: The gif trailer is written when the encoder goes out of scope
+                drop(encoder);
+                filepath
+            }
+        };
+        info!(
+            "Saved recording with {} frames and a duration of {:.2} seconds to '{}' in {:.2} seconds",
+            self.num_written_frames,
+            end_timestamp - self.start_timestamp,
+            path,
+            timer.elapsed_time()
+        );
+
+        Ok(())
+    }
+
+    fn create_writer(&self) -> Result<FrameWriter, Error> {
+        let name = match self.framebuffer_target {
+            FramebufferTarget::Screen => String::from("screen"),
+            FramebufferTarget::Offscreen(ref framebuffer_info) => {
+                framebuffer_info.name.to_lowercase()
+            }
+        };
+        let recording_name = format!("recording_{}_{}", name, create_timestamp_string()?);
+        std::fs::create_dir_all(RECORDING_DIR).context(format!(
+            "Could not create recording directory '{}'",
+            RECORDING_DIR
+        ))?;
+
+        match self.format {
+            RecordingFormat::PngSequence => {
+                let dirpath = format!("{}/{}", RECORDING_DIR, recording_name);
+                std::fs::create_dir_all(&dirpath)
+                    .context(format!("Could not create directory '{}'", dirpath))?;
+                Ok(FrameWriter::PngSequence {
+                    dirpath,
+                    frame_durations: String::new(),
+                })
+            }
+            RecordingFormat::Gif => {
+                let filepath = format!("{}/{}.gif", RECORDING_DIR, recording_name);
+                let file = std::fs::File::create(&filepath)
+                    .context(format!("Could not create file '{}'", filepath))?;
+                let mut encoder =
+                    gif::Encoder::new(std::io::BufWriter::new(file), self.width, self.height, &[])
+                        .context("Could not create gif encoder")?;
+                encoder
+                    .write_extension(gif::ExtensionData::Repetitions(gif::Repeat::Infinite))
+                    .context("Could not make gif looping")?;
+                Ok(FrameWriter::Gif { filepath, encoder })
+            }
+        }
+    }
+
+    // NOTE: We convert the start times of the frames to GIF delay units instead of their
+    //       durations. This way rounding errors do not accumulate over the recording.
+    fn to_gif_delay_units(&self, timestamp: f64) -> u64 {
+        ((timestamp - self.start_timestamp) * GIF_DELAY_UNITS_PER_SECOND).round() as u64
+    }
+
+    fn write_frame(&mut self, frame: &RecordedFrame, end_timestamp: f64) -> Result<(), Error> {
+        let frame_index = self.num_written_frames;
+        let (width, height) = (self.width, self.height);
+        let frame_start = self.to_gif_delay_units(frame.timestamp);
+        let frame_end = self.to_gif_delay_units(end_timestamp);
+
+        match self.writer.as_mut().expect("Recording has no frame writer") {
+            FrameWriter::PngSequence {
+                dirpath,
+                frame_durations,
+            } => {
+                let filename = format!("frame_{:05}.png", frame_index);
+                let filepath = format!("{}/{}", dirpath, filename);
+                game_lib::utility::save_pixels_to_png_file(
+                    &filepath,
+                    &frame.pixels,
+                    usize::from(width),
+                    usize::from(height),
+                )
+                .context(format!("Could not write frame to '{}'", filepath))?;
+                *frame_durations += &format!(
+                    "{} {:.3}\n",
+                    filename,
+                    1000.0 * (end_timestamp - frame.timestamp)
+                );
+            }
+            FrameWriter::Gif { encoder, .. } => {
+                let mut gif_frame = create_gif_frame(width, height, &frame.pixels);
+                gif_frame.delay = std::cmp::max(
+                    GIF_MIN_FRAME_DELAY,
+                    std::cmp::min(frame_end - frame_start, u64::from(u16::max_value())) as u16,
+                );
+                encoder
+                    .write_frame(&gif_frame)
+                    .context(format!("Could not encode gif frame {}", frame_index))?;
+            }
+        }
+        self.num_written_frames += 1;
+
+        Ok(())
+    }
+}
+
+/// Creates a gif frame with its own palette. As long as the pixels do not contain more than 256
+/// distinct colors (which is usually the case for pixel-art) the frame is lossless.
+fn create_gif_frame(width: u16, height: u16, pixels: &[Pixel]) -> gif::Frame<'static> {
+    let mut palette_indices: HashMap<(u8, u8, u8), u8> = HashMap::new();
+    let mut palette: Vec<u8> = Vec::new();
+    let mut indices: Vec<u8> = Vec::with_capacity(pixels.len());
+
+    for pixel in pixels {
+        let color = (pixel.r, pixel.g, pixel.b);
+        let index = match palette_indices.get(&color) {
+            Some(&index) => index,
+            None => {
+                if palette_indices.len() == 256 {
+                    warn!("Gif frame has more than 256 colors and will be quantized");
+                    let mut rgba: Vec<u8> = pixels
+                        .iter()
+                        .flat_map(|pixel| vec![pixel.r, pixel.g, pixel.b, 255])
+                        .collect();
+                    return gif::Frame::from_rgba(width, height, &mut rgba);
+                }
+                let index = palette_indices.len() as u8;
+                palette_indices.insert(color, index);
+                palette.extend_from_slice(&[color.0, color.1, color.2]);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    gif::Frame::from_palette_pixels(width, height, &indices, &palette, None)
+}
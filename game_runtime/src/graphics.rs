@@ -1,7 +1,8 @@
 use game_lib;
 use game_lib::{
-    Color, ComponentBytes, DrawCommand, FramebufferInfo, FramebufferTarget, Mat4, Mat4Helper, Mesh,
-    Pixel, Rect, TextureArrayInfo, Vertex, VertexIndex,
+    BlendMode, Color, ComponentBytes, DepthMode, DrawCommand, DrawTexture, FramebufferInfo,
+    FramebufferTarget, Mat4, Mat4Helper, Mesh, Pixel, PostProcessEffect, Rect, RenderState,
+    ShaderInfo, ShaderUniforms, TextureArrayInfo, Vertex, VertexIndex,
 };
 
 use crate::OptionHelper;
@@ -22,10 +23,18 @@ pub type DepthFormat = gfx::format::DepthStencil;
 
 type TextureSampler<R> = gfx::handle::Sampler<R>;
 type VertexBuffer<R> = gfx::handle::Buffer<R, VertexGFX>;
+type DownloadBuffer<R> = gfx::handle::Buffer<R, [u8; 4]>;
+/// Download buffers are kept after their pixels were collected so that continuous readbacks (as
+/// used by recordings) do not need to create a new buffer every frame
+const MAX_NUM_POOLED_DOWNLOAD_BUFFERS: usize = 4;
+
+type ColorTexture<R> = gfx::handle::Texture<R, <ColorFormat as gfx::format::Formatted>::Surface>;
 type RenderTargetColor<R> = gfx::handle::RenderTargetView<R, ColorFormat>;
 type RenderTargetDepth<R> = gfx::handle::DepthStencilView<R, DepthFormat>;
 type ShaderResourceView<R> = gfx::handle::ShaderResourceView<R, [f32; 4]>;
+type ShaderSet<R> = gfx::ShaderSet<R>;
 type PipelineStateObject<R> = gfx::PipelineState<R, pipe::Meta>;
+type PostProcessPipelineStateObject<R> = gfx::PipelineState<R, postprocess_pipe::Meta>;
 
 use gfx::state::{Blend, BlendValue, ColorMask, Equation, Factor};
 gfx_defines! {
@@ -42,6 +51,12 @@ gfx_defines! {
         transform: gfx::Global<[[f32; 4];4]> = "u_transform",
         use_texture_array: gfx::Global<i32> = "u_use_texture_array",
 
+        // NOTE: These are only used by custom shaders
+        params_0: gfx::Global<[f32; 4]> = "u_params_0",
+        params_1: gfx::Global<[f32; 4]> = "u_params_1",
+        params_2: gfx::Global<[f32; 4]> = "u_params_2",
+        params_3: gfx::Global<[f32; 4]> = "u_params_3",
+
         texture: gfx::TextureSampler<[f32; 4]> = "u_sampler",
         texture_array: gfx::TextureSampler<[f32; 4]> = "u_sampler_array",
 
@@ -55,6 +70,21 @@ gfx_defines! {
                                                    ),
         out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
     }
+
+    pipeline postprocess_pipe {
+        vertex_buffer: gfx::VertexBuffer<VertexGFX> = (),
+
+        transform: gfx::Global<[[f32; 4];4]> = "u_transform",
+        effect: gfx::Global<i32> = "u_effect",
+        params: gfx::Global<[f32; 4]> = "u_params",
+        source_dim: gfx::Global<[f32; 2]> = "u_source_dim",
+
+        source: gfx::TextureSampler<[f32; 4]> = "u_source",
+        secondary: gfx::TextureSampler<[f32; 4]> = "u_secondary",
+        lut: gfx::TextureSampler<[f32; 4]> = "u_lut",
+
+        out_color: gfx::RenderTarget<ColorFormat> = "out_color_0",
+    }
 }
 
 /// Converts a slice of [`Vertex`] into a slice of [`VertexGFX`] for gfx to consume.
@@ -77,12 +107,10 @@ where
     pub info: FramebufferInfo,
     pub texture_sampler: TextureSampler<R>,
 
-    pub pipeline_state_object_fill: PipelineStateObject<R>,
-    pub pipeline_state_object_line: PipelineStateObject<R>,
-
     pub color_render_target_view: RenderTargetColor<R>,
     pub depth_render_target_view: RenderTargetDepth<R>,
     pub shader_resource_view: Option<ShaderResourceView<R>>,
+    pub color_texture: Option<ColorTexture<R>>,
 }
 
 impl<R> Framebuffer<R>
@@ -96,6 +124,7 @@ where
         color_render_target_view: RenderTargetColor<R>,
         depth_render_target_view: RenderTargetDepth<R>,
         shader_resource_view: Option<ShaderResourceView<R>>,
+        color_texture: Option<ColorTexture<R>>,
     ) -> Result<Framebuffer<R>, Error>
     where
         F: gfx::Factory<R>,
@@ -115,65 +144,14 @@ where
         let sampler_info = SamplerInfo::new(FilterMethod::Scale, WrapMode::Tile);
         let texture_sampler = factory.create_sampler(sampler_info);
 
-        //
-        trace!("Creating shader set for framebuffer");
-        //
-        let vertex_shader = include_bytes!("shaders/basic.glslv").to_vec();
-        let fragment_shader = include_bytes!("shaders/basic.glslf").to_vec();
-        let shader_set = factory
-            .create_shader_set(&vertex_shader, &fragment_shader)
-            .context("Could not create shader set for framebuffer")?;
-
-        //
-        trace!("Creating rasterizers for line drawing and filling");
-        //
-        use gfx::state::{CullFace, FrontFace, RasterMethod, Rasterizer};
-        let line_rasterizer = Rasterizer {
-            front_face: FrontFace::CounterClockwise,
-            cull_face: CullFace::Nothing,
-            method: RasterMethod::Line(1),
-            offset: None,
-            samples: None,
-        };
-        let fill_rasterizer = Rasterizer {
-            front_face: FrontFace::CounterClockwise,
-            cull_face: CullFace::Nothing,
-            method: RasterMethod::Fill,
-            offset: None,
-            samples: None,
-        };
-
-        //
-        trace!("Creating framebuffer pipeline state objects");
-        //
-        let pipeline_state_object_line = factory
-            .create_pipeline_state(
-                &shader_set,
-                gfx::Primitive::LineList,
-                line_rasterizer,
-                pipe::new(),
-            )
-            .context("Failed to create pipeline state object for line drawing")?;
-
-        let pipeline_state_object_fill = factory
-            .create_pipeline_state(
-                &shader_set,
-                gfx::Primitive::TriangleList,
-                fill_rasterizer,
-                pipe::new(),
-            )
-            .context("Failed to create pipeline state object for filled drawing")?;
-
         Ok(Framebuffer {
             info,
             texture_sampler,
 
-            pipeline_state_object_fill,
-            pipeline_state_object_line,
-
             shader_resource_view,
             color_render_target_view,
             depth_render_target_view,
+            color_texture,
         })
     }
 
@@ -184,8 +162,28 @@ where
         //
         info!("Creating offscreen render targets");
         //
-        let (_, shader_resource_view, color_render_target_view) = factory
-            .create_render_target::<ColorFormat>(info.width, info.height)
+        // NOTE: We do not use `factory.create_render_target` here because we need the color
+        //       texture to be a valid source for copying its pixels back to the cpu
+        use gfx::format::{ChannelTyped, Formatted};
+        use gfx::memory::{Bind, Usage};
+        let color_texture = factory
+            .create_texture::<<ColorFormat as Formatted>::Surface>(
+                gfx::texture::Kind::D2(info.width, info.height, gfx::texture::AaMode::Single),
+                1,
+                Bind::SHADER_RESOURCE | Bind::RENDER_TARGET | Bind::TRANSFER_SRC,
+                Usage::Data,
+                Some(<<ColorFormat as Formatted>::Channel as ChannelTyped>::get_channel_type()),
+            )
+            .context("Failed to create a framebuffer color texture")?;
+        let shader_resource_view = factory
+            .view_texture_as_shader_resource::<ColorFormat>(
+                &color_texture,
+                (0, 0),
+                gfx::format::Swizzle::new(),
+            )
+            .context("Failed to create a framebuffer shader resource view")?;
+        let color_render_target_view = factory
+            .view_texture_as_render_target(&color_texture, 0, None)
             .context("Failed to create a framebuffer color render target")?;
         let depth_render_target_view = factory
             .create_depth_stencil_view_only::<DepthFormat>(info.width, info.height)
@@ -198,6 +196,7 @@ where
             color_render_target_view,
             depth_render_target_view,
             Some(shader_resource_view),
+            Some(color_texture),
         )
     }
 
@@ -206,6 +205,7 @@ where
         transform: &Mat4,
         texture: ShaderResourceView<R>,
         texture_mode: TextureMode,
+        uniforms: &ShaderUniforms,
         vertex_buffer: VertexBuffer<R>,
     ) -> pipe::Data<R>
     where
@@ -220,6 +220,11 @@ where
                 TextureMode::ArrayTexture => 1,
             },
 
+            params_0: uniforms.params[0],
+            params_1: uniforms.params[1],
+            params_2: uniforms.params[2],
+            params_3: uniforms.params[3],
+
             texture: (texture.clone(), self.texture_sampler.clone()),
             texture_array: (texture, self.texture_sampler.clone()),
 
@@ -229,6 +234,186 @@ where
     }
 }
 
+//==================================================================================================
+// Pipeline states
+//==================================================================================================
+//
+
+/// Identifies a pipeline state object. These are created on demand for every combination of
+/// shader and render settings that is drawn with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineStateKey {
+    shader: Option<ShaderInfo>,
+    blend_mode: BlendMode,
+    depth_mode: DepthMode,
+    draw_mode: DrawMode,
+}
+
+// NOTE: All our colors are premultiplied by their alpha
+fn blend_state(blend_mode: BlendMode) -> Blend {
+    use gfx::state::BlendChannel;
+    let alpha_channel = BlendChannel {
+        equation: Equation::Add,
+        source: Factor::One,
+        destination: Factor::OneMinus(BlendValue::SourceAlpha),
+    };
+    match blend_mode {
+        BlendMode::Alpha => Blend::new(
+            Equation::Add,
+            Factor::One,
+            Factor::OneMinus(BlendValue::SourceAlpha),
+        ),
+        BlendMode::Additive => Blend {
+            color: BlendChannel {
+                equation: Equation::Add,
+                source: Factor::One,
+                destination: Factor::One,
+            },
+            alpha: BlendChannel {
+                equation: Equation::Add,
+                source: Factor::Zero,
+                destination: Factor::One,
+            },
+        },
+        BlendMode::Multiply => Blend {
+            color: BlendChannel {
+                equation: Equation::Add,
+                source: Factor::ZeroPlus(BlendValue::DestColor),
+                destination: Factor::OneMinus(BlendValue::SourceAlpha),
+            },
+            alpha: alpha_channel,
+        },
+    }
+}
+
+fn depth_state(depth_mode: DepthMode) -> gfx::state::Depth {
+    match depth_mode {
+        DepthMode::TestAndWrite => gfx::preset::depth::LESS_EQUAL_WRITE,
+        DepthMode::Test => gfx::preset::depth::LESS_EQUAL_TEST,
+        DepthMode::Disabled => gfx::preset::depth::PASS_TEST,
+    }
+}
+
+fn rasterizer(draw_mode: DrawMode) -> gfx::state::Rasterizer {
+    use gfx::state::{CullFace, FrontFace, RasterMethod, Rasterizer};
+    Rasterizer {
+        front_face: FrontFace::CounterClockwise,
+        cull_face: CullFace::Nothing,
+        method: match draw_mode {
+            DrawMode::Lines => RasterMethod::Line(1),
+            DrawMode::Fill => RasterMethod::Fill,
+        },
+        offset: None,
+        samples: None,
+    }
+}
+
+//==================================================================================================
+// PostProcessor
+//==================================================================================================
+//
+
+// NOTE: These need to match the effect ids in `shaders/postprocess.glslf`
+const POSTPROCESS_EFFECT_BRIGHT_PASS: i32 = 0;
+const POSTPROCESS_EFFECT_BLUR: i32 = 1;
+const POSTPROCESS_EFFECT_BLOOM_COMPOSITE: i32 = 2;
+const POSTPROCESS_EFFECT_SCANLINES: i32 = 3;
+const POSTPROCESS_EFFECT_VIGNETTE: i32 = 4;
+const POSTPROCESS_EFFECT_COLOR_GRADING: i32 = 5;
+
+/// Returns the effect id and parameters that the post-processing shader expects for an effect
+fn postprocess_effect_uniforms(effect: &PostProcessEffect) -> (i32, [f32; 4]) {
+    match *effect {
+        PostProcessEffect::BrightPass { threshold } => {
+            (POSTPROCESS_EFFECT_BRIGHT_PASS, [threshold, 0.0, 0.0, 0.0])
+        }
+        PostProcessEffect::Blur { direction, radius } => (
+            POSTPROCESS_EFFECT_BLUR,
+            [direction.x, direction.y, radius, 0.0],
+        ),
+        PostProcessEffect::BloomComposite { intensity } => (
+            POSTPROCESS_EFFECT_BLOOM_COMPOSITE,
+            [intensity, 0.0, 0.0, 0.0],
+        ),
+        PostProcessEffect::Scanlines {
+            intensity,
+            line_thickness,
+        } => (
+            POSTPROCESS_EFFECT_SCANLINES,
+            [intensity, line_thickness, 0.0, 0.0],
+        ),
+        PostProcessEffect::Vignette {
+            intensity,
+            radius,
+            softness,
+        } => (
+            POSTPROCESS_EFFECT_VIGNETTE,
+            [intensity, radius, softness, 0.0],
+        ),
+        PostProcessEffect::ColorGrading { intensity } => {
+            (POSTPROCESS_EFFECT_COLOR_GRADING, [intensity, 0.0, 0.0, 0.0])
+        }
+    }
+}
+
+/// Holds the pipeline state that is shared by all post-processing passes
+struct PostProcessor<R>
+where
+    R: gfx::Resources,
+{
+    pipeline_state_object: PostProcessPipelineStateObject<R>,
+    nearest_sampler: TextureSampler<R>,
+    // NOTE: The color grading LUT only stores a few color levels and needs to be interpolated
+    linear_sampler: TextureSampler<R>,
+}
+
+impl<R> PostProcessor<R>
+where
+    R: gfx::Resources,
+{
+    fn new<F>(factory: &mut F) -> Result<PostProcessor<R>, Error>
+    where
+        F: gfx::Factory<R>,
+    {
+        info!("Creating post-processor");
+
+        use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+        let nearest_sampler =
+            factory.create_sampler(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp));
+        let linear_sampler =
+            factory.create_sampler(SamplerInfo::new(FilterMethod::Bilinear, WrapMode::Clamp));
+
+        let vertex_shader = include_bytes!("shaders/basic.glslv").to_vec();
+        let fragment_shader = include_bytes!("shaders/postprocess.glslf").to_vec();
+        let shader_set = factory
+            .create_shader_set(&vertex_shader, &fragment_shader)
+            .context("Could not create shader set for post-processing")?;
+
+        use gfx::state::{CullFace, FrontFace, RasterMethod, Rasterizer};
+        let fill_rasterizer = Rasterizer {
+            front_face: FrontFace::CounterClockwise,
+            cull_face: CullFace::Nothing,
+            method: RasterMethod::Fill,
+            offset: None,
+            samples: None,
+        };
+        let pipeline_state_object = factory
+            .create_pipeline_state(
+                &shader_set,
+                gfx::Primitive::TriangleList,
+                fill_rasterizer,
+                postprocess_pipe::new(),
+            )
+            .context("Failed to create pipeline state object for post-processing")?;
+
+        Ok(PostProcessor {
+            pipeline_state_object,
+            nearest_sampler,
+            linear_sampler,
+        })
+    }
+}
+
 //==================================================================================================
 // RenderingContext
 //==================================================================================================
@@ -240,12 +425,37 @@ enum TextureMode {
     Regular,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum DrawMode {
     Lines,
     Fill,
 }
 
+/// Tells the caller of [`RenderingContext::collect_readbacks`] what a readback was requested for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReadbackPurpose {
+    Screenshot,
+    Recording,
+}
+
+/// The pixels of a framebuffer in row-major order starting at the top left
+pub struct Readback {
+    pub framebuffer_info: FramebufferInfo,
+    pub purpose: ReadbackPurpose,
+    pub pixels: Vec<Pixel>,
+}
+
+/// A readback whose pixels were already scheduled to be copied into a download buffer and
+/// that can be collected as soon as the encoder was flushed
+struct PendingReadback<R>
+where
+    R: gfx::Resources,
+{
+    framebuffer_info: FramebufferInfo,
+    purpose: ReadbackPurpose,
+    download_buffer: DownloadBuffer<R>,
+}
+
 pub struct RenderingContext<C, R, F>
 where
     R: gfx::Resources,
@@ -259,9 +469,22 @@ where
     pub encoder: gfx::Encoder<R, C>,
     pub screen_framebuffer: Framebuffer<R>,
 
+    postprocessor: PostProcessor<R>,
+    default_shader_set: ShaderSet<R>,
+    shader_sets: HashMap<ShaderInfo, ShaderSet<R>>,
+    pipeline_state_objects: HashMap<PipelineStateKey, PipelineStateObject<R>>,
     framebuffers: HashMap<FramebufferInfo, Framebuffer<R>>,
     textures: HashMap<TextureArrayInfo, ShaderResourceView<R>>,
     textures_pixeldata: HashMap<TextureArrayInfo, Vec<Vec<Pixel>>>,
+
+    // NOTE: The screen framebuffer has no texture we could read back from. So when we want to
+    //       read back the screen we redirect all screen drawing for the current frame into
+    //       `screen_capture_framebuffer` and blit it to the actual screen afterwards.
+    readback_requests: Vec<(FramebufferTarget, ReadbackPurpose)>,
+    screen_capture_framebuffer: Option<Framebuffer<R>>,
+    screen_capture_is_active: bool,
+    pending_readbacks: Vec<PendingReadback<R>>,
+    download_buffer_pool: Vec<DownloadBuffer<R>>,
 }
 
 impl<C, R, F> RenderingContext<C, R, F>
@@ -287,19 +510,40 @@ where
             screen_color_render_target_view,
             screen_depth_render_target_view,
             None,
+            None,
         )
         .context(format!(
             "Could not create framebuffer {:?}",
             framebuffer_name
         ))?;
+        let postprocessor =
+            PostProcessor::new(&mut factory).context("Could not create post-processor")?;
+
+        //
+        trace!("Creating default shader set");
+        //
+        let vertex_shader = include_bytes!("shaders/basic.glslv").to_vec();
+        let fragment_shader = include_bytes!("shaders/basic.glslf").to_vec();
+        let default_shader_set = factory
+            .create_shader_set(&vertex_shader, &fragment_shader)
+            .context("Could not create default shader set")?;
 
         Ok(RenderingContext {
             factory,
             encoder,
             screen_framebuffer,
+            postprocessor,
+            default_shader_set,
+            shader_sets: HashMap::new(),
+            pipeline_state_objects: HashMap::new(),
             framebuffers: HashMap::new(),
             textures: HashMap::new(),
             textures_pixeldata: HashMap::new(),
+            readback_requests: Vec::new(),
+            screen_capture_framebuffer: None,
+            screen_capture_is_active: false,
+            pending_readbacks: Vec::new(),
+            download_buffer_pool: Vec::new(),
         })
     }
 
@@ -322,34 +566,40 @@ where
                 DrawCommand::DrawLines {
                     transform,
                     mesh,
-                    texture_array_info,
+                    texture,
                     framebuffer,
+                    render_state,
                 } => {
                     let (vertices, indices) = mesh.to_vertices_indices();
+                    let (texture, texture_mode) = self.get_draw_texture(texture)?;
                     self.draw(
                         transform,
-                        self.get_texture_array(&texture_array_info)?.clone(),
-                        TextureMode::ArrayTexture,
+                        texture,
+                        texture_mode,
                         vertices,
                         indices,
                         framebuffer,
+                        render_state,
                         DrawMode::Lines,
                     )
                 }
                 DrawCommand::DrawPolys {
                     transform,
                     mesh,
-                    texture_array_info,
+                    texture,
                     framebuffer,
+                    render_state,
                 } => {
                     let (vertices, indices) = mesh.to_vertices_indices();
+                    let (texture, texture_mode) = self.get_draw_texture(texture)?;
                     self.draw(
                         transform,
-                        self.get_texture_array(&texture_array_info)?.clone(),
-                        TextureMode::ArrayTexture,
+                        texture,
+                        texture_mode,
                         vertices,
                         indices,
                         framebuffer,
+                        render_state,
                         DrawMode::Fill,
                     )
                 }
@@ -375,6 +625,21 @@ where
                     *source_rect,
                     *target_rect,
                 ),
+                DrawCommand::PostProcess {
+                    source_framebuffer,
+                    secondary_framebuffer,
+                    color_grading_lut,
+                    target_framebuffer,
+                    target_rect,
+                    effect,
+                } => self.postprocess(
+                    source_framebuffer,
+                    secondary_framebuffer.as_ref(),
+                    color_grading_lut.as_ref(),
+                    target_framebuffer,
+                    *target_rect,
+                    effect,
+                ),
                 DrawCommand::CreateFramebuffer { framebuffer_info } => {
                     self.create_framebuffer(framebuffer_info)
                 }
@@ -392,10 +657,24 @@ where
                 DrawCommand::DeleteTextureArray { texture_array_info } => {
                     self.delete_texture_array(&texture_array_info)
                 }
+                DrawCommand::CreateShader {
+                    shader_info,
+                    vertex_shader_source,
+                    fragment_shader_source,
+                } => self.create_shader(
+                    shader_info,
+                    vertex_shader_source.as_ref(),
+                    fragment_shader_source,
+                ),
+                DrawCommand::DeleteShader { shader_info } => self.delete_shader(shader_info),
             };
             processing_result
                 .context(format!("Could not execute draw command {:?}", draw_command))?;
         }
+
+        self.schedule_readbacks()
+            .context("Could not schedule framebuffer readbacks")?;
+
         Ok(())
     }
 
@@ -410,6 +689,7 @@ where
         vertices: &[Vertex],
         indices: &[VertexIndex],
         framebuffer_target: &FramebufferTarget,
+        render_state: &RenderState,
         draw_mode: DrawMode,
     ) -> Result<(), Error>
     where
@@ -422,21 +702,17 @@ where
             .factory
             .create_vertex_buffer_with_slice(convert_to_gfx_format(&vertices), &*indices);
         let framebuffer = self.get_framebuffer(framebuffer_target)?;
-        let pipeline_data =
-            framebuffer.create_pipeline_data(transform, texture, texture_mode, vertex_buffer);
-
-        match draw_mode {
-            DrawMode::Lines => self.encoder.draw(
-                &slice,
-                &framebuffer.pipeline_state_object_line,
-                &pipeline_data,
-            ),
-            DrawMode::Fill => self.encoder.draw(
-                &slice,
-                &framebuffer.pipeline_state_object_fill,
-                &pipeline_data,
-            ),
-        }
+        let pipeline_state_object = self.get_pipeline_state_object(render_state, draw_mode)?;
+        let pipeline_data = framebuffer.create_pipeline_data(
+            transform,
+            texture,
+            texture_mode,
+            &render_state.uniforms,
+            vertex_buffer,
+        );
+
+        self.encoder
+            .draw(&slice, &pipeline_state_object, &pipeline_data);
         Ok(())
     }
 
@@ -485,6 +761,22 @@ where
         target_rect: Rect,
     ) -> Result<(), Error> {
         let source_framebuffer = self.get_framebuffer_by_info(source_framebuffer_info)?;
+        self.blit_framebuffer_internal(
+            source_framebuffer,
+            target_framebuffer,
+            source_rect,
+            target_rect,
+        )
+    }
+
+    fn blit_framebuffer_internal(
+        &mut self,
+        source_framebuffer: Framebuffer<R>,
+        target_framebuffer: &FramebufferTarget,
+        source_rect: Rect,
+        target_rect: Rect,
+    ) -> Result<(), Error> {
+        let source_framebuffer_info = &source_framebuffer.info;
         let target_framebuffer_info = self.get_framebuffer(target_framebuffer)?.info;
 
         trace!(
@@ -514,12 +806,15 @@ where
             1.0,
         );
 
-        let texture = source_framebuffer.shader_resource_view.ok_or_else(|| {
-            failure::err_msg(format!(
+        let texture = source_framebuffer
+            .shader_resource_view
+            .clone()
+            .ok_or_else(|| {
+                failure::err_msg(format!(
             "Could not blit framebuffer because source {:?} does not have a shader resouce view",
             source_framebuffer_info
         ))
-        })?;
+            })?;
 
         self.draw(
             &projection_mat,
@@ -528,12 +823,217 @@ where
             &vertices,
             &indices,
             target_framebuffer,
+            &RenderState::default(),
             DrawMode::Fill,
         )?;
 
         Ok(())
     }
 
+    // ---------------------------------------------------------------------------------------------
+    // Shaders
+    //
+    fn create_shader(
+        &mut self,
+        shader_info: &ShaderInfo,
+        vertex_shader_source: Option<&String>,
+        fragment_shader_source: &str,
+    ) -> Result<(), Error> {
+        debug!("Creating shader for {:?}", shader_info);
+
+        let default_vertex_shader = include_bytes!("shaders/basic.glslv");
+        let vertex_shader = match vertex_shader_source {
+            Some(source) => source.as_bytes(),
+            None => &default_vertex_shader[..],
+        };
+        // NOTE: A custom shader that does not compile must not end the game so we draw with the
+        //       default shader in its place
+        let shader_set = match self
+            .factory
+            .create_shader_set(vertex_shader, fragment_shader_source.as_bytes())
+        {
+            Ok(shader_set) => shader_set,
+            Err(error) => {
+                error!(
+                    "Could not create shader set for {:?} - using the default shader instead: {}",
+                    shader_info, error
+                );
+                self.default_shader_set.clone()
+            }
+        };
+
+        self.shader_sets
+            .insert(shader_info.clone(), shader_set)
+            .none_or(failure::err_msg(format!(
+                "Could not create shader because it already exists for {:?}",
+                shader_info
+            )))?;
+
+        Ok(())
+    }
+
+    fn delete_shader(&mut self, shader_info: &ShaderInfo) -> Result<(), Error> {
+        debug!("Deleting shader for {:?}", shader_info);
+
+        self.shader_sets.remove(shader_info).ok_or_else(|| {
+            failure::err_msg(format!(
+                "Could not delete shader because it did not exist for {:?}",
+                shader_info
+            ))
+        })?;
+        self.pipeline_state_objects
+            .retain(|key, _| key.shader.as_ref() != Some(shader_info));
+
+        Ok(())
+    }
+
+    fn get_pipeline_state_object(
+        &mut self,
+        render_state: &RenderState,
+        draw_mode: DrawMode,
+    ) -> Result<PipelineStateObject<R>, Error> {
+        let key = PipelineStateKey {
+            shader: render_state.shader.clone(),
+            blend_mode: render_state.blend_mode,
+            depth_mode: render_state.depth_mode,
+            draw_mode,
+        };
+        if let Some(pipeline_state_object) = self.pipeline_state_objects.get(&key) {
+            return Ok(pipeline_state_object.clone());
+        }
+
+        debug!("Creating pipeline state object for {:?}", key);
+        let shader_set = match &key.shader {
+            Some(shader_info) => self.shader_sets.get(shader_info).ok_or_else(|| {
+                failure::err_msg(format!("Could not find shader for {:?}", shader_info))
+            })?,
+            None => &self.default_shader_set,
+        };
+        let primitive = match draw_mode {
+            DrawMode::Lines => gfx::Primitive::LineList,
+            DrawMode::Fill => gfx::Primitive::TriangleList,
+        };
+        let pipeline_init = pipe::Init {
+            out_color: (
+                "out_color_0",
+                ColorMask::all(),
+                blend_state(render_state.blend_mode),
+            ),
+            out_depth: depth_state(render_state.depth_mode),
+            ..pipe::new()
+        };
+        let pipeline_state_object = self
+            .factory
+            .create_pipeline_state(shader_set, primitive, rasterizer(draw_mode), pipeline_init)
+            .context(format!(
+                "Failed to create pipeline state object for {:?}",
+                key
+            ))?;
+
+        self.pipeline_state_objects
+            .insert(key, pipeline_state_object.clone());
+        Ok(pipeline_state_object)
+    }
+
+    // ---------------------------------------------------------------------------------------------
+    // Post-processing
+    //
+    fn postprocess(
+        &mut self,
+        source_framebuffer_info: &FramebufferInfo,
+        secondary_framebuffer_info: Option<&FramebufferInfo>,
+        color_grading_lut_info: Option<&TextureArrayInfo>,
+        target_framebuffer: &FramebufferTarget,
+        target_rect: Rect,
+        effect: &PostProcessEffect,
+    ) -> Result<(), Error> {
+        let source_texture = self.get_framebuffer_texture(source_framebuffer_info)?;
+        // NOTE: Samplers that an effect does not use still need a texture bound to them
+        let secondary_texture = match secondary_framebuffer_info {
+            Some(framebuffer_info) => self.get_framebuffer_texture(framebuffer_info)?,
+            None => source_texture.clone(),
+        };
+        let lut_texture = match color_grading_lut_info {
+            Some(texture_array_info) => self.get_texture_array(texture_array_info)?.clone(),
+            None => source_texture.clone(),
+        };
+        let target_framebuffer = self.get_framebuffer(target_framebuffer)?;
+
+        let vertices = game_lib::vertices_from_rects(
+            target_rect,
+            Rect::unit_rect(),
+            0,
+            0.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+            0.0,
+        );
+        let indices: [VertexIndex; 6] = [0, 1, 2, 2, 3, 0];
+        let (vertex_buffer, slice) = self
+            .factory
+            .create_vertex_buffer_with_slice(convert_to_gfx_format(&vertices), &indices[..]);
+
+        // NOTE: The projection matrix is flipped 'upside-down' the same way as for blitting
+        let projection_mat = Mat4::ortho_origin_bottom_left(
+            f32::from(target_framebuffer.info.width),
+            f32::from(target_framebuffer.info.height),
+            -1.0,
+            1.0,
+        );
+        let (effect_id, params) = postprocess_effect_uniforms(effect);
+        let postprocessor = &self.postprocessor;
+        let pipeline_data = postprocess_pipe::Data {
+            vertex_buffer,
+
+            transform: projection_mat.into(),
+            effect: effect_id,
+            params,
+            source_dim: [
+                f32::from(source_framebuffer_info.width),
+                f32::from(source_framebuffer_info.height),
+            ],
+
+            source: (source_texture, postprocessor.nearest_sampler.clone()),
+            secondary: (secondary_texture, postprocessor.nearest_sampler.clone()),
+            lut: (lut_texture, postprocessor.linear_sampler.clone()),
+
+            out_color: target_framebuffer.color_render_target_view.clone(),
+        };
+        self.encoder
+            .draw(&slice, &postprocessor.pipeline_state_object, &pipeline_data);
+
+        Ok(())
+    }
+
+    fn get_framebuffer_texture(
+        &self,
+        framebuffer_info: &FramebufferInfo,
+    ) -> Result<ShaderResourceView<R>, Error> {
+        self.get_framebuffer_by_info(framebuffer_info)?
+            .shader_resource_view
+            .ok_or_else(|| {
+                failure::err_msg(format!(
+                    "Framebuffer {:?} does not have a shader resource view",
+                    framebuffer_info
+                ))
+            })
+    }
+
+    fn get_draw_texture(
+        &self,
+        texture: &DrawTexture,
+    ) -> Result<(ShaderResourceView<R>, TextureMode), Error> {
+        match texture {
+            DrawTexture::TextureArray(texture_array_info) => Ok((
+                self.get_texture_array(texture_array_info)?.clone(),
+                TextureMode::ArrayTexture,
+            )),
+            DrawTexture::Framebuffer(framebuffer_info) => Ok((
+                self.get_framebuffer_texture(framebuffer_info)?,
+                TextureMode::Regular,
+            )),
+        }
+    }
+
     // ---------------------------------------------------------------------------------------------
     // Framebuffers
     //
@@ -569,7 +1069,14 @@ where
 
     fn get_framebuffer(&self, framebuffer: &FramebufferTarget) -> Result<Framebuffer<R>, Error> {
         match framebuffer {
-            FramebufferTarget::Screen => Ok(self.screen_framebuffer.clone()),
+            FramebufferTarget::Screen => {
+                if self.screen_capture_is_active {
+                    if let Some(ref capture_framebuffer) = self.screen_capture_framebuffer {
+                        return Ok(capture_framebuffer.clone());
+                    }
+                }
+                Ok(self.screen_framebuffer.clone())
+            }
             FramebufferTarget::Offscreen(framebuffer_info) => {
                 self.get_framebuffer_by_info(framebuffer_info)
             }
@@ -592,6 +1099,198 @@ where
             .clone())
     }
 
+    // ---------------------------------------------------------------------------------------------
+    // Pixel readback
+    //
+
+    /// Requests the content of the given framebuffer to be read back after the next call to
+    /// [`process_draw_commands`]. The pixels can be collected via [`collect_readbacks`] after the
+    /// encoder was flushed.
+    ///
+    /// [`process_draw_commands`]: #method.process_draw_commands
+    /// [`collect_readbacks`]: #method.collect_readbacks
+    pub fn request_readback(
+        &mut self,
+        framebuffer_target: FramebufferTarget,
+        purpose: ReadbackPurpose,
+    ) -> Result<(), Error> {
+        trace!(
+            "Requesting readback of {:?} for {:?}",
+            framebuffer_target,
+            purpose
+        );
+
+        if let FramebufferTarget::Screen = framebuffer_target {
+            let screen_info = &self.screen_framebuffer.info;
+            let capture_framebuffer_is_outdated = match self.screen_capture_framebuffer {
+                Some(ref capture_framebuffer) => {
+                    capture_framebuffer.info.width != screen_info.width
+                        || capture_framebuffer.info.height != screen_info.height
+                }
+                None => true,
+            };
+            if capture_framebuffer_is_outdated {
+                let capture_info = FramebufferInfo {
+                    id: u32::max_value() - 1,
+                    width: screen_info.width,
+                    height: screen_info.height,
+                    name: String::from("ScreenCapture"),
+                };
+                let capture_framebuffer = Framebuffer::new(&mut self.factory, &capture_info)
+                    .context("Could not create framebuffer for capturing the screen")?;
+                self.screen_capture_framebuffer = Some(capture_framebuffer);
+            }
+            self.screen_capture_is_active = true;
+        }
+        self.readback_requests.push((framebuffer_target, purpose));
+
+        Ok(())
+    }
+
+    fn schedule_readbacks(&mut self) -> Result<(), Error> {
+        if self.screen_capture_is_active {
+            let capture_framebuffer = self
+                .screen_capture_framebuffer
+                .clone()
+                .ok_or_else(|| failure::err_msg("Screen capture framebuffer does not exist"))?;
+
+            // NOTE: We need to deactivate the screen capture first so that we actually blit to
+            //       the real screen here
+            self.screen_capture_is_active = false;
+            let screen_rect = Rect::from_width_height(
+                f32::from(capture_framebuffer.info.width),
+                f32::from(capture_framebuffer.info.height),
+            );
+            self.blit_framebuffer_internal(
+                capture_framebuffer,
+                &FramebufferTarget::Screen,
+                screen_rect,
+                screen_rect,
+            )?;
+        }
+
+        let readback_requests = std::mem::replace(&mut self.readback_requests, Vec::new());
+        for (framebuffer_target, purpose) in readback_requests {
+            let framebuffer = match framebuffer_target {
+                FramebufferTarget::Screen => self
+                    .screen_capture_framebuffer
+                    .clone()
+                    .ok_or_else(|| failure::err_msg("Screen capture framebuffer does not exist"))?,
+                FramebufferTarget::Offscreen(framebuffer_info) => {
+                    // NOTE: The framebuffer may have been deleted by the draw commands of this
+                    //       frame in which case we just skip the readback
+                    match self.get_framebuffer_by_info(&framebuffer_info) {
+                        Ok(framebuffer) => framebuffer,
+                        Err(error) => {
+                            warn!("Skipping readback for {:?}: {}", purpose, error);
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.schedule_readback(framebuffer, purpose)?;
+        }
+
+        Ok(())
+    }
+
+    fn schedule_readback(
+        &mut self,
+        framebuffer: Framebuffer<R>,
+        purpose: ReadbackPurpose,
+    ) -> Result<(), Error> {
+        let color_texture = framebuffer.color_texture.clone().ok_or_else(|| {
+            failure::err_msg(format!(
+                "Could not read back pixels because {:?} does not have a color texture",
+                framebuffer.info
+            ))
+        })?;
+
+        let num_pixels = usize::from(framebuffer.info.width) * usize::from(framebuffer.info.height);
+        let download_buffer = match self
+            .download_buffer_pool
+            .iter()
+            .position(|buffer| buffer.len() == num_pixels)
+        {
+            Some(pool_index) => self.download_buffer_pool.swap_remove(pool_index),
+            None => self
+                .factory
+                .create_download_buffer::<[u8; 4]>(num_pixels)
+                .context("Could not create download buffer for readback")?,
+        };
+
+        use gfx::format::{ChannelTyped, Formatted};
+        use gfx::memory::Typed;
+        let image_info = color_texture.get_info().to_raw_image_info(
+            <<ColorFormat as Formatted>::Channel as ChannelTyped>::get_channel_type(),
+            0,
+        );
+        self.encoder
+            .copy_texture_to_buffer_raw(
+                color_texture.raw(),
+                None,
+                image_info,
+                download_buffer.raw(),
+                0,
+            )
+            .map_err(|error| {
+                failure::err_msg(format!(
+                    "Could not copy pixels of {:?} into download buffer: {:?}",
+                    framebuffer.info, error
+                ))
+            })?;
+
+        self.pending_readbacks.push(PendingReadback {
+            framebuffer_info: framebuffer.info,
+            purpose,
+            download_buffer,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the pixels of all readbacks that were scheduled in this frame. Must be called after
+    /// the encoder was flushed, otherwise the read back pixels are not yet available.
+    pub fn collect_readbacks(&mut self) -> Result<Vec<Readback>, Error> {
+        let pending_readbacks = std::mem::replace(&mut self.pending_readbacks, Vec::new());
+        let mut readbacks = Vec::with_capacity(pending_readbacks.len());
+        for pending_readback in pending_readbacks {
+            let width = usize::from(pending_readback.framebuffer_info.width);
+            let reader = self
+                .factory
+                .read_mapping(&pending_readback.download_buffer)
+                .map_err(|error| {
+                    failure::err_msg(format!(
+                        "Could not map download buffer of {:?}: {:?}",
+                        pending_readback.framebuffer_info, error
+                    ))
+                })?;
+
+            // NOTE: The rows of our framebuffers are stored bottom to top so we need to flip them
+            let pixels: Vec<Pixel> = reader
+                .chunks(width)
+                .rev()
+                .flat_map(|row| row.iter())
+                .map(|color| Pixel::new(color[0], color[1], color[2], color[3]))
+                .collect();
+            drop(reader);
+
+            if self.download_buffer_pool.len() == MAX_NUM_POOLED_DOWNLOAD_BUFFERS {
+                self.download_buffer_pool.remove(0);
+            }
+            self.download_buffer_pool
+                .push(pending_readback.download_buffer);
+
+            readbacks.push(Readback {
+                framebuffer_info: pending_readback.framebuffer_info,
+                purpose: pending_readback.purpose,
+                pixels,
+            });
+        }
+
+        Ok(readbacks)
+    }
+
     // ---------------------------------------------------------------------------------------------
     // Textures
     //
@@ -16,16 +16,16 @@ TODO(JaSc):
       in-menu/in-game
   - Gamestate + logic + timing
   - Audio playback
-  - Some nice glowing shader effects
+  x Some nice glowing shader effects
   - BG music with PHAT BEATSIES
 
 TODO(JaSc): (Bigger things for vacations)
   x Throw out generalized coordinate system and replace by simple pixel-based coordinate system
   x Make framebuffer handling client side. For this we need to create some new draw commands and
     restructure the platform layer a little
-  - Make it possible for debug overlays like intersections to draw to world-space as well as
+  x Make it possible for debug overlays like intersections to draw to world-space as well as
     canvas-space to make i.e. arrow-heads uniformly sized regardless of arrow-size/zoom-level
-  - Allow do draw lines with arbitrary thickness
+  x Allow do draw lines with arbitrary thickness
   - Add system commands from client to platform that can change settings like vsync without
     restart. This requires some major codeflow refactoring but would allow us to better modularize
     the platform layer. We also would need to re-upload all textures to the graphics context.
@@ -36,31 +36,36 @@ BACKLOG(JaSc):
   - The following are things to remember to extract out of the old C project in the long term
     x Debug macro to print a variable and it's name quickly
     x Be able to conveniently do debug printing on screen
-    - Identification and sorting of translucent sprites
-    - Moving camera system
+    x Identification and sorting of translucent sprites
+    x Moving camera system
     x Aseprite image parser and converter
     x Texture array of atlases implementation
-    - Drawing debug overlays (grids/camera-frustums/crosshairs/depthbuffer)
+    x Drawing debug overlays (grids/camera-frustums/crosshairs/depthbuffer)
     - Gamepad input
     x Correct mouse zooming and panning
     x Raycasting and collision detection
     x Fixed sized pixel perfect canvase (framebuffer)
-    - Flexible sized pixel perfect canvase (framebuffer)
+    x Flexible sized pixel perfect canvase (framebuffer)
     - Live looped input playback and recording
     x Hot reloading of game code
     - Disable hot reloading when making a publish build
 */
 
 extern crate libloading;
-use game_lib::{self, GameContext, GameInput, Point, Rect, SystemCommand, Vec2};
+use game_lib::{
+    self, DrawCaptureRecorder, DrawCaptureReplayer, GameContext, GameInput, Point, Rect,
+    SystemCommand, Vec2,
+};
 
+mod capture;
 mod game_interface;
 mod graphics;
 mod input;
 mod timer;
 
+use crate::capture::FrameRecorder;
 use crate::game_interface::GameLib;
-use crate::graphics::{ColorFormat, DepthFormat, RenderingContext};
+use crate::graphics::{ColorFormat, DepthFormat, ReadbackPurpose, RenderingContext};
 use crate::timer::Timer;
 
 use failure::{self, Error, ResultExt};
@@ -348,11 +353,28 @@ fn main() -> Result<(), Error> {
 
     let timer_startup = Timer::new();
     let mut timer_delta = Timer::new();
+
+    let mut frame_recorder: Option<FrameRecorder> = None;
+    let mut draw_capture_recorder = DrawCaptureRecorder::new();
+
+    // NOTE: When started with `--replay <capture file>` we render the given draw capture in a
+    //       loop instead of the draw commands of the game
+    let replay_capture_filepath = std::env::args().skip_while(|arg| arg != "--replay").nth(1);
+    let mut draw_capture_replayer = match replay_capture_filepath {
+        Some(capture_filepath) => {
+            let draw_capture = capture::load_draw_capture(&capture_filepath)
+                .context("Could not load draw capture for replay")?;
+            Some(DrawCaptureReplayer::new(draw_capture))
+        }
+        None => None,
+    };
     //
     info!("Entering main event loop");
     info!("------------------------");
     //
     while is_running {
+        game_context.profiler().begin_frame();
+
         // Testing library hotreloading
         if game_lib.needs_reloading() {
             game_lib = game_lib.reload();
@@ -362,6 +384,7 @@ fn main() -> Result<(), Error> {
             }
         }
 
+        game_context.profiler().begin_zone("input");
         use sdl2::event::Event;
         use sdl2::event::WindowEvent;
         for event in events.poll_iter() {
@@ -468,18 +491,23 @@ fn main() -> Result<(), Error> {
             //                 .unwrap();
         }
 
+        game_context.profiler().end_zone();
+
         // Prepare input and update game
         input.mouse_pos_screen = mouse_pos_screen;
         input.mouse_delta_screen = mouse_delta_screen;
         mouse_delta_screen = Vec2::zero();
 
         input.screen_dim = screen_dimensions;
+        input.is_recording = frame_recorder.is_some();
         input.time_since_startup = timer_startup.elapsed_time();
         input.time_delta = timer_delta.elapsed_time() as f32;
         timer_delta.reset();
 
         let timer_update = Timer::new();
+        game_context.profiler().begin_zone("update_and_draw");
         game_lib.update_and_draw(&input, &mut game_context);
+        game_context.profiler().end_zone();
         input.time_update = timer_update.elapsed_time() as f32;
 
         let timer_audio = Timer::new();
@@ -498,24 +526,112 @@ fn main() -> Result<(), Error> {
                     // window.hide_cursor(do_enable && window_has_focus);
                     relative_mouse_mode_enabled = do_enable;
                 }
+                SystemCommand::TakeScreenshot(framebuffer_target) => {
+                    rc.request_readback(framebuffer_target, ReadbackPurpose::Screenshot)
+                        .context("Could not request screenshot")?;
+                }
+                SystemCommand::StartRecording {
+                    framebuffer_target,
+                    format,
+                    max_duration_seconds,
+                } => {
+                    if frame_recorder.is_some() {
+                        warn!("Cannot start recording because another recording is running");
+                    } else {
+                        frame_recorder = Some(FrameRecorder::new(
+                            framebuffer_target,
+                            format,
+                            max_duration_seconds,
+                        ));
+                    }
+                }
+                SystemCommand::StopRecording => {
+                    if let Some(recorder) = frame_recorder.take() {
+                        if let Err(error) = recorder.finish(input.time_since_startup) {
+                            error!("Could not finish recording: {}", error);
+                        }
+                    }
+                }
+                SystemCommand::CaptureDrawCommands { num_frames } => {
+                    draw_capture_recorder.start_capture(num_frames);
+                }
                 SystemCommand::ShutdownGame => is_running = false,
             }
         }
 
+        if let Some(ref recorder) = frame_recorder {
+            rc.request_readback(recorder.framebuffer_target(), ReadbackPurpose::Recording)
+                .context("Could not request readback for recording")?;
+        }
+
         // Draw to screen
         let timer_draw = Timer::new();
-        rc.process_draw_commands(game_context.get_draw_commands())
+        game_context.profiler().begin_zone("process_draw_commands");
+        let mut draw_commands = game_context.get_draw_commands();
+        if let Some(draw_capture) = draw_capture_recorder.record_frame(&draw_commands) {
+            if let Err(error) = capture::save_draw_capture(&draw_capture) {
+                error!("Could not save draw capture: {}", error);
+            }
+        }
+        if let Some(ref mut replayer) = draw_capture_replayer {
+            draw_commands = replayer.next_frame();
+        }
+        rc.process_draw_commands(draw_commands)
             .context("Could not to process a draw command")?;
+        game_context.profiler().end_zone();
         input.time_draw = timer_draw.elapsed_time() as f32;
 
         // Flush and flip buffers
+        game_context.profiler().begin_zone("flush");
         rc.encoder.flush(&mut device);
+        game_context.profiler().end_zone();
+
+        // Process framebuffer readbacks
+        let mut recording_needs_finishing = frame_recorder.is_some();
+        let mut recording_failed = false;
+        for readback in rc
+            .collect_readbacks()
+            .context("Could not collect framebuffer readbacks")?
+        {
+            match readback.purpose {
+                ReadbackPurpose::Screenshot => {
+                    if let Err(error) = capture::save_screenshot(&readback) {
+                        error!("Could not save screenshot: {}", error);
+                    }
+                }
+                ReadbackPurpose::Recording => {
+                    if let Some(ref mut recorder) = frame_recorder {
+                        match recorder.add_frame(readback, input.time_since_startup) {
+                            Ok(needs_more_frames) => recording_needs_finishing = !needs_more_frames,
+                            Err(error) => {
+                                error!("Could not add frame to recording: {}", error);
+                                recording_needs_finishing = false;
+                                recording_failed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if recording_failed {
+            frame_recorder = None;
+        } else if recording_needs_finishing {
+            if let Some(recorder) = frame_recorder.take() {
+                let end_timestamp = input.time_since_startup + f64::from(input.time_delta);
+                if let Err(error) = recorder.finish(end_timestamp) {
+                    error!("Could not finish recording: {}", error);
+                }
+            }
+        }
 
+        game_context.profiler().begin_zone("swap_window");
         window.gl_swap_window();
         device.cleanup();
+        game_context.profiler().end_zone();
 
         // Reset input
         input.prepare_for_next_frame();
+        game_context.profiler().end_frame();
     }
 
     Ok(())